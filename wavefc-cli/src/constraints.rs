@@ -0,0 +1,252 @@
+use crate::error::CliError;
+use std::fs;
+use std::hash::Hash;
+use std::path::Path;
+use wavefc::prelude::*;
+
+/// Reads a constraints file and applies each of its constraints to the filled wave.
+///
+/// Every line holds an `x` and `y` position in the output, either `allow` or `ban`, and then the sample values the constraint applies to. Empty lines and lines starting with `#` are ignored. For example:
+///
+/// ```text
+/// # water along the top edge
+/// 0 0 allow S
+/// 1 0 allow S
+/// 5 3 ban L C
+/// ```
+///
+/// The `lookup` function parses the values written in the file into the values used by the sample.
+pub(crate) fn apply_constraints_file<T, F>(
+    wave: &mut Wave<T>,
+    path: &Path,
+    lookup: F,
+) -> Result<(), CliError>
+where
+    T: Eq + Hash + Clone,
+    F: Fn(&str) -> Option<T>,
+{
+    apply_constraints(wave, path, false, lookup)
+}
+
+/// Reads a constraints file the same way as `apply_constraints_file`, where every position also has a `z` after its `x` and `y`.
+pub(crate) fn apply_volume_constraints_file<T, F>(
+    wave: &mut Wave<T>,
+    path: &Path,
+    lookup: F,
+) -> Result<(), CliError>
+where
+    T: Eq + Hash + Clone,
+    F: Fn(&str) -> Option<T>,
+{
+    apply_constraints(wave, path, true, lookup)
+}
+
+/// Parses a value of a character sample, which is written as the single character itself.
+pub(crate) fn char_value(value: &str) -> Option<char> {
+    let mut chars = value.chars();
+
+    match (chars.next(), chars.next()) {
+        (Some(ch), None) => Some(ch),
+        _ => None,
+    }
+}
+
+/// Parses a colour of an image sample, written in hex with an optional alpha channel, such as `#3f7fbf`.
+pub(crate) fn colour_value(value: &str) -> Option<[u8; 4]> {
+    let hex = value.strip_prefix('#').unwrap_or(value);
+
+    if hex.len() != 6 && hex.len() != 8 {
+        return None;
+    }
+
+    let mut color = [u8::MAX; 4];
+
+    for (i, channel) in color.iter_mut().enumerate().take(hex.len() / 2) {
+        *channel = u8::from_str_radix(hex.get(i * 2..i * 2 + 2)?, 16).ok()?;
+    }
+
+    Some(color)
+}
+
+fn apply_constraints<T, F>(
+    wave: &mut Wave<T>,
+    path: &Path,
+    volume: bool,
+    lookup: F,
+) -> Result<(), CliError>
+where
+    T: Eq + Hash + Clone,
+    F: Fn(&str) -> Option<T>,
+{
+    let content = fs::read_to_string(path)
+        .map_err(|e| format!("The constraints file provided cannot be read: {}", e))?;
+
+    apply_constraint_lines(wave, &content, volume, lookup)
+}
+
+/// Applies each constraint of the contents of a constraints file, in the format described by `apply_constraints_file`.
+fn apply_constraint_lines<T, F>(
+    wave: &mut Wave<T>,
+    content: &str,
+    volume: bool,
+    lookup: F,
+) -> Result<(), CliError>
+where
+    T: Eq + Hash + Clone,
+    F: Fn(&str) -> Option<T>,
+{
+    for (i, line) in content.lines().enumerate() {
+        let line = line.trim();
+
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let context = format!("Constraints file line {}", i + 1);
+        let error = |message: &str| CliError::from(message.to_string()).context(&context);
+        let mut parts = line.split_whitespace();
+
+        let mut coordinate = || {
+            parts
+                .next()
+                .and_then(|p| p.parse::<usize>().ok())
+                .ok_or_else(|| {
+                    error(if volume {
+                        "expected an x, y and z position"
+                    } else {
+                        "expected an x and y position"
+                    })
+                })
+        };
+
+        let x = coordinate()?;
+        let y = coordinate()?;
+        let z = if volume { coordinate()? } else { 0 };
+        let position = Vector3::new(x, y, z);
+        let kind = parts.next();
+
+        let values = parts
+            .map(|p| {
+                lookup(p)
+                    .filter(|v| wave.palette().id(v).is_some())
+                    .ok_or_else(|| error(&format!("{} isn't in the sample", p)))
+            })
+            .collect::<Result<Vec<T>, CliError>>()?;
+
+        if values.is_empty() {
+            return Err(error("expected at least one value"));
+        }
+
+        match kind {
+            Some("allow") => wave.constrain_voxel(position, &values),
+            Some("ban") => wave.ban_voxel(position, &values),
+            _ => return Err(error("expected either allow or ban after the position")),
+        }
+        .map_err(|e| CliError::from(e).context(&context))?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A filled wave of a checkerboard of a and b, where constraining one cell settles all of them.
+    fn checkerboard() -> Wave<char> {
+        let mut wave = Wave::new();
+        wave.analyze(
+            deconstruct_string("ab\nba", false),
+            Vector2::new(1, 1),
+            BorderMode::Clamp,
+        )
+        .unwrap();
+        wave.fill(Vector2::new(3, 3)).unwrap();
+        wave
+    }
+
+    /// The output of a `checkerboard` with the constraints applied to it.
+    fn constrained(content: &str) -> Vec<Vec<char>> {
+        let mut wave = checkerboard();
+        apply_constraint_lines(&mut wave, content, false, char_value).unwrap();
+        wave.perfect_rep().unwrap()
+    }
+
+    /// The message and exit code of the error the constraints give on a `checkerboard`.
+    fn failure(content: &str, volume: bool) -> (String, u8) {
+        let error = apply_constraint_lines(&mut checkerboard(), content, volume, char_value)
+            .expect_err("The constraints were applied.");
+        (error.to_string(), error.code)
+    }
+
+    #[test]
+    fn allowed_values_are_kept() {
+        assert_eq!(constrained("# the corner\n\n  0 0 allow a\n")[0][0], 'a');
+    }
+
+    #[test]
+    fn banned_values_are_left_out() {
+        assert_eq!(constrained("1 0 ban a")[1][0], 'b');
+    }
+
+    #[test]
+    fn malformed_lines_are_reported_with_their_line() {
+        let error = |line: usize, message: &str| {
+            (format!("Constraints file line {}: {}", line, message), 1)
+        };
+
+        assert_eq!(
+            failure("0 allow a", false),
+            error(1, "expected an x and y position")
+        );
+        assert_eq!(
+            failure("# a comment\n\n0 0 allow c", false),
+            error(3, "c isn't in the sample")
+        );
+        assert_eq!(
+            failure("0 0 allow", false),
+            error(1, "expected at least one value")
+        );
+        assert_eq!(
+            failure("0 0 keep a", false),
+            error(1, "expected either allow or ban after the position")
+        );
+        assert_eq!(
+            failure("0 0 allow a", true),
+            error(1, "expected an x, y and z position")
+        );
+    }
+
+    #[test]
+    fn volume_positions_have_a_z() {
+        let mut wave = checkerboard();
+        apply_constraint_lines(&mut wave, "0 0 0 allow a", true, char_value).unwrap();
+        assert_eq!(wave.perfect_rep().unwrap()[0][0], 'a');
+    }
+
+    #[test]
+    fn wave_errors_keep_their_exit_code() {
+        let (message, code) = failure("0 0 allow a\n3 0 allow b", false);
+        assert!(
+            message.starts_with("Constraints file line 2: "),
+            "{}",
+            message
+        );
+        assert_eq!(code, 17);
+
+        // a checkerboard can't have the same value next to itself
+        assert_eq!(failure("0 0 allow a\n1 0 allow a", false).1, 16);
+    }
+
+    #[test]
+    fn values_are_parsed_from_their_text() {
+        assert_eq!(char_value("a"), Some('a'));
+        assert_eq!(char_value("ab"), None);
+        assert_eq!(char_value(""), None);
+
+        assert_eq!(colour_value("#3f7fbf"), Some([0x3f, 0x7f, 0xbf, 0xff]));
+        assert_eq!(colour_value("3f7fbf80"), Some([0x3f, 0x7f, 0xbf, 0x80]));
+        assert_eq!(colour_value("#3f7f"), None);
+        assert_eq!(colour_value("#3f7fbg"), None);
+    }
+}
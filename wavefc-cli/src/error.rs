@@ -0,0 +1,116 @@
+use std::fmt;
+use wavefc::prelude::*;
+
+/// The exit code for errors which don't come from the wave, such as unreadable files.
+const GENERAL_EXIT_CODE: u8 = 1;
+
+/// An error which ends the program, alongside the exit code it ends with.
+#[derive(Debug)]
+pub(crate) struct CliError {
+    message: String,
+    pub code: u8,
+}
+
+impl CliError {
+    /// Prefixes the message with some context about where the error happened, keeping the exit code.
+    pub fn context(self, context: &str) -> Self {
+        Self {
+            message: format!("{}: {}", context, self.message),
+            code: self.code,
+        }
+    }
+}
+
+/// Each kind of `WaveError` gets its own exit code, so scripts can tell them apart.
+fn exit_code(error: &WaveError) -> u8 {
+    match error {
+        WaveError::NoPatterns => 10,
+        WaveError::NotFilled => 11,
+        WaveError::SizeNotMultipleOfChunk { .. } => 12,
+        WaveError::MaxContradictions(_) => 13,
+        WaveError::NotCollapsed => 14,
+        WaveError::Contradiction { .. } => 15,
+        WaveError::UnsatisfiableConstraint { .. } => 16,
+        WaveError::OutOfBounds { .. } => 17,
+        WaveError::MaskSizeMismatch { .. } => 18,
+        WaveError::HistoryMismatch { .. } => 19,
+        WaveError::InvalidRecord { .. } => 20,
+        WaveError::UnknownTile { .. } => 21,
+        WaveError::UnknownNode { .. } => 22,
+        WaveError::UnknownLabel { .. } => 23,
+        WaveError::UnknownValue { .. } => 24,
+        WaveError::HistoryDisabled => 25,
+        WaveError::RulesetMismatch { .. } => 26,
+        WaveError::InvalidLogStep { .. } => 27,
+        WaveError::Cancelled => 28,
+        WaveError::DeadlineExceeded => 29,
+        WaveError::ObservationLimitReached => 30,
+        WaveError::UnsatisfiableCount { .. } => 31,
+        WaveError::RegionTooSmall { .. } => 32,
+        WaveError::UnknownCountValue { .. } => 33,
+        WaveError::InvalidRuleset { .. } => 34,
+        WaveError::TooManyLabels => 35,
+        WaveError::UnsupportedChunkSize { .. } => 36,
+        WaveError::InvalidTileWeight { .. } => 37,
+        WaveError::InvalidValueWeight { .. } => 38,
+        WaveError::UnsupportedTopology => 39,
+    }
+}
+
+impl From<WaveError> for CliError {
+    fn from(error: WaveError) -> Self {
+        Self {
+            message: error.to_string(),
+            code: exit_code(&error),
+        }
+    }
+}
+
+impl From<String> for CliError {
+    fn from(message: String) -> Self {
+        Self {
+            message,
+            code: GENERAL_EXIT_CODE,
+        }
+    }
+}
+
+impl fmt::Display for CliError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn wave_errors_get_their_own_exit_code() {
+        assert_eq!(CliError::from(WaveError::NoPatterns).code, 10);
+        assert_eq!(CliError::from(WaveError::UnsupportedTopology).code, 39);
+        assert_eq!(
+            CliError::from(WaveError::InvalidTileWeight {
+                name: "line".to_string()
+            })
+            .code,
+            37
+        );
+    }
+
+    #[test]
+    fn other_errors_exit_with_the_general_code() {
+        let error = CliError::from("The sample file cannot be read".to_string());
+        assert_eq!(error.code, GENERAL_EXIT_CODE);
+    }
+
+    #[test]
+    fn context_keeps_the_exit_code() {
+        let error = CliError::from(WaveError::NotFilled).context("Tileset");
+        assert_eq!(error.code, 11);
+        assert_eq!(
+            error.to_string(),
+            format!("Tileset: {}", WaveError::NotFilled)
+        );
+    }
+}
@@ -1,25 +1,25 @@
 use super::DEFAULT_MAX_CONTRADICTIONS;
-use crate::shared::SharedArgs;
+use crate::constraints::{apply_constraints_file, colour_value};
+use crate::error::CliError;
+use crate::shared::{attempt_count, SharedArgs};
 use chrono::Local;
 use clap::ArgMatches;
 use image::io::Reader as ImageReader;
 use image::ImageBuffer;
 use image::Rgba;
+use image::RgbaImage;
 use open::that;
-use std::collections::HashMap;
 use std::fs::canonicalize;
-use std::path::PathBuf;
-use std::thread;
-use std::time::{Duration, Instant};
-use std::sync::{Mutex, Arc};
+use std::path::{Path, PathBuf};
+use std::time::Instant;
 use wavefc::prelude::*;
 
-const EXTRA_THREAD_COUNT: usize = 4;
-
-pub fn handler(matches: &ArgMatches) -> Result<(), String> {
+pub fn handler(matches: &ArgMatches) -> Result<(), CliError> {
     let pathbuf = matches.get_one::<PathBuf>("sample").unwrap();
     let output = matches.get_one::<PathBuf>("output");
     let open = matches.get_flag("open");
+    let inpaint = matches.get_one::<PathBuf>("inpaint");
+    let mask = matches.get_one::<PathBuf>("mask");
 
     let shared_args = SharedArgs::from(matches);
 
@@ -27,7 +27,7 @@ pub fn handler(matches: &ArgMatches) -> Result<(), String> {
 
     let image_result = ImageReader::open(pathbuf.as_path().to_str().unwrap());
     let image = image_result
-        .map_err(|e| format!("The image path provided was invalid: {}", e.to_string()))?
+        .map_err(|e| format!("The image path provided was invalid: {}", e))?
         .decode()
         .expect("Unable to decode the provided image");
     let width = image.width();
@@ -36,37 +36,14 @@ pub fn handler(matches: &ArgMatches) -> Result<(), String> {
     println!("Sample has the dimensions {} by {}.", width, height);
 
     let casted = image.into_rgba8();
-    let mut source_map = HashMap::new();
-    let mut id_counter: usize = 0;
-    let mut bit_sample: Vec<Vec<usize>> = vec![];
-
-    for x in 0..width {
-        if (bit_sample.len() as u32) < x + 1 {
-            bit_sample.push(vec![]);
-        }
-
-        for y in 0..height {
-            let pixel = casted.get_pixel(x, y);
-
-            source_map.entry(pixel.0).or_insert_with(|| {
-                let v = id_counter;
-                id_counter += 1;
-                v
-            });
-
-            bit_sample[x as usize].push(source_map[&pixel.0]);
-        }
-    }
+    let sample: Vec<Vec<[u8; 4]>> = (0..width)
+        .map(|x| (0..height).map(|y| casted.get_pixel(x, y).0).collect())
+        .collect();
 
     let preparation_duration = preparation_start.elapsed();
 
-    println!("Found {} unique colors.", source_map.len());
-
-    let chunk_size = if shared_args.tilesize.is_some() {
-        let mut size = Vector2::new(
-            *shared_args.tilesize.unwrap(),
-            *shared_args.tilesize.unwrap(),
-        );
+    let chunk_size = if let Some(tilesize) = shared_args.tilesize {
+        let mut size = Vector2::new(*tilesize, *tilesize);
 
         if let Some(width) = shared_args.tilewidth {
             size.x = *width;
@@ -83,10 +60,16 @@ pub fn handler(matches: &ArgMatches) -> Result<(), String> {
 
     let mut wave = Wave::new();
 
-    if !shared_args.use_transforms {
-        wave.flags.push(Flags::NoTransforms);
+    if let Some(seed) = shared_args.seed {
+        wave.set_seed(*seed);
     }
 
+    wave.set_heuristic(shared_args.heuristic);
+    wave.set_backtracking(shared_args.backtracking);
+    wave.set_periodic(shared_args.periodic_x, shared_args.periodic_y);
+
+    wave.set_symmetry(shared_args.symmetry);
+
     if !shared_args.use_weights {
         wave.flags.push(Flags::NoWeights);
     }
@@ -95,110 +78,109 @@ pub fn handler(matches: &ArgMatches) -> Result<(), String> {
 
     let analysis_start = Instant::now();
     println!("Analyzing... (this could take some time)");
-    wave.analyze(bit_sample, chunk_size, BorderMode::Clamp);
+    wave.analyze(sample, chunk_size, shared_args.border_mode)?;
     let analysis_duration = analysis_start.elapsed();
     println!("Finished analyzing.");
+    println!("Found {} unique colors.", wave.palette().len());
 
     wave.fill(Vector2::new(shared_args.width, shared_args.height))?;
 
+    // colours are written in hex, with an optional alpha channel
+    if let Some(path) = shared_args.constraints {
+        apply_constraints_file(&mut wave, path, colour_value)?;
+    }
+
     let real_contradictions = if let Some(max) = shared_args.max_contradictions {
         *max
     } else {
         DEFAULT_MAX_CONTRADICTIONS
     };
 
-    let mut waves = vec![];
-    waves.reserve(EXTRA_THREAD_COUNT);
+    // The existing output and the mask are indexed the same way as the sample, as [x][y].
+    let inpainting = if let (Some(inpaint), Some(mask)) = (inpaint, mask) {
+        let size = (shared_args.width as u32, shared_args.height as u32);
+        let existing_image = read_image(inpaint)?;
+        let mask_image = read_image(mask)?;
+
+        if existing_image.dimensions() != size || mask_image.dimensions() != size {
+            return Err(
+                "The image to inpaint and the mask must both be the size of the output."
+                    .to_string()
+                    .into(),
+            );
+        }
 
-    for _ in 0..EXTRA_THREAD_COUNT {
-        waves.push(wave.clone());
-    }
+        let mut existing = vec![];
+        let mut masked = vec![];
 
-    let finished = Arc::new(Mutex::new(false));
-    let mut thread_handles = vec![];
-    let collapse_start = Instant::now();
+        for x in 0..size.0 {
+            let mut column = vec![];
+            let mut mask_column = vec![];
 
-    for (i, mut wave) in waves.into_iter().enumerate() {
-        let finished_ref_copy = finished.clone();
+            for y in 0..size.1 {
+                let pixel = existing_image.get_pixel(x, y);
 
-        thread_handles.push(thread::spawn(move || {
-            let midway_print = Some(
-                |iterations: usize, failures: usize, _: Vec<Vec<Vec<usize>>>| {
-                    let finished_local = finished_ref_copy.lock().unwrap();
-
-                    if !*finished_local {
-                        println!(
-                            "Thread {}: Currently on attempt {} iteration {}",
-                            i + 1,
-                            failures + 1,
-                            iterations + 1
-                        );
-                    }
-                },
-            );
+                if wave.palette().id(&pixel.0).is_none() {
+                    return Err(format!(
+                        "The color {:?} at {}, {} in the image to inpaint isn't in the sample.",
+                        pixel.0, x, y
+                    )
+                    .into());
+                }
 
-            println!("Thread {}: Currently on attempt 1 iteration 1", i + 1);
-            wave.collapse_all(real_contradictions, midway_print)
-                .map(|_| wave)
-        }));
-    }
+                column.push(pixel.0);
 
-    let success: Option<Wave>;
-    let collapse_duration: Duration;
+                // anything closer to white than black is regenerated
+                let [r, g, b, _] = mask_image.get_pixel(x, y).0;
+                mask_column.push((r as u32 + g as u32 + b as u32) / 3 > 127);
+            }
 
-    'outer: loop {
-        if thread_handles.is_empty() {
-            return Err("Failed to find result. The max numbe of contradictions has been reached on all threads.".to_string());
+            existing.push(column);
+            masked.push(mask_column);
         }
 
-        let finished_threads_indexes: Vec<usize> = thread_handles
-            .iter()
-            .enumerate()
-            .filter_map(|(i, h)| if h.is_finished() { Some(i) } else { None })
-            .collect();
-
-        if finished_threads_indexes.is_empty() {
-            thread::sleep(Duration::from_millis(250));
-            continue;
-        }
+        Some((existing, masked))
+    } else {
+        None
+    };
 
-        let mut removed = 0;
+    let collapse_start = Instant::now();
 
-        for index in finished_threads_indexes {
-            let handle = thread_handles.remove(index - removed);
-            removed += 1;
+    let stats = wave
+        .collapse_parallel(attempt_count(&shared_args), |worker, wave| {
+            let midway_print = Some(
+                |iterations: usize, failures: usize, _: Vec<Vec<Vec<[u8; 4]>>>| {
+                    println!(
+                        "Thread {}: Currently on attempt {} iteration {}",
+                        worker + 1,
+                        failures + 1,
+                        iterations + 1
+                    );
+                },
+            );
 
-            let result = handle.join().expect("Failed to join threads.");
+            println!("Thread {}: Currently on attempt 1 iteration 1", worker + 1);
 
-            if let Ok(wave) = result {
-                // A successful collapse was made here
-                collapse_duration = collapse_start.elapsed();
-                success = Some(wave);
-                *finished.lock().unwrap() = true;
-                break 'outer;
+            if let Some((existing, mask)) = &inpainting {
+                wave.inpaint(existing, mask, real_contradictions, midway_print)
+            } else {
+                wave.collapse_all(real_contradictions, midway_print)
             }
-        }
-    }
+        })
+        .map_err(|e| CliError::from(e).context("Failed to find a result on any thread"))?;
 
-    debug_assert!(success.is_some());
+    let collapse_duration = collapse_start.elapsed();
 
-    let result = success.unwrap().perfect_rep()?;
+    let seed = wave.seed();
+    let result = wave.perfect_rep()?;
     let mut result_buffer: ImageBuffer<Rgba<u8>, Vec<u8>> =
         ImageBuffer::new(shared_args.width as u32, shared_args.height as u32);
 
-    std::mem::drop(shared_args);
-
     let dimensions = result_buffer.dimensions();
 
     for x in 0..dimensions.0 {
         for y in 0..dimensions.1 {
-            let sample_id = result[x as usize][y as usize];
-            let pixel_data = source_map
-                .iter()
-                .find_map(|(key, &val)| if val == sample_id { Some(key) } else { None })
-                .unwrap();
-            let copy = pixel_data.clone();
-            result_buffer.put_pixel(x, y, Rgba::from(copy));
+            result_buffer.put_pixel(x, y, Rgba::from(result[x as usize][y as usize]));
         }
     }
 
@@ -216,10 +198,12 @@ pub fn handler(matches: &ArgMatches) -> Result<(), String> {
         .save(output_pathbuf.as_path())
         .map_err(|e| e.to_string())?;
 
-    println!("");
+    println!();
     println!("Saved result to {}", output_pathbuf.to_str().unwrap());
 
-    println!("\nPreparation Time: {:?}", preparation_duration);
+    println!("\nSeed: {}", seed);
+    println!("Backtracks: {}", stats.backtracks);
+    println!("Preparation Time: {:?}", preparation_duration);
     println!("Analysis Time: {:?}", analysis_duration);
     println!("Collapse Time: {:?}", collapse_duration);
 
@@ -230,7 +214,14 @@ pub fn handler(matches: &ArgMatches) -> Result<(), String> {
         that(absolute_path).map_err(|e| e.to_string())?;
     }
 
-    println!("\nWaiting for other threads to finish...\n");
-
     Ok(())
 }
+
+pub(crate) fn read_image(path: &Path) -> Result<RgbaImage, String> {
+    let image = ImageReader::open(path)
+        .map_err(|e| format!("The image path provided was invalid: {}", e))?
+        .decode()
+        .map_err(|e| format!("Unable to decode the provided image: {}", e))?;
+
+    Ok(image.into_rgba8())
+}
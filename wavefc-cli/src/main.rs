@@ -4,18 +4,44 @@ extern crate wavefc;
 
 use clap::{arg, crate_version, value_parser, Arg, Command};
 use std::path::PathBuf;
+use std::process::ExitCode;
 
+mod constraints;
+mod error;
 mod shared;
 use shared::expand_shared_args;
 
 mod image_process;
+mod rules_process;
 mod string_process;
+mod tiled_process;
+mod tileset;
+mod voxel_process;
 use image_process::handler as image_mode;
+use error::CliError;
+use rules_process::{
+    analyze_handler as analyze_mode, generate_handler as generate_mode,
+    replay_handler as replay_mode,
+};
 use string_process::handler as string_mode;
+use tiled_process::handler as tiled_mode;
+use voxel_process::handler as voxel_mode;
 
 const DEFAULT_MAX_CONTRADICTIONS: usize = 20;
+/// The number of attempts which are made at once on their own threads, unless a seed is given.
+const PARALLEL_ATTEMPTS: usize = 4;
 
-fn main() -> Result<(), String> {
+fn main() -> ExitCode {
+    match run() {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            ExitCode::from(e.code)
+        }
+    }
+}
+
+fn run() -> Result<(), CliError> {
     let matches = Command::new("Wave Function Collapse")
         .version(crate_version!())
         .subcommand(expand_shared_args!(
@@ -26,6 +52,7 @@ fn main() -> Result<(), String> {
                     .arg(arg!( -p --noprint "Disables incrementally printing the function's progress."))
                     .arg(arg!( -l --whitespace "Takes into account whitespace in the sample."))
                     .arg(arg!( -d --disablecom "Disables stripping commas from the input sample."))
+                    .arg(arg!( --hex "Treats the sample and output as hexagons in offset rows, where odd rows are shifted half a cell to the right. Only a tile size of 1 is supported."))
                 )
         )
         .subcommand(expand_shared_args!(
@@ -37,13 +64,99 @@ fn main() -> Result<(), String> {
                     .arg(arg!( -o --output <file> "The path to output the final image to." )
                         .value_parser(value_parser!(PathBuf)))
                     .arg(arg!( -O --open "Opens the output image in the default system application." ))
+                    .arg(arg!( -i --inpaint <file> "An existing output to regenerate part of. It must be the same size as the output and only use colors from the sample." )
+                        .requires("mask")
+                        .value_parser(value_parser!(PathBuf)))
+                    .arg(arg!( -M --mask <file> "A black and white image the size of the output, where the white parts of the --inpaint image are regenerated." )
+                        .requires("inpaint")
+                        .value_parser(value_parser!(PathBuf)))
+                )
+        )
+        .subcommand(expand_shared_args!(
+                Command::new("tiled")
+                    .about("Creates a new image from a tileset of tile images and the tiles which can be placed next to each other. The width and height are in tiles.")
+                    .arg(Arg::new("tileset")
+                        .required(true)
+                        .help("A .xml, .json or .toml tileset, with an image for each tile in the same directory.")
+                        .value_parser(value_parser!(PathBuf)))
+                    .arg(arg!( -o --output <file> "The path to output the final image to." )
+                        .value_parser(value_parser!(PathBuf)))
+                    .arg(arg!( -O --open "Opens the output image in the default system application." ))
+                )
+        )
+        .subcommand(expand_shared_args!(
+                Command::new("voxel")
+                    .about("Creates a new volume from a sample of character layers, from the bottom layer up with an empty line between each of them.")
+                    .arg(Arg::new("sample")
+                        .required(true)
+                        .value_parser(value_parser!(PathBuf)))
+                    .arg(arg!( --tiledepth <number> "Specify the tile size depth (precedent over --tilesize)." )
+                        .value_parser(value_parser!(usize)))
+                    .arg(arg!( -o --output <file> "The path to output the final layers to, instead of printing them." )
+                        .value_parser(value_parser!(PathBuf)))
+                )
+                .arg(Arg::new("depth")
+                    .required(true)
+                    .value_parser(value_parser!(usize)))
+        )
+        .subcommand(
+            Command::new("analyze")
+                .about("Learns the rules of a sample and saves them, so outputs can be generated from them later on without analyzing the sample again. Images are read as images, and anything else as a character map.")
+                .arg(Arg::new("sample")
+                    .required(true)
+                    .value_parser(value_parser!(PathBuf)))
+                .arg(arg!( -o --out <file> "The path to save the rules to. Files ending in .json are saved as JSON, and any others in a compact binary format." )
+                    .required(true)
+                    .value_parser(value_parser!(PathBuf)))
+                .arg(arg!( -m --tilesize <number> "Specify the tile size used in the analysis. By default this value is 1." )
+                    .value_parser(value_parser!(usize)))
+                .arg(arg!( -j --tilewidth <number> "Specify the tile size width (precedent over --tilesize)." )
+                    .value_parser(value_parser!(usize)))
+                .arg(arg!( -k --tileheight <number> "Specify the tile size height (precedent over --tilesize)." )
+                    .value_parser(value_parser!(usize)))
+                .arg(arg!( -t --symmetry <group> "The transforms of the sample's tiles used in rule analysis. Rotations require a square tile size. By default this is mirror-xy.")
+                    .value_parser(["none", "mirror-x", "mirror-y", "mirror-xy", "rotations", "full"]))
+                .arg(arg!( -x --border <mode> "How the edges of the sample are analyzed. Use wrap for seamless samples. By default this is clamp.")
+                    .value_parser(["wrap", "clamp", "exclude"]))
+                .arg(arg!( -l --whitespace "Takes into account whitespace in a character map."))
+                .arg(arg!( --hex "Treats a character map as hexagons in offset rows, where odd rows are shifted half a cell to the right."))
+        )
+        .subcommand(expand_shared_args!(
+                Command::new("generate")
+                    .about("Creates a new output from rules saved by analyze. The tile size, symmetry and border mode come from the rules.")
+                    .arg(arg!( --rules <file> "The rules file to generate from." )
+                        .required(true)
+                        .value_parser(value_parser!(PathBuf)))
+                    .arg(arg!( -o --output <file> "The path to output the result to. It's required for the rules of an image, and the result is printed otherwise." )
+                        .value_parser(value_parser!(PathBuf)))
+                    .arg(arg!( --record <file> "Saves a log of the decisions made to the file, which replay can rebuild the output from. Files ending in .json are saved as JSON, and any others in a compact binary format." )
+                        .value_parser(value_parser!(PathBuf)))
                 )
         )
+        .subcommand(
+            Command::new("replay")
+                .about("Rebuilds an output from a log saved by generate --record, either completely or up to one of its steps.")
+                .arg(Arg::new("log")
+                    .required(true)
+                    .value_parser(value_parser!(PathBuf)))
+                .arg(arg!( --rules <file> "The rules file the log was recorded with." )
+                    .required(true)
+                    .value_parser(value_parser!(PathBuf)))
+                .arg(arg!( -u --until <step> "Only replays this many steps of the log. Cells which haven't been decided by then are left blank." )
+                    .value_parser(value_parser!(usize)))
+                .arg(arg!( -o --output <file> "The path to output the result to. It's required for the rules of an image, and the result is printed otherwise." )
+                    .value_parser(value_parser!(PathBuf)))
+        )
         .get_matches();
 
     match matches.subcommand().expect("No command was provided.") {
         ("string", sub_matches) => string_mode(sub_matches)?,
         ("image", sub_matches) => image_mode(sub_matches)?,
+        ("tiled", sub_matches) => tiled_mode(sub_matches)?,
+        ("voxel", sub_matches) => voxel_mode(sub_matches)?,
+        ("analyze", sub_matches) => analyze_mode(sub_matches)?,
+        ("generate", sub_matches) => generate_mode(sub_matches)?,
+        ("replay", sub_matches) => replay_mode(sub_matches)?,
         _ => println!("Unknown command."),
     };
 
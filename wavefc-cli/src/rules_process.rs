@@ -0,0 +1,344 @@
+use super::DEFAULT_MAX_CONTRADICTIONS;
+use crate::constraints::{apply_constraints_file, char_value, colour_value};
+use crate::error::CliError;
+use crate::image_process::read_image;
+use crate::shared::{border_mode_arg, symmetry_arg, SharedArgs};
+use clap::ArgMatches;
+use image::{ImageFormat, Rgba, RgbaImage};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use std::any::Any;
+use std::fs::{self, File};
+use std::hash::Hash;
+use std::io::{BufReader, BufWriter};
+use std::path::{Path, PathBuf};
+use std::time::Instant;
+use wavefc::prelude::*;
+
+/// The rules learned from either kind of sample, tagged with the kind so `generate` knows what to output.
+#[derive(Serialize, Deserialize)]
+#[serde(tag = "sample", rename_all = "lowercase")]
+enum RulesFile {
+    String(Ruleset<char>),
+    Image(Ruleset<[u8; 4]>),
+}
+
+pub fn analyze_handler(matches: &ArgMatches) -> Result<(), CliError> {
+    let pathbuf = matches.get_one::<PathBuf>("sample").unwrap();
+    let out = matches.get_one::<PathBuf>("out").unwrap();
+    let hex = matches.get_flag("hex");
+    let use_whitespace = matches.get_flag("whitespace");
+
+    let chunk_size = {
+        let tilesize = matches.get_one::<usize>("tilesize").copied().unwrap_or(1);
+        let mut size = Vector2::new(tilesize, tilesize);
+
+        if let Some(width) = matches.get_one::<usize>("tilewidth") {
+            size.x = *width;
+        }
+
+        if let Some(height) = matches.get_one::<usize>("tileheight") {
+            size.y = *height;
+        }
+
+        size
+    };
+
+    let border_mode = border_mode_arg(matches);
+    let analysis_start = Instant::now();
+
+    // anything which can be read as an image is one, and everything else is a character map
+    let rules = if ImageFormat::from_path(pathbuf).is_ok() {
+        let image = read_image(pathbuf)?;
+        let sample: Vec<Vec<[u8; 4]>> = (0..image.width())
+            .map(|x| {
+                (0..image.height())
+                    .map(|y| image.get_pixel(x, y).0)
+                    .collect()
+            })
+            .collect();
+
+        let mut wave = Wave::new();
+        wave.set_symmetry(symmetry_arg(matches));
+        wave.analyze(sample, chunk_size, border_mode)?;
+
+        RulesFile::Image(wave.ruleset())
+    } else {
+        let content = fs::read_to_string(pathbuf)
+            .map_err(|e| format!("The sample provided cannot be read: {}", e))?
+            .replace(", ", "")
+            .replace(',', "");
+
+        let sample = if hex {
+            deconstruct_hex(&content)
+        } else {
+            deconstruct_string(&content, use_whitespace)
+        };
+
+        let mut wave = Wave::new();
+        wave.set_symmetry(symmetry_arg(matches));
+
+        if hex {
+            wave.set_topology(Hex);
+        }
+
+        wave.analyze(sample, chunk_size, border_mode)?;
+
+        RulesFile::String(wave.ruleset())
+    };
+
+    let analysis_duration = analysis_start.elapsed();
+    let pattern_count = match &rules {
+        RulesFile::String(ruleset) => ruleset.patterns().len(),
+        RulesFile::Image(ruleset) => ruleset.patterns().len(),
+    };
+
+    if pattern_count == 0 {
+        return Err(WaveError::NoPatterns.into());
+    }
+
+    write_file(out, &rules, "rules")?;
+
+    println!("Saved {} patterns to {}", pattern_count, out.display());
+    println!("\nAnalysis Time: {:?}", analysis_duration);
+
+    Ok(())
+}
+
+pub fn generate_handler(matches: &ArgMatches) -> Result<(), CliError> {
+    let rules_path = matches.get_one::<PathBuf>("rules").unwrap();
+    let output = matches.get_one::<PathBuf>("output");
+    let record = matches.get_one::<PathBuf>("record");
+
+    let shared_args = SharedArgs::from(matches);
+
+    let preparation_start = Instant::now();
+    let rules: RulesFile = read_file(rules_path, "rules")?;
+    let preparation_duration = preparation_start.elapsed();
+
+    let collapse_start = Instant::now();
+
+    let (seed, stats) = match rules {
+        RulesFile::String(ruleset) => {
+            let mut wave = Wave::from_ruleset(ruleset)?;
+            let stats = collapse(&mut wave, &shared_args, char_value, record.is_some())?;
+            let result = wave.perfect_rep()?;
+
+            save_log(&wave, record)?;
+            output_string(&wave, result, output)?;
+
+            (wave.seed(), stats)
+        }
+        RulesFile::Image(ruleset) => {
+            let path = output.ok_or_else(|| {
+                "An --output path is required for the rules of an image.".to_string()
+            })?;
+
+            let mut wave = Wave::from_ruleset(ruleset)?;
+            let stats = collapse(&mut wave, &shared_args, colour_value, record.is_some())?;
+            let result = wave.perfect_rep()?;
+
+            save_log(&wave, record)?;
+            save_image(result, path)?;
+
+            (wave.seed(), stats)
+        }
+    };
+
+    let collapse_duration = collapse_start.elapsed();
+
+    println!("\nSeed: {}", seed);
+    println!("Backtracks: {}", stats.backtracks);
+    println!("Preparation Time: {:?}", preparation_duration);
+    println!("Collapse Time: {:?}", collapse_duration);
+
+    Ok(())
+}
+
+pub fn replay_handler(matches: &ArgMatches) -> Result<(), CliError> {
+    let log_path = matches.get_one::<PathBuf>("log").unwrap();
+    let rules_path = matches.get_one::<PathBuf>("rules").unwrap();
+    let output = matches.get_one::<PathBuf>("output");
+    let until = matches.get_one::<usize>("until").copied();
+
+    let rules: RulesFile = read_file(rules_path, "rules")?;
+    let log: GenerationLog = read_file(log_path, "log")?;
+
+    let replay_start = Instant::now();
+
+    match rules {
+        RulesFile::String(ruleset) => {
+            let mut wave = Wave::from_ruleset(ruleset)?;
+            wave.replay(&log, until)?;
+
+            output_string(&wave, decided_rep(&wave, ' '), output)?;
+        }
+        RulesFile::Image(ruleset) => {
+            let path = output.ok_or_else(|| {
+                "An --output path is required for the rules of an image.".to_string()
+            })?;
+
+            let mut wave = Wave::from_ruleset(ruleset)?;
+            wave.replay(&log, until)?;
+
+            save_image(decided_rep(&wave, [0; 4]), path)?;
+        }
+    }
+
+    let replay_duration = replay_start.elapsed();
+    let steps = until.map_or(log.len(), |until| until.min(log.len()));
+
+    println!("\nSeed: {}", log.seed());
+    println!("Steps: {} of {}", steps, log.len());
+    println!("Replay Time: {:?}", replay_duration);
+
+    Ok(())
+}
+
+/// The value of every cell which has been decided, and the blank value for those which haven't (or have contradicted).
+fn decided_rep<T: Eq + Hash + Clone>(wave: &Wave<T>, blank: T) -> Vec<Vec<T>> {
+    wave.current_rep()
+        .into_iter()
+        .map(|column| {
+            column
+                .into_iter()
+                .map(|cell| match cell.as_slice() {
+                    [value] => value.clone(),
+                    _ => blank.clone(),
+                })
+                .collect()
+        })
+        .collect()
+}
+
+/// Prints the characters of the result, or saves them if an output path was given.
+fn output_string(
+    wave: &Wave<char>,
+    result: Vec<Vec<char>>,
+    output: Option<&PathBuf>,
+) -> Result<(), CliError> {
+    let topology: &dyn Any = wave.topology();
+    let string = if topology.is::<Hex>() {
+        reconstruct_hex(result)
+    } else {
+        reconstruct_string(result, true, false, wave.seed())
+    };
+
+    if let Some(path) = output {
+        fs::write(path, string + "\n").map_err(|e| e.to_string())?;
+        println!("Saved result to {}", path.display());
+    } else {
+        println!("{}", string);
+    }
+
+    Ok(())
+}
+
+fn save_image(result: Vec<Vec<[u8; 4]>>, path: &Path) -> Result<(), CliError> {
+    let width = result.len() as u32;
+    let height = result.first().map_or(0, |column| column.len()) as u32;
+    let image = RgbaImage::from_fn(width, height, |x, y| {
+        Rgba::from(result[x as usize][y as usize])
+    });
+
+    image.save(path).map_err(|e| e.to_string())?;
+    println!("Saved result to {}", path.display());
+
+    Ok(())
+}
+
+/// Saves the decisions of the wave to the path given with `--record`, if there was one.
+fn save_log<T: Eq + Hash + Clone>(
+    wave: &Wave<T>,
+    record: Option<&PathBuf>,
+) -> Result<(), CliError> {
+    if let Some(path) = record {
+        write_file(path, &wave.generation_log()?, "log")?;
+        println!("Saved log to {}", path.display());
+    }
+
+    Ok(())
+}
+
+/// Sets the wave up with the shared arguments which apply to a loaded ruleset, fills it and collapses it.
+///
+/// The wave's history is only kept when its log is going to be saved.
+fn collapse<T, F>(
+    wave: &mut Wave<T>,
+    shared_args: &SharedArgs,
+    lookup: F,
+    keep_history: bool,
+) -> Result<CollapseStats, CliError>
+where
+    T: Eq + Hash + Clone,
+    F: Fn(&str) -> Option<T>,
+{
+    if let Some(seed) = shared_args.seed {
+        wave.set_seed(*seed);
+    }
+
+    wave.set_heuristic(shared_args.heuristic);
+    wave.set_backtracking(shared_args.backtracking);
+    wave.set_periodic(shared_args.periodic_x, shared_args.periodic_y);
+
+    if !shared_args.use_weights {
+        wave.flags.push(Flags::NoWeights);
+    }
+
+    if !keep_history {
+        wave.flags.push(Flags::NoHistory);
+    }
+
+    wave.fill(Vector2::new(shared_args.width, shared_args.height))?;
+
+    if let Some(path) = shared_args.constraints {
+        apply_constraints_file(wave, path, lookup)?;
+    }
+
+    let real_contradictions = if let Some(max) = shared_args.max_contradictions {
+        *max
+    } else {
+        DEFAULT_MAX_CONTRADICTIONS
+    };
+
+    Ok(wave.collapse_all(
+        real_contradictions,
+        None::<fn(usize, usize, Vec<Vec<Vec<T>>>)>,
+    )?)
+}
+
+/// Files ending in `.json` are written as JSON, and any others in the compact binary CBOR format.
+fn is_json(path: &Path) -> bool {
+    path.extension()
+        .is_some_and(|e| e.eq_ignore_ascii_case("json"))
+}
+
+/// Writes the rules or log to a file, where `kind` names what's being written in errors.
+fn write_file<S: Serialize>(path: &Path, value: &S, kind: &str) -> Result<(), CliError> {
+    let file =
+        File::create(path).map_err(|e| format!("The {} file cannot be created: {}", kind, e))?;
+    let writer = BufWriter::new(file);
+
+    if is_json(path) {
+        serde_json::to_writer_pretty(writer, value).map_err(|e| e.to_string())?;
+    } else {
+        ciborium::ser::into_writer(value, writer).map_err(|e| e.to_string())?;
+    }
+
+    Ok(())
+}
+
+fn read_file<D: DeserializeOwned>(path: &Path, kind: &str) -> Result<D, CliError> {
+    let file = File::open(path)
+        .map_err(|e| format!("The {} file provided cannot be read: {}", kind, e))?;
+    let reader = BufReader::new(file);
+
+    let value = if is_json(path) {
+        serde_json::from_reader(reader).map_err(|e| e.to_string())
+    } else {
+        ciborium::de::from_reader(reader).map_err(|e| e.to_string())
+    };
+
+    // the context reads as "Rules file: ..." or "Log file: ..."
+    let context = format!("{}{} file", kind[..1].to_uppercase(), &kind[1..]);
+    value.map_err(|e| CliError::from(e).context(&context))
+}
@@ -1,4 +1,7 @@
+use super::PARALLEL_ATTEMPTS;
 use clap::ArgMatches;
+use std::path::PathBuf;
+use wavefc::prelude::*;
 
 pub(crate) struct SharedArgs<'a> {
     pub width: usize,
@@ -7,8 +10,15 @@ pub(crate) struct SharedArgs<'a> {
     pub tilewidth: Option<&'a usize>,
     pub tileheight: Option<&'a usize>,
     pub use_weights: bool,
-    pub use_transforms: bool,
+    pub symmetry: Symmetry,
     pub max_contradictions: Option<&'a usize>,
+    pub seed: Option<&'a u64>,
+    pub heuristic: SelectionHeuristic,
+    pub backtracking: Option<BacktrackLimits>,
+    pub border_mode: BorderMode,
+    pub periodic_x: bool,
+    pub periodic_y: bool,
+    pub constraints: Option<&'a PathBuf>,
 }
 
 impl<'a> From<&'a ArgMatches> for SharedArgs<'a> {
@@ -20,12 +30,78 @@ impl<'a> From<&'a ArgMatches> for SharedArgs<'a> {
             tilewidth: matches.get_one::<usize>("tilewidth"),
             tileheight: matches.get_one::<usize>("tileheight"),
             use_weights: !matches.get_flag("noweights"),
-            use_transforms: !matches.get_flag("notransforms"),
+            symmetry: symmetry_arg(matches),
             max_contradictions: matches.get_one::<usize>("attempts"),
+            seed: matches.get_one::<u64>("seed"),
+            heuristic: match matches.get_one::<String>("heuristic").map(|s| s.as_str()) {
+                Some("mrv") => SelectionHeuristic::Mrv,
+                Some("scanline") => SelectionHeuristic::Scanline,
+                Some("random") => SelectionHeuristic::Random,
+                _ => SelectionHeuristic::MinEntropy,
+            },
+            backtracking: backtrack_limits(matches),
+            border_mode: border_mode_arg(matches),
+            periodic_x: matches.get_flag("periodic-x"),
+            periodic_y: matches.get_flag("periodic-y"),
+            constraints: matches.get_one::<PathBuf>("constraints"),
         }
     }
 }
 
+/// The `--symmetry` argument, which is `Symmetry::MirrorXY` if it isn't given.
+pub(crate) fn symmetry_arg(matches: &ArgMatches) -> Symmetry {
+    match matches.get_one::<String>("symmetry").map(|s| s.as_str()) {
+        Some("none") => Symmetry::None,
+        Some("mirror-x") => Symmetry::MirrorX,
+        Some("mirror-y") => Symmetry::MirrorY,
+        Some("rotations") => Symmetry::Rotations,
+        Some("full") => Symmetry::Full,
+        _ => Symmetry::MirrorXY,
+    }
+}
+
+/// The `--border` argument, which is `BorderMode::Clamp` if it isn't given.
+pub(crate) fn border_mode_arg(matches: &ArgMatches) -> BorderMode {
+    match matches.get_one::<String>("border").map(|s| s.as_str()) {
+        Some("wrap") => BorderMode::Wrap,
+        Some("exclude") => BorderMode::Exclude,
+        _ => BorderMode::Clamp,
+    }
+}
+
+/// The number of attempts to collapse the output in parallel, which is `PARALLEL_ATTEMPTS` unless a seed is given.
+///
+/// Which thread finishes first isn't deterministic, so a seeded run is kept to a single thread to be reproducible.
+pub(crate) fn attempt_count(shared_args: &SharedArgs) -> usize {
+    if shared_args.seed.is_some() {
+        1
+    } else {
+        PARALLEL_ATTEMPTS
+    }
+}
+
+/// Backtracking is enabled when either of its limits is given, with the other one left at its default.
+fn backtrack_limits(matches: &ArgMatches) -> Option<BacktrackLimits> {
+    let max_backtracks = matches.get_one::<usize>("backtrack");
+    let max_depth = matches.get_one::<usize>("backtrack-depth");
+
+    if max_backtracks.is_none() && max_depth.is_none() {
+        return None;
+    }
+
+    let mut limits = BacktrackLimits::default();
+
+    if let Some(max) = max_backtracks {
+        limits.max_backtracks = *max;
+    }
+
+    if let Some(depth) = max_depth {
+        limits.max_depth = *depth;
+    }
+
+    Some(limits)
+}
+
 /// Applies several shared arguments for different subcommands to the given `clap::Command` provided.
 macro_rules! expand_shared_args {
     ($e:expr) => {
@@ -45,7 +121,22 @@ macro_rules! expand_shared_args {
             .arg(arg!( -a --attempts <number> "The maximum number of contradictions (attempts) that can be reached before the program quits.")
                 .value_parser(value_parser!(usize)))
             .arg(arg!( -w --noweights "Disables using weights in when calculating superposition entropy."))
-            .arg(arg!( -t --notransforms "Disables using transforms in rule analysis."))
+            .arg(arg!( -t --symmetry <group> "The transforms of the sample's tiles used in rule analysis. Rotations require a square tile size. By default this is mirror-xy.")
+                .value_parser(["none", "mirror-x", "mirror-y", "mirror-xy", "rotations", "full"]))
+            .arg(arg!( -r --seed <number> "Seeds the random number generator to reproduce a previous result. By default a random seed is used.")
+                .value_parser(value_parser!(u64)))
+            .arg(arg!( -e --heuristic <name> "The strategy used to choose the next tile to collapse. By default this is min-entropy.")
+                .value_parser(["min-entropy", "mrv", "scanline", "random"]))
+            .arg(arg!( -x --border <mode> "How the edges of the sample are analyzed. Use wrap for seamless samples. By default this is clamp.")
+                .value_parser(["wrap", "clamp", "exclude"]))
+            .arg(arg!( --"periodic-x" "Wraps the output around horizontally, so it tiles seamlessly side by side."))
+            .arg(arg!( --"periodic-y" "Wraps the output around vertically, so it tiles seamlessly top to bottom."))
+            .arg(arg!( -c --constraints <file> "A file of cells to pin to (allow) or keep from (ban) certain sample values, one per line as: x y allow|ban values...")
+                .value_parser(value_parser!(PathBuf)))
+            .arg(arg!( -b --backtrack <number> "Backtracks on contradictions instead of restarting, up to this many times in total.")
+                .value_parser(value_parser!(usize)))
+            .arg(arg!( --"backtrack-depth" <number> "The maximum number of collapses that can be rolled back when backtracking.")
+                .value_parser(value_parser!(usize)))
     }
 }
 
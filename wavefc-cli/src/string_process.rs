@@ -1,16 +1,19 @@
 use super::DEFAULT_MAX_CONTRADICTIONS;
-use crate::shared::SharedArgs;
+use crate::constraints::{apply_constraints_file, char_value};
+use crate::error::CliError;
+use crate::shared::{attempt_count, SharedArgs};
 use clap::ArgMatches;
 use std::fs;
 use std::path::PathBuf;
 use std::time::Instant;
 use wavefc::prelude::*;
 
-pub fn handler(matches: &ArgMatches) -> Result<(), String> {
+pub fn handler(matches: &ArgMatches) -> Result<(), CliError> {
     let pathbuf = matches.get_one::<PathBuf>("sample");
     let print = !matches.get_flag("noprint");
     let use_whitespace = matches.get_flag("whitespace");
     let disablecommas = matches.get_flag("disablecom");
+    let hex = matches.get_flag("hex");
 
     let shared_args = SharedArgs::from(matches);
 
@@ -39,7 +42,11 @@ pub fn handler(matches: &ArgMatches) -> Result<(), String> {
         panic!("The input sample cannot be empty")
     }
 
-    let (sample, source_map) = deconstruct_string(&input, use_whitespace);
+    let sample = if hex {
+        deconstruct_hex(&input)
+    } else {
+        deconstruct_string(&input, use_whitespace)
+    };
 
     let preparation_duration = preparation_start.elapsed();
 
@@ -49,11 +56,8 @@ pub fn handler(matches: &ArgMatches) -> Result<(), String> {
         println!("Warning: The sample provided has no items.");
     }
 
-    let chunk_size = if shared_args.tilesize.is_some() {
-        let mut size = Vector2::new(
-            *shared_args.tilesize.unwrap(),
-            *shared_args.tilesize.unwrap(),
-        );
+    let chunk_size = if let Some(tilesize) = shared_args.tilesize {
+        let mut size = Vector2::new(*tilesize, *tilesize);
 
         if let Some(width) = shared_args.tilewidth {
             size.x = *width;
@@ -70,8 +74,18 @@ pub fn handler(matches: &ArgMatches) -> Result<(), String> {
 
     let mut wave = Wave::new();
 
-    if !shared_args.use_transforms {
-        wave.flags.push(Flags::NoTransforms);
+    if let Some(seed) = shared_args.seed {
+        wave.set_seed(*seed);
+    }
+
+    wave.set_heuristic(shared_args.heuristic);
+    wave.set_backtracking(shared_args.backtracking);
+    wave.set_periodic(shared_args.periodic_x, shared_args.periodic_y);
+
+    wave.set_symmetry(shared_args.symmetry);
+
+    if hex {
+        wave.set_topology(Hex);
     }
 
     if !shared_args.use_weights {
@@ -79,39 +93,58 @@ pub fn handler(matches: &ArgMatches) -> Result<(), String> {
     }
 
     let a_start = Instant::now();
-    wave.analyze(sample, chunk_size, BorderMode::Clamp);
+    wave.analyze(sample, chunk_size, shared_args.border_mode)?;
     let a_dur = a_start.elapsed();
     wave.fill(Vector2::new(shared_args.width, shared_args.height))?;
 
+    if let Some(path) = shared_args.constraints {
+        apply_constraints_file(&mut wave, path, char_value)?;
+    }
+
     let real_contradictions = if let Some(max) = shared_args.max_contradictions {
         *max
     } else {
         DEFAULT_MAX_CONTRADICTIONS
     };
 
-    let midway_print = Some(
-        |iterations: usize, failures: usize, current_rep: Vec<Vec<Vec<usize>>>| {
-            let string = construct_wip_string(current_rep, &source_map);
-            println!(
-                "Iteration: {}, Attempt: {}\n{}\n",
-                iterations + 1,
-                failures + 1,
-                string
+    let c_start = Instant::now();
+    let stats = wave
+        .collapse_parallel(attempt_count(&shared_args), |worker, wave| {
+            let midway_print = Some(
+                |iterations: usize, failures: usize, current_rep: Vec<Vec<Vec<char>>>| {
+                    let string = construct_wip_string(current_rep);
+                    println!(
+                        "Thread: {}, Iteration: {}, Attempt: {}\n{}\n",
+                        worker + 1,
+                        iterations + 1,
+                        failures + 1,
+                        string
+                    );
+                },
             );
-        },
-    );
 
-    let c_start = Instant::now();
-    wave.collapse_all(real_contradictions, if print { midway_print } else { None })?;
+            // the progress is printed as a square grid, so it's left out for hexagons
+            wave.collapse_all(
+                real_contradictions,
+                if print && !hex { midway_print } else { None },
+            )
+        })
+        .map_err(|e| CliError::from(e).context("Failed to find a result on any thread"))?;
     let c_dur = c_start.elapsed();
 
     let result = wave.perfect_rep()?;
-    let string = reconstruct_string(result, &source_map, true, print);
+    let string = if hex {
+        reconstruct_hex(result)
+    } else {
+        reconstruct_string(result, true, print, wave.seed())
+    };
     println!("{}", string);
 
     if print {
+        println!("\nSeed: {}", wave.seed());
+        println!("Backtracks: {}", stats.backtracks);
         println!(
-            "\nAnalysis and Prep. Time: {:?}",
+            "Analysis and Prep. Time: {:?}",
             preparation_duration + a_dur
         );
         println!("Collapse Time: {:?}", c_dur);
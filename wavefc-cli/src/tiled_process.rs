@@ -0,0 +1,175 @@
+use super::DEFAULT_MAX_CONTRADICTIONS;
+use crate::constraints::apply_constraints_file;
+use crate::error::CliError;
+use crate::shared::SharedArgs;
+use crate::tileset::read_tileset;
+use chrono::Local;
+use clap::ArgMatches;
+use image::imageops;
+use image::io::Reader as ImageReader;
+use image::RgbaImage;
+use open::that;
+use std::fs::canonicalize;
+use std::path::{Path, PathBuf};
+use std::time::Instant;
+use wavefc::prelude::*;
+
+pub fn handler(matches: &ArgMatches) -> Result<(), CliError> {
+    let tileset_path = matches.get_one::<PathBuf>("tileset").unwrap();
+    let output = matches.get_one::<PathBuf>("output");
+    let open = matches.get_flag("open");
+
+    let shared_args = SharedArgs::from(matches);
+
+    let preparation_start = Instant::now();
+
+    let model = read_tileset(tileset_path).map_err(|e| CliError::from(e).context("Tileset"))?;
+    let mut wave = model.wave()?;
+
+    if let Some(seed) = shared_args.seed {
+        wave.set_seed(*seed);
+    }
+
+    wave.set_heuristic(shared_args.heuristic);
+    wave.set_backtracking(shared_args.backtracking);
+    wave.set_periodic(shared_args.periodic_x, shared_args.periodic_y);
+
+    if !shared_args.use_weights {
+        wave.flags.push(Flags::NoWeights);
+    }
+
+    wave.flags.push(Flags::NoHistory);
+
+    let tiles = load_tile_images(tileset_path, wave.palette().values())?;
+    let preparation_duration = preparation_start.elapsed();
+
+    println!("Loaded {} tile variants.", tiles.len());
+
+    wave.fill(Vector2::new(shared_args.width, shared_args.height))?;
+
+    // tiles are written by name, with an optional orientation after a colon, such as corner:1
+    if let Some(path) = shared_args.constraints {
+        apply_constraints_file(&mut wave, path, |value| {
+            value.replacen(':', " ", 1).parse::<TileVariant>().ok()
+        })?;
+    }
+
+    let real_contradictions = if let Some(max) = shared_args.max_contradictions {
+        *max
+    } else {
+        DEFAULT_MAX_CONTRADICTIONS
+    };
+
+    let collapse_start = Instant::now();
+    let stats = wave.collapse_all(
+        real_contradictions,
+        None::<fn(usize, usize, Vec<Vec<Vec<TileVariant>>>)>,
+    )?;
+    let collapse_duration = collapse_start.elapsed();
+
+    let result = wave.perfect_rep()?;
+    let (tile_width, tile_height) = tiles[0].dimensions();
+    let mut result_buffer = RgbaImage::new(
+        shared_args.width as u32 * tile_width,
+        shared_args.height as u32 * tile_height,
+    );
+
+    for (x, column) in result.iter().enumerate() {
+        for (y, variant) in column.iter().enumerate() {
+            let id = wave.palette().id(variant).unwrap();
+            imageops::replace(
+                &mut result_buffer,
+                &tiles[id],
+                x as i64 * tile_width as i64,
+                y as i64 * tile_height as i64,
+            );
+        }
+    }
+
+    let output_pathbuf = if let Some(filename) = output {
+        filename.to_owned()
+    } else {
+        let mut path = tileset_path.to_owned();
+        let file_stem = tileset_path.file_stem().unwrap().to_str().unwrap();
+        let time_string = Local::now().format("%Y-%m-%dT%H-%M-%S").to_string();
+        path.set_file_name(time_string + " " + file_stem + ".png");
+        path
+    };
+
+    result_buffer
+        .save(output_pathbuf.as_path())
+        .map_err(|e| e.to_string())?;
+
+    println!("Saved result to {}", output_pathbuf.to_str().unwrap());
+
+    println!("\nSeed: {}", wave.seed());
+    println!("Backtracks: {}", stats.backtracks);
+    println!("Preparation Time: {:?}", preparation_duration);
+    println!("Collapse Time: {:?}", collapse_duration);
+
+    if open {
+        let absolute_path =
+            canonicalize(output_pathbuf.to_str().unwrap()).map_err(|e| e.to_string())?;
+        println!("\nOpening... {}", absolute_path.to_str().unwrap());
+        that(absolute_path).map_err(|e| e.to_string())?;
+    }
+
+    Ok(())
+}
+
+/// Loads the image of each variant, in the same order as the given variants, from the directory of the tileset.
+///
+/// A variant uses the image named after it (such as `corner 1.png`) if there is one. Otherwise the tile's own image (`corner.png`) is rotated and mirrored to match the variant's orientation.
+fn load_tile_images(
+    tileset_path: &Path,
+    variants: &[TileVariant],
+) -> Result<Vec<RgbaImage>, String> {
+    let directory = tileset_path.parent().unwrap_or(Path::new(""));
+    let mut images: Vec<RgbaImage> = Vec::with_capacity(variants.len());
+
+    for variant in variants {
+        let own_path = directory.join(format!("{}.png", variant));
+
+        let image = if own_path.exists() {
+            read_tile(&own_path)?
+        } else {
+            let mut image = read_tile(&directory.join(format!("{}.png", variant.name)))?;
+
+            for _ in 0..variant.rotations() {
+                image = imageops::rotate270(&image);
+            }
+
+            if variant.mirrored() {
+                image = imageops::flip_horizontal(&image);
+            }
+
+            image
+        };
+
+        if let Some(first) = images.first() {
+            if first.dimensions() != image.dimensions() {
+                return Err(format!(
+                    "The image of {} isn't the same size as the other tiles.",
+                    variant
+                ));
+            }
+        }
+
+        images.push(image);
+    }
+
+    if images.is_empty() {
+        return Err("The tileset has no tiles.".to_string());
+    }
+
+    Ok(images)
+}
+
+fn read_tile(path: &Path) -> Result<RgbaImage, String> {
+    let image = ImageReader::open(path)
+        .map_err(|e| format!("The tile image {} cannot be opened: {}", path.display(), e))?
+        .decode()
+        .map_err(|e| format!("The tile image {} cannot be decoded: {}", path.display(), e))?;
+
+    Ok(image.into_rgba8())
+}
@@ -0,0 +1,247 @@
+use serde::Deserialize;
+use std::fs;
+use std::path::Path;
+use wavefc::prelude::*;
+
+#[derive(Deserialize)]
+struct TilesetFile {
+    tiles: Vec<TileEntry>,
+    #[serde(default)]
+    neighbors: Vec<NeighborEntry>,
+}
+
+#[derive(Deserialize)]
+struct TileEntry {
+    name: String,
+    #[serde(default = "default_symmetry")]
+    symmetry: String,
+    #[serde(default = "default_weight")]
+    weight: f64,
+}
+
+#[derive(Deserialize)]
+struct NeighborEntry {
+    left: String,
+    right: String,
+}
+
+fn default_symmetry() -> String {
+    "X".to_string()
+}
+
+fn default_weight() -> f64 {
+    1.
+}
+
+/// Reads a tileset into a `TiledModel`, from a JSON or TOML file or an XML file in the format of Maxim Gumin's tilesets, depending on its extension.
+///
+/// Both list the tiles with a `name`, a `symmetry` (X, I, \, T, L or F, by default X) and a `weight` (by default 1), and the pairs of tiles which can be placed next to each other with a `left` and `right` tile, optionally followed by an orientation such as `corner 1`. For example:
+///
+/// ```text
+/// <set>
+///   <tiles>
+///     <tile name="empty" symmetry="X"/>
+///     <tile name="line" symmetry="I" weight="0.5"/>
+///   </tiles>
+///   <neighbors>
+///     <neighbor left="line" right="line"/>
+///     <neighbor left="line 1" right="empty"/>
+///   </neighbors>
+/// </set>
+/// ```
+///
+/// The JSON and TOML versions hold the same `tiles` and `neighbors` as arrays of objects, which are `[[tiles]]` and `[[neighbors]]` tables in TOML.
+pub(crate) fn read_tileset(path: &Path) -> Result<TiledModel, String> {
+    let content = fs::read_to_string(path)
+        .map_err(|e| format!("The tileset file provided cannot be read: {}", e))?;
+
+    let tileset = parse_tileset(&content, path.extension().and_then(|e| e.to_str()))?;
+    build_model(tileset)
+}
+
+/// Parses the contents of a tileset file in the format given by its extension.
+fn parse_tileset(content: &str, extension: Option<&str>) -> Result<TilesetFile, String> {
+    match extension {
+        Some("json") => serde_json::from_str(content)
+            .map_err(|e| format!("The tileset file isn't valid: {}", e)),
+        Some("toml") => {
+            toml::from_str(content).map_err(|e| format!("The tileset file isn't valid: {}", e))
+        }
+        Some("xml") => parse_xml(content),
+        _ => Err("The tileset file must be a .json, .toml or .xml file.".to_string()),
+    }
+}
+
+fn parse_xml(content: &str) -> Result<TilesetFile, String> {
+    let document = roxmltree::Document::parse(content)
+        .map_err(|e| format!("The tileset file isn't valid: {}", e))?;
+
+    let attribute = |node: roxmltree::Node, name: &str| {
+        node.attribute(name).map(|a| a.to_string()).ok_or_else(|| {
+            format!(
+                "The <{}> on line {} has no {} attribute",
+                node.tag_name().name(),
+                document.text_pos_at(node.range().start).row,
+                name
+            )
+        })
+    };
+
+    let mut tileset = TilesetFile {
+        tiles: vec![],
+        neighbors: vec![],
+    };
+
+    let set = document.root_element();
+
+    if !set.has_tag_name("set") {
+        return Err("The tileset file must have a <set> at its root".to_string());
+    }
+
+    // only the tiles and neighbours directly inside the set are read, as the <subsets> of a tileset list some of its tiles again without their symmetry or weight
+    let entries = |group: &'static str, entry: &'static str| {
+        set.children()
+            .filter(move |n| n.has_tag_name(group))
+            .flat_map(move |n| n.children().filter(move |c| c.has_tag_name(entry)))
+    };
+
+    for node in entries("tiles", "tile") {
+        tileset.tiles.push(TileEntry {
+            name: attribute(node, "name")?,
+            symmetry: node
+                .attribute("symmetry")
+                .map_or_else(default_symmetry, String::from),
+            weight: match node.attribute("weight") {
+                Some(weight) => weight
+                    .parse()
+                    .map_err(|_| format!("{} isn't a valid weight", weight))?,
+                None => default_weight(),
+            },
+        });
+    }
+
+    for node in entries("neighbors", "neighbor") {
+        tileset.neighbors.push(NeighborEntry {
+            left: attribute(node, "left")?,
+            right: attribute(node, "right")?,
+        });
+    }
+
+    Ok(tileset)
+}
+
+fn build_model(tileset: TilesetFile) -> Result<TiledModel, String> {
+    let mut model = TiledModel::new();
+
+    for tile in tileset.tiles {
+        let symmetry = tile
+            .symmetry
+            .parse()
+            .map_err(|e| format!("Tile {}: {}", tile.name, e))?;
+        model.add_tile(&tile.name, symmetry, tile.weight);
+    }
+
+    for neighbor in tileset.neighbors {
+        model.add_neighbours(neighbor.left.parse()?, neighbor.right.parse()?);
+    }
+
+    Ok(model)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn subsets_keep_the_tiles_symmetry_and_weight() {
+        let tileset = parse_xml(
+            r#"<set>
+                <tiles>
+                    <tile name="corner" symmetry="L" weight="0.5"/>
+                    <tile name="empty"/>
+                </tiles>
+                <neighbors>
+                    <neighbor left="corner" right="empty"/>
+                </neighbors>
+                <subsets>
+                    <subset name="corners">
+                        <tile name="corner"/>
+                    </subset>
+                </subsets>
+            </set>"#,
+        )
+        .unwrap();
+
+        assert_eq!(tileset.tiles.len(), 2);
+        assert_eq!(tileset.tiles[0].symmetry, "L");
+        assert_eq!(tileset.tiles[0].weight, 0.5);
+        assert_eq!(tileset.neighbors.len(), 1);
+
+        // the corner keeps all four of its orientations
+        assert_eq!(build_model(tileset).unwrap().variants().len(), 5);
+    }
+
+    #[test]
+    fn json_and_toml_tilesets_match() {
+        let json = parse_tileset(
+            r#"{
+                "tiles": [{ "name": "line", "symmetry": "I", "weight": 2 }, { "name": "empty" }],
+                "neighbors": [{ "left": "line 1", "right": "empty" }]
+            }"#,
+            Some("json"),
+        )
+        .unwrap();
+        let toml = parse_tileset(
+            r#"
+            [[tiles]]
+            name = "line"
+            symmetry = "I"
+            weight = 2.0
+
+            [[tiles]]
+            name = "empty"
+
+            [[neighbors]]
+            left = "line 1"
+            right = "empty"
+            "#,
+            Some("toml"),
+        )
+        .unwrap();
+
+        for tileset in [json, toml] {
+            assert_eq!(tileset.tiles[0].weight, 2.);
+            assert_eq!(tileset.tiles[1].symmetry, "X");
+            assert_eq!(tileset.tiles[1].weight, 1.);
+            assert_eq!(tileset.neighbors[0].left, "line 1");
+            assert_eq!(build_model(tileset).unwrap().variants().len(), 3);
+        }
+    }
+
+    #[test]
+    fn tilesets_need_a_known_extension() {
+        assert!(parse_tileset(r#"{ "tiles": [] }"#, Some("yaml")).is_err());
+        assert!(parse_tileset(r#"{ "tiles": [] }"#, None).is_err());
+    }
+
+    #[test]
+    fn invalid_tile_attributes_are_reported() {
+        assert_eq!(
+            parse_xml(r#"<set><tiles><tile name="line" weight="heavy"/></tiles></set>"#).err(),
+            Some("heavy isn't a valid weight".to_string())
+        );
+        assert_eq!(
+            parse_xml("<set>\n<tiles>\n<tile symmetry=\"I\"/></tiles></set>").err(),
+            Some("The <tile> on line 3 has no name attribute".to_string())
+        );
+
+        let tileset =
+            parse_xml(r#"<set><tiles><tile name="line" symmetry="Z"/></tiles></set>"#).unwrap();
+        assert!(build_model(tileset).is_err());
+    }
+
+    #[test]
+    fn tilesets_need_a_set_at_their_root() {
+        assert!(parse_xml(r#"<tiles><tile name="empty"/></tiles>"#).is_err());
+    }
+}
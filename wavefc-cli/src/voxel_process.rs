@@ -0,0 +1,99 @@
+use super::DEFAULT_MAX_CONTRADICTIONS;
+use crate::constraints::{apply_volume_constraints_file, char_value};
+use crate::error::CliError;
+use crate::shared::SharedArgs;
+use clap::ArgMatches;
+use std::fs;
+use std::path::PathBuf;
+use std::time::Instant;
+use wavefc::prelude::*;
+
+pub fn handler(matches: &ArgMatches) -> Result<(), CliError> {
+    let pathbuf = matches.get_one::<PathBuf>("sample").unwrap();
+    let depth = *matches.get_one::<usize>("depth").unwrap();
+    let tiledepth = matches.get_one::<usize>("tiledepth");
+    let output = matches.get_one::<PathBuf>("output");
+
+    let shared_args = SharedArgs::from(matches);
+
+    let preparation_start = Instant::now();
+
+    let content = fs::read_to_string(pathbuf)
+        .map_err(|e| format!("The sample provided cannot be read: {}", e))?;
+    let sample =
+        deconstruct_layers(&content, false).map_err(|e| CliError::from(e).context("Sample"))?;
+
+    let chunk_size = {
+        let tilesize = shared_args.tilesize.copied().unwrap_or(1);
+        let mut size = Vector3::new(tilesize, tilesize, tilesize);
+
+        if let Some(width) = shared_args.tilewidth {
+            size.x = *width;
+        }
+
+        if let Some(height) = shared_args.tileheight {
+            size.y = *height;
+        }
+
+        if let Some(depth) = tiledepth {
+            size.z = *depth;
+        }
+
+        size
+    };
+
+    let mut wave = Wave::new();
+
+    if let Some(seed) = shared_args.seed {
+        wave.set_seed(*seed);
+    }
+
+    wave.set_heuristic(shared_args.heuristic);
+    wave.set_backtracking(shared_args.backtracking);
+    wave.set_periodic(shared_args.periodic_x, shared_args.periodic_y);
+    wave.set_symmetry(shared_args.symmetry);
+
+    if !shared_args.use_weights {
+        wave.flags.push(Flags::NoWeights);
+    }
+
+    wave.flags.push(Flags::NoHistory);
+
+    wave.analyze_volume(sample, chunk_size, shared_args.border_mode);
+    wave.fill_volume(Vector3::new(shared_args.width, shared_args.height, depth))?;
+    let preparation_duration = preparation_start.elapsed();
+
+    // the same single characters as in the string mode, after an x, y and z position
+    if let Some(path) = shared_args.constraints {
+        apply_volume_constraints_file(&mut wave, path, char_value)?;
+    }
+
+    let real_contradictions = if let Some(max) = shared_args.max_contradictions {
+        *max
+    } else {
+        DEFAULT_MAX_CONTRADICTIONS
+    };
+
+    let collapse_start = Instant::now();
+    let stats = wave.collapse_all(
+        real_contradictions,
+        None::<fn(usize, usize, Vec<Vec<Vec<char>>>)>,
+    )?;
+    let collapse_duration = collapse_start.elapsed();
+
+    let result = reconstruct_layers(&wave.perfect_volume()?);
+
+    if let Some(path) = output {
+        fs::write(path, result + "\n").map_err(|e| e.to_string())?;
+        println!("Saved result to {}", path.display());
+    } else {
+        println!("{}", result);
+    }
+
+    println!("\nSeed: {}", wave.seed());
+    println!("Backtracks: {}", stats.backtracks);
+    println!("Preparation Time: {:?}", preparation_duration);
+    println!("Collapse Time: {:?}", collapse_duration);
+
+    Ok(())
+}
@@ -0,0 +1,148 @@
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+/// A fixed-size set of indexes stored as packed bits.
+///
+/// This is used to hold the pattern ids which are still possible for an element. The number of set bits is cached, so checking the length of the set doesn't require counting.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub(crate) struct BitSet {
+    words: Vec<u64>,
+    capacity: usize,
+    len: usize,
+}
+
+impl BitSet {
+    /// Creates a set that can hold indexes up to (but not including) `capacity`, with none of them set.
+    pub fn empty(capacity: usize) -> Self {
+        Self {
+            words: vec![0; capacity.div_ceil(64)],
+            capacity,
+            len: 0,
+        }
+    }
+
+    /// Creates a set that can hold indexes up to (but not including) `capacity`, with all of them set.
+    pub fn full(capacity: usize) -> Self {
+        let mut set = Self::empty(capacity);
+
+        for word in set.words.iter_mut() {
+            *word = u64::MAX;
+        }
+
+        // clear the unused bits at the end of the last word
+        if !capacity.is_multiple_of(64) {
+            if let Some(last) = set.words.last_mut() {
+                *last = (1 << (capacity % 64)) - 1;
+            }
+        }
+
+        set.len = capacity;
+        set
+    }
+
+    /// The number of indexes in the set.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn contains(&self, index: usize) -> bool {
+        index < self.capacity && self.words[index / 64] & (1 << (index % 64)) != 0
+    }
+
+    /// Adds the index to the set, returning whether it wasn't already present.
+    pub fn insert(&mut self, index: usize) -> bool {
+        debug_assert!(index < self.capacity);
+
+        let word = &mut self.words[index / 64];
+        let mask = 1 << (index % 64);
+
+        if *word & mask != 0 {
+            return false;
+        }
+
+        *word |= mask;
+        self.len += 1;
+        true
+    }
+
+    /// Removes the index from the set, returning whether it was present.
+    pub fn remove(&mut self, index: usize) -> bool {
+        if !self.contains(index) {
+            return false;
+        }
+
+        self.words[index / 64] &= !(1 << (index % 64));
+        self.len -= 1;
+        true
+    }
+
+    pub fn clear(&mut self) {
+        for word in self.words.iter_mut() {
+            *word = 0;
+        }
+
+        self.len = 0;
+    }
+
+    /// Adds every index in `other` to this set.
+    pub fn union_with(&mut self, other: &BitSet) {
+        debug_assert_eq!(self.capacity, other.capacity);
+
+        for (word, other_word) in self.words.iter_mut().zip(other.words.iter()) {
+            // only the bits which are new to this set add to its length
+            self.len += (other_word & !*word).count_ones() as usize;
+            *word |= other_word;
+        }
+    }
+
+    /// The indexes in this set which aren't in `other`.
+    pub fn difference(&self, other: &BitSet) -> Vec<usize> {
+        debug_assert_eq!(self.capacity, other.capacity);
+
+        let mut indexes = vec![];
+
+        for (i, (word, other_word)) in self.words.iter().zip(other.words.iter()).enumerate() {
+            push_set_bits(&mut indexes, i, word & !other_word);
+        }
+
+        indexes
+    }
+
+    /// The smallest index in the set, if there is one.
+    pub fn first(&self) -> Option<usize> {
+        self.words
+            .iter()
+            .enumerate()
+            .find(|(_, w)| **w != 0)
+            .map(|(i, w)| i * 64 + w.trailing_zeros() as usize)
+    }
+
+    /// Iterates over the indexes in the set in ascending order.
+    pub fn iter(&self) -> impl Iterator<Item = usize> + '_ {
+        self.words.iter().enumerate().flat_map(|(i, word)| {
+            let mut remaining = *word;
+
+            std::iter::from_fn(move || {
+                if remaining == 0 {
+                    return None;
+                }
+
+                let bit = remaining.trailing_zeros() as usize;
+                remaining &= remaining - 1;
+                Some(i * 64 + bit)
+            })
+        })
+    }
+}
+
+fn push_set_bits(indexes: &mut Vec<usize>, word_index: usize, mut word: u64) {
+    while word != 0 {
+        indexes.push(word_index * 64 + word.trailing_zeros() as usize);
+        word &= word - 1;
+    }
+}
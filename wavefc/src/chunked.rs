@@ -0,0 +1,314 @@
+use crate::{mix_seed, Square, Wave, WaveError};
+use cgmath::Vector2;
+use std::any::Any;
+use std::collections::HashMap;
+use std::hash::Hash;
+
+/// Generates an unbounded world lazily, one fixed size region at a time, such as the areas around a player as they explore.
+///
+/// Regions are addressed by their coordinate in the grid of regions, so the region at `(1, -2)` covers the cells from `(width, -2 * height)` up to `(2 * width, -height)`. Every region is made of four pieces, which are each collapsed by their own run of the wave:
+///
+/// * the corner chunk at its origin, which is collapsed on its own,
+/// * the seam along its top edge up to the next corner, and the one along its left edge, which are collapsed between the two corners they join, and
+/// * the rest of the region, which is collapsed with every corner and seam around it pinned.
+///
+/// The pieces are filled with a margin of one chunk where nothing is pinned, so the pieces next to them can still be collapsed. As the corners and seams a region shares with its neighbours are derived from the world seed and their own coordinate, every region only depends on the world seed and its coordinate, whichever order the regions are requested in and whether they were unloaded in between.
+///
+/// When a piece runs out of contradictions, it's tried again from scratch with a seed derived from the first, up to the number of retries set with `set_max_retries`. Setting up backtracking on the wave (see `Wave::set_backtracking`) lets it repair contradictions near the seams within each try as well. A region which still can't be collapsed, such as when the corners and seams it's pinned to can't be joined at all, only fails on its own. Requesting it again fails the same way, so a world seed which gives such regions is best changed.
+///
+/// Generation only works on planar waves with the `Square` topology, which `new` checks. The wave's constraints are managed by the generator, and it never wraps around.
+pub struct ChunkedGenerator<T> {
+    wave: Wave<T>,
+    region_size: Vector2<usize>,
+    world_seed: u64,
+    max_contradictions: usize,
+    max_retries: usize,
+    regions: HashMap<Vector2<i64>, Vec<Vec<T>>>,
+    /// The corners and seams which have been collapsed, by the coordinate of the region they belong to.
+    pieces: HashMap<(Piece, Vector2<i64>), Vec<Vec<T>>>,
+}
+
+/// A part of a region which is collapsed by its own run of the wave.
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+enum Piece {
+    /// The chunk at the origin of the region.
+    Corner,
+    /// The cells along the top edge of the region between its corner and the next corner along x, one chunk tall.
+    TopSeam,
+    /// The cells along the left edge of the region between its corner and the next corner along y, one chunk wide.
+    LeftSeam,
+    /// Everything of the region which isn't its corner or one of its seams.
+    Rest,
+}
+
+impl<T: Eq + Hash + Clone> ChunkedGenerator<T> {
+    /// Creates a generator for regions of the given size, which collapses them with a wave which has already analyzed its sample.
+    ///
+    /// An error is returned if the wave has no patterns, if it isn't planar or doesn't have the `Square` topology, if the size of the regions isn't a multiple of the wave's chunk size, or if it's less than two chunks along either axis.
+    pub fn new(
+        mut wave: Wave<T>,
+        region_size: Vector2<usize>,
+        world_seed: u64,
+    ) -> Result<Self, WaveError> {
+        if wave.patterns.is_empty() {
+            return Err(WaveError::NoPatterns);
+        }
+
+        let chunk_size = wave.chunk_size;
+        let topology: &dyn Any = wave.topology();
+
+        // the seams are laid out as rows and columns of square chunks, which other grids don't have
+        if !topology.is::<Square>() || chunk_size.z != 1 {
+            return Err(WaveError::UnsupportedTopology);
+        }
+
+        if region_size.x == 0
+            || region_size.y == 0
+            || !region_size.x.is_multiple_of(chunk_size.x)
+            || !region_size.y.is_multiple_of(chunk_size.y)
+        {
+            return Err(WaveError::SizeNotMultipleOfChunk {
+                size: region_size.extend(1),
+                chunk_size,
+            });
+        }
+
+        // otherwise the corners of neighbouring regions would touch, without a seam to join them
+        if region_size.x < chunk_size.x * 2 || region_size.y < chunk_size.y * 2 {
+            return Err(WaveError::RegionTooSmall {
+                region_size,
+                chunk_size,
+            });
+        }
+
+        wave.set_periodic(false, false);
+        wave.clear_constraints();
+
+        Ok(Self {
+            wave,
+            region_size,
+            world_seed,
+            max_contradictions: 20,
+            max_retries: 3,
+            regions: HashMap::new(),
+            pieces: HashMap::new(),
+        })
+    }
+
+    pub fn world_seed(&self) -> u64 {
+        self.world_seed
+    }
+
+    pub fn region_size(&self) -> Vector2<usize> {
+        self.region_size
+    }
+
+    /// Sets the number of times the wave can restart a region before giving up on it. By default this is 20.
+    pub fn set_max_contradictions(&mut self, max_contradictions: usize) {
+        self.max_contradictions = max_contradictions;
+    }
+
+    /// Sets the number of times a corner, seam or region is tried again with another seed after running out of contradictions, before giving up on it. By default this is 3.
+    pub fn set_max_retries(&mut self, max_retries: usize) {
+        self.max_retries = max_retries;
+    }
+
+    /// The region at the given coordinate, generating it first if it hasn't been already.
+    ///
+    /// Regions are indexed the same way as the result of `Wave::perfect_rep`. An error is returned if the region or one of the corners and seams around it can't be collapsed, in which case the region isn't kept.
+    pub fn region(&mut self, coordinate: Vector2<i64>) -> Result<&[Vec<T>], WaveError> {
+        if !self.regions.contains_key(&coordinate) {
+            let region = self.collapse_piece(Piece::Rest, coordinate)?;
+            let region = crop(&region, Vector2::new(0, 0), self.region_size);
+            self.regions.insert(coordinate, region);
+        }
+
+        Ok(&self.regions[&coordinate])
+    }
+
+    /// The region at the given coordinate, if it has been generated.
+    pub fn get(&self, coordinate: Vector2<i64>) -> Option<&[Vec<T>]> {
+        self.regions.get(&coordinate).map(|r| r.as_slice())
+    }
+
+    /// The value of the cell at the given position of the world, generating the region it's in first if needed.
+    pub fn cell(&mut self, position: Vector2<i64>) -> Result<&T, WaveError> {
+        let (coordinate, offset) = self.locate(position);
+        let region = self.region(coordinate)?;
+
+        Ok(&region[offset.x][offset.y])
+    }
+
+    /// Forgets the region at the given coordinate, returning it if it had been generated.
+    ///
+    /// Its corner and seams are forgotten as well. Generating the region again gives the same result, as it only depends on the world seed and its coordinate.
+    pub fn unload(&mut self, coordinate: Vector2<i64>) -> Option<Vec<Vec<T>>> {
+        for piece in [Piece::Corner, Piece::TopSeam, Piece::LeftSeam] {
+            self.pieces.remove(&(piece, coordinate));
+        }
+
+        self.regions.remove(&coordinate)
+    }
+
+    /// The coordinates of every region which has been generated, in no particular order.
+    pub fn coordinates(&self) -> impl Iterator<Item = &Vector2<i64>> {
+        self.regions.keys()
+    }
+
+    /// Finds the region the cell at the given world position is in, and its position within that region.
+    fn locate(&self, position: Vector2<i64>) -> (Vector2<i64>, Vector2<usize>) {
+        let width = self.region_size.x as i64;
+        let height = self.region_size.y as i64;
+
+        (
+            Vector2::new(position.x.div_euclid(width), position.y.div_euclid(height)),
+            Vector2::new(
+                position.x.rem_euclid(width) as usize,
+                position.y.rem_euclid(height) as usize,
+            ),
+        )
+    }
+
+    /// The cells a piece of the region at the given coordinate covers, as the world position of its first cell and its size.
+    fn bounds(&self, piece: Piece, coordinate: Vector2<i64>) -> (Vector2<i64>, Vector2<usize>) {
+        let chunk = Vector2::new(self.wave.chunk_size.x, self.wave.chunk_size.y);
+        let origin = Vector2::new(
+            coordinate.x * self.region_size.x as i64,
+            coordinate.y * self.region_size.y as i64,
+        );
+
+        let (offset, size) = match piece {
+            Piece::Corner => (Vector2::new(0, 0), chunk),
+            Piece::TopSeam => (
+                Vector2::new(chunk.x, 0),
+                Vector2::new(self.region_size.x - chunk.x, chunk.y),
+            ),
+            Piece::LeftSeam => (
+                Vector2::new(0, chunk.y),
+                Vector2::new(chunk.x, self.region_size.y - chunk.y),
+            ),
+            Piece::Rest => (chunk, self.region_size - chunk),
+        };
+
+        (
+            Vector2::new(origin.x + offset.x as i64, origin.y + offset.y as i64),
+            size,
+        )
+    }
+
+    /// Makes sure the piece of the region at the given coordinate has been collapsed, along with the pieces it's pinned to.
+    fn generate_piece(&mut self, piece: Piece, coordinate: Vector2<i64>) -> Result<(), WaveError> {
+        if self.pieces.contains_key(&(piece, coordinate)) {
+            return Ok(());
+        }
+
+        let area = self.collapse_piece(piece, coordinate)?;
+        let margin = Vector2::new(self.wave.chunk_size.x, self.wave.chunk_size.y);
+        let (_, size) = self.bounds(piece, coordinate);
+
+        self.pieces
+            .insert((piece, coordinate), crop(&area, margin, size));
+        Ok(())
+    }
+
+    /// Collapses the piece of the region at the given coordinate with a margin of one chunk around it, pinning the pieces it touches, and returns the whole collapsed area.
+    fn collapse_piece(
+        &mut self,
+        piece: Piece,
+        coordinate: Vector2<i64>,
+    ) -> Result<Vec<Vec<T>>, WaveError> {
+        let at = |x, y| Vector2::new(coordinate.x + x, coordinate.y + y);
+        // corners are collapsed on their own, seams between the corners at their ends, and the rest of a region inside all of them
+        let pins = match piece {
+            Piece::Corner => vec![],
+            Piece::TopSeam => vec![(Piece::Corner, at(0, 0)), (Piece::Corner, at(1, 0))],
+            Piece::LeftSeam => vec![(Piece::Corner, at(0, 0)), (Piece::Corner, at(0, 1))],
+            Piece::Rest => vec![
+                (Piece::Corner, at(0, 0)),
+                (Piece::Corner, at(1, 0)),
+                (Piece::Corner, at(0, 1)),
+                (Piece::Corner, at(1, 1)),
+                (Piece::TopSeam, at(0, 0)),
+                (Piece::TopSeam, at(0, 1)),
+                (Piece::LeftSeam, at(0, 0)),
+                (Piece::LeftSeam, at(1, 0)),
+            ],
+        };
+
+        for &(pin, coordinate) in &pins {
+            self.generate_piece(pin, coordinate)?;
+        }
+
+        let margin = Vector2::new(self.wave.chunk_size.x, self.wave.chunk_size.y);
+        let (position, size) = self.bounds(piece, coordinate);
+        // the world position of the wave's first cell
+        let origin = Vector2::new(position.x - margin.x as i64, position.y - margin.y as i64);
+        let seed = [piece as u64, coordinate.x as u64, coordinate.y as u64]
+            .into_iter()
+            .fold(self.world_seed, mix_seed);
+
+        for retry in 0..=self.max_retries {
+            self.wave.clear_constraints();
+            self.wave.fill(size + margin * 2)?;
+
+            // retries only depend on the first seed, so the piece still only depends on the world seed and its coordinate
+            if retry == 0 {
+                self.wave.set_seed(seed);
+            } else {
+                self.wave.set_seed(mix_seed(seed, retry as u64));
+            }
+
+            let collapsed = self.pin(&pins, origin).and_then(|_| {
+                self.wave.collapse_all(
+                    self.max_contradictions,
+                    None::<fn(usize, usize, Vec<Vec<Vec<T>>>)>,
+                )
+            });
+
+            self.wave.clear_constraints();
+
+            match collapsed {
+                Ok(_) => return self.wave.perfect_rep(),
+                Err(WaveError::MaxContradictions(_)) if retry < self.max_retries => continue,
+                Err(error) => return Err(error),
+            }
+        }
+
+        unreachable!("the last retry always returns")
+    }
+
+    /// Constrains the wave to the values of the given pieces, with the wave's first cell at the given world position.
+    fn pin(
+        &mut self,
+        pins: &[(Piece, Vector2<i64>)],
+        origin: Vector2<i64>,
+    ) -> Result<(), WaveError> {
+        for &(pin, coordinate) in pins {
+            let (position, _) = self.bounds(pin, coordinate);
+            let offset = Vector2::new(
+                (position.x - origin.x) as usize,
+                (position.y - origin.y) as usize,
+            );
+
+            // the constraints are kept by the wave, so restarts are pinned the same way
+            for (x, column) in self.pieces[&(pin, coordinate)].iter().enumerate() {
+                for (y, value) in column.iter().enumerate() {
+                    self.wave.constrain(
+                        Vector2::new(offset.x + x, offset.y + y),
+                        std::slice::from_ref(value),
+                    )?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// The cells of a grid within the rectangle starting at `offset` with the given size.
+fn crop<T: Clone>(cells: &[Vec<T>], offset: Vector2<usize>, size: Vector2<usize>) -> Vec<Vec<T>> {
+    cells[offset.x..offset.x + size.x]
+        .iter()
+        .map(|column| column[offset.y..offset.y + size.y].to_vec())
+        .collect()
+}
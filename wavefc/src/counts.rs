@@ -0,0 +1,150 @@
+use crate::{Element, Pattern};
+use cgmath::Vector3;
+
+/// The number of cells of the output which are or could still be each value with a count limit, kept up to date as patterns are removed from the elements and put back, so the limits can be checked without going over the whole output.
+///
+/// A cell is decided when every pattern left in its element has the value there, and possible when only some of them do. Elements which have run out of patterns don't count towards either.
+#[derive(Clone)]
+pub(crate) struct ValueCounts {
+    /// The number of limited values, which are counted in the order of the wave's limits.
+    limits: usize,
+    /// The number of cells in a chunk.
+    cells: usize,
+    /// For each pattern (by id) and cell of its chunk, the limited value it has there, if any.
+    slots: Vec<Vec<Option<usize>>>,
+    /// For each element (by index), the number of patterns it has left.
+    remaining: Vec<usize>,
+    /// For each element, cell of its chunk and limited value, the number of the element's patterns with the value in that cell.
+    support: Vec<usize>,
+    /// For each limited value, the number of cells which can only be it.
+    decided: Vec<usize>,
+    /// For each limited value, the number of cells which could still be it, alongside other values.
+    possible: Vec<usize>,
+}
+
+impl ValueCounts {
+    /// Counts the given palette ids over the elements from scratch.
+    pub fn new(
+        values: &[usize],
+        patterns: &[Pattern],
+        chunk_size: Vector3<usize>,
+        elements: &[Element],
+    ) -> Self {
+        let slots: Vec<Vec<Option<usize>>> = patterns
+            .iter()
+            .map(|pattern| {
+                chunk_cells(chunk_size)
+                    .map(|c| {
+                        let value = pattern.contents[c.x][c.y][c.z];
+                        values.iter().position(|&v| v == value)
+                    })
+                    .collect()
+            })
+            .collect();
+
+        let cells = chunk_size.x * chunk_size.y * chunk_size.z;
+        let mut counts = Self {
+            limits: values.len(),
+            cells,
+            slots,
+            remaining: elements.iter().map(|e| e.values.len()).collect(),
+            support: vec![0; elements.len() * cells * values.len()],
+            decided: vec![0; values.len()],
+            possible: vec![0; values.len()],
+        };
+
+        for (element_index, element) in elements.iter().enumerate() {
+            for pattern in element.values.iter() {
+                for cell in 0..cells {
+                    if let Some(slot) = counts.slots[pattern][cell] {
+                        let index = counts.index(element_index, cell, slot);
+                        counts.support[index] += 1;
+                    }
+                }
+            }
+
+            for cell in 0..cells {
+                for slot in 0..counts.limits {
+                    let support = counts.support[counts.index(element_index, cell, slot)];
+                    counts.tally(slot, support, counts.remaining[element_index], true);
+                }
+            }
+        }
+
+        counts
+    }
+
+    /// The number of cells which can only be the limited value at the given index.
+    pub fn decided(&self, slot: usize) -> usize {
+        self.decided[slot]
+    }
+
+    /// The number of cells which could still be the limited value at the given index, alongside other values.
+    pub fn possible(&self, slot: usize) -> usize {
+        self.possible[slot]
+    }
+
+    /// Takes a pattern which was just removed from an element out of the counts.
+    pub fn remove(&mut self, element_index: usize, pattern: usize) {
+        self.update(element_index, pattern, false);
+    }
+
+    /// Puts a pattern which was just restored to an element back into the counts.
+    pub fn restore(&mut self, element_index: usize, pattern: usize) {
+        self.update(element_index, pattern, true);
+    }
+
+    fn update(&mut self, element_index: usize, pattern: usize, added: bool) {
+        let remaining = self.remaining[element_index];
+        let updated = if added { remaining + 1 } else { remaining - 1 };
+
+        // every cell of the element changes, as the number of patterns the values are measured against does
+        for cell in 0..self.cells {
+            let value = self.slots[pattern][cell];
+
+            for slot in 0..self.limits {
+                let index = self.index(element_index, cell, slot);
+                let support = self.support[index];
+
+                self.tally(slot, support, remaining, false);
+
+                if value == Some(slot) {
+                    self.support[index] = if added { support + 1 } else { support - 1 };
+                }
+
+                self.tally(slot, self.support[index], updated, true);
+            }
+        }
+
+        self.remaining[element_index] = updated;
+    }
+
+    /// Adds a cell to the totals of a limited value, or takes it out, given the number of its element's patterns with the value there and the number of patterns the element has left.
+    fn tally(&mut self, slot: usize, support: usize, remaining: usize, added: bool) {
+        let total = if support == 0 {
+            return;
+        } else if support == remaining {
+            &mut self.decided[slot]
+        } else {
+            &mut self.possible[slot]
+        };
+
+        if added {
+            *total += 1;
+        } else {
+            *total -= 1;
+        }
+    }
+
+    fn index(&self, element_index: usize, cell: usize, slot: usize) -> usize {
+        (element_index * self.cells + cell) * self.limits + slot
+    }
+}
+
+/// The offsets of the cells in a chunk of the given size, in the order they're counted in.
+fn chunk_cells(chunk_size: Vector3<usize>) -> impl Iterator<Item = Vector3<usize>> {
+    (0..chunk_size.x).flat_map(move |x| {
+        (0..chunk_size.y)
+            .flat_map(move |y| (0..chunk_size.z).map(move |z| Vector3::new(x, y, z)))
+    })
+}
@@ -0,0 +1,220 @@
+use crate::CollapseStats;
+use cgmath::{Vector2, Vector3};
+use std::fmt;
+
+/// The errors which can occur while setting up, collapsing or inspecting a `Wave`.
+///
+/// Positions are those of the wave's elements (one per chunk of the output), unless stated otherwise. When displayed, the z of a position is left out if it's 0, and the depth of a size if it's 1, as it always is for planar waves.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum WaveError {
+    /// No patterns were found, either because `analyze` wasn't called or the sample was flawed.
+    NoPatterns,
+    /// The wave hasn't been filled, or was filled with an empty size.
+    NotFilled,
+    /// The output size isn't a multiple of the chunk size used in the analysis.
+    SizeNotMultipleOfChunk {
+        size: Vector3<usize>,
+        chunk_size: Vector3<usize>,
+    },
+    /// The regions of a `ChunkedGenerator` must be at least two chunks wide and tall, so their seams don't touch.
+    RegionTooSmall {
+        region_size: Vector2<usize>,
+        chunk_size: Vector3<usize>,
+    },
+    /// A `ChunkedGenerator` was given a wave which isn't planar or doesn't have the `Square` topology, which are the only waves it can line up seams on.
+    UnsupportedTopology,
+    /// The wave's topology can't learn patterns from chunks of the given size.
+    UnsupportedChunkSize { chunk_size: Vector2<usize> },
+    /// `collapse_all` reached its max number of contradictions, with the statistics of the run up to that point.
+    MaxContradictions(CollapseStats),
+    /// The wave isn't completely collapsed yet.
+    NotCollapsed,
+    /// An element ran out of patterns.
+    Contradiction { position: Vector3<usize> },
+    /// A constraint on the output cell at the given position can't be satisfied alongside the previous constraints.
+    UnsatisfiableConstraint { position: Vector3<usize> },
+    /// The count limits of the sample value with the given palette id can't be satisfied, either on their own or with the wave's cells and constraints.
+    UnsatisfiableCount { id: usize },
+    /// A count limit asks for at least `min` cells of a value which isn't in the analyzed sample, so no cell can ever be it.
+    UnknownCountValue { min: usize },
+    /// The output cell at the given position is outside of the output.
+    OutOfBounds { position: Vector3<usize> },
+    /// An inpainting mask isn't the same size as the existing output.
+    MaskSizeMismatch {
+        expected: Vector2<usize>,
+        found: Vector2<usize>,
+    },
+    /// A history record was made at a different iteration than the one the wave is on.
+    HistoryMismatch {
+        record_iteration: usize,
+        wave_iteration: usize,
+    },
+    /// A history record refers to an element or patterns which don't exist in the wave.
+    InvalidRecord { position: Vector3<usize> },
+    /// The collapse was cancelled before it finished, with the wave's cancel token or by another worker of `collapse_parallel` succeeding first.
+    Cancelled,
+    /// The deadline of the wave's `CollapseBudget` passed before the collapse finished.
+    DeadlineExceeded,
+    /// The collapse made as many observations as the wave's `CollapseBudget` allows without finishing.
+    ObservationLimitReached,
+    /// The wave's history is disabled with `Flags::NoHistory`, so it has no log of its decisions.
+    HistoryDisabled,
+    /// A `GenerationLog` was made with different rules than the wave replaying it has, going by their fingerprints.
+    RulesetMismatch { expected: u64, found: u64 },
+    /// The step of a `GenerationLog` at the given index (from 0) can't be made on the wave, because its element doesn't exist or can't be the pattern anymore.
+    InvalidLogStep { step: usize },
+    /// A `Ruleset` loaded with `Wave::from_ruleset` is inconsistent, for the given reason.
+    InvalidRuleset { reason: String },
+    /// A `TiledModel` refers to a tile which hasn't been added, or to an orientation outside of the 8 a tile can have.
+    UnknownTile { name: String, orientation: usize },
+    /// A tile of a `TiledModel` has a weight which isn't a positive, finite number, so it can't be chosen in proportion to it.
+    InvalidTileWeight { name: String },
+    /// A `Graph` refers to a node which hasn't been added.
+    UnknownNode { node: usize },
+    /// A `Graph` already has the 256 labels it can have, as they're numbered with a `u8`.
+    TooManyLabels,
+    /// A `Graph` or `GraphModel` refers to a label which hasn't been added to the graph.
+    UnknownLabel { label: u8 },
+    /// A `GraphModel` refers to a value index which hasn't been added.
+    UnknownValue { id: usize },
+    /// The value of a `GraphModel` at the given index has a weight which isn't a positive, finite number, so it can't be chosen in proportion to it.
+    InvalidValueWeight { id: usize },
+}
+
+impl fmt::Display for WaveError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            WaveError::NoPatterns => write!(f, "The number of rules identified was zero. The input was flawed or the wave was configured incorrectly."),
+            WaveError::NotFilled => write!(f, "The superpositions are empty or were not filled properly"),
+            WaveError::SizeNotMultipleOfChunk { size, chunk_size } => write!(
+                f,
+                "The output size {} must be a multiple of the chunk size {}",
+                Size(size),
+                Size(chunk_size)
+            ),
+            WaveError::RegionTooSmall {
+                region_size,
+                chunk_size,
+            } => write!(
+                f,
+                "The region size {}x{} must be at least two chunks ({}) along each axis",
+                region_size.x,
+                region_size.y,
+                Size(chunk_size)
+            ),
+            WaveError::UnsupportedTopology => write!(f, "Regions can only be generated by planar waves on a square grid"),
+            WaveError::UnsupportedChunkSize { chunk_size } => write!(
+                f,
+                "The topology can't learn patterns from chunks of {}x{}",
+                chunk_size.x, chunk_size.y
+            ),
+            WaveError::MaxContradictions(stats) => write!(
+                f,
+                "The max number of contradictions has been reached ({} contradictions, {} backtracks)",
+                stats.contradictions, stats.backtracks
+            ),
+            WaveError::NotCollapsed => write!(f, "The superpositions aren't completely collapsed yet"),
+            WaveError::Contradiction { position } => write!(
+                f,
+                "A contradiction occurred at {}",
+                Position(position)
+            ),
+            WaveError::UnsatisfiableConstraint { position } => write!(
+                f,
+                "The constraint on {} cannot be satisfied",
+                Position(position)
+            ),
+            WaveError::UnsatisfiableCount { id } => write!(
+                f,
+                "The count limits of the value with palette id {} cannot be satisfied",
+                id
+            ),
+            WaveError::UnknownCountValue { min } => write!(
+                f,
+                "The count limit asks for at least {} cells of a value which isn't in the sample",
+                min
+            ),
+            WaveError::OutOfBounds { position } => write!(
+                f,
+                "The position {} is outside of the output",
+                Position(position)
+            ),
+            WaveError::MaskSizeMismatch { expected, found } => write!(
+                f,
+                "The mask must be the same size as the existing output ({}x{}), but it's {}x{}",
+                expected.x, expected.y, found.x, found.y
+            ),
+            WaveError::HistoryMismatch {
+                record_iteration,
+                wave_iteration,
+            } => write!(
+                f,
+                "The record's iteration ({}) does not match the internal state of the wave ({})",
+                record_iteration, wave_iteration
+            ),
+            WaveError::InvalidRecord { position } => write!(
+                f,
+                "The record at {} refers to an element or patterns which don't exist",
+                Position(position)
+            ),
+            WaveError::Cancelled => write!(f, "The collapse was cancelled before it finished"),
+            WaveError::DeadlineExceeded => write!(f, "The deadline passed before the collapse finished"),
+            WaveError::ObservationLimitReached => write!(f, "The max number of observations was made before the collapse finished"),
+            WaveError::HistoryDisabled => write!(f, "The wave's history is disabled, so it has no log of its decisions"),
+            WaveError::RulesetMismatch { expected, found } => write!(
+                f,
+                "The log was made with different rules ({:016x}) than the wave has ({:016x})",
+                expected, found
+            ),
+            WaveError::InvalidLogStep { step } => write!(
+                f,
+                "Step {} of the log can't be replayed on the wave",
+                step
+            ),
+            WaveError::InvalidRuleset { reason } => write!(f, "The ruleset is invalid: {}", reason),
+            WaveError::UnknownTile { name, orientation } => write!(
+                f,
+                "The tileset has no tile {} with orientation {}",
+                name, orientation
+            ),
+            WaveError::InvalidTileWeight { name } => write!(
+                f,
+                "The weight of tile {} must be a positive, finite number",
+                name
+            ),
+            WaveError::UnknownNode { node } => write!(f, "The graph has no node {}", node),
+            WaveError::TooManyLabels => write!(f, "The graph can't have more than 256 labels"),
+            WaveError::UnknownLabel { label } => write!(f, "The graph has no label {}", label),
+            WaveError::UnknownValue { id } => write!(f, "The graph model has no value {}", id),
+            WaveError::InvalidValueWeight { id } => write!(
+                f,
+                "The weight of value {} must be a positive, finite number",
+                id
+            ),
+        }
+    }
+}
+
+impl std::error::Error for WaveError {}
+
+struct Position<'a>(&'a Vector3<usize>);
+
+impl fmt::Display for Position<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.0.z {
+            0 => write!(f, "({}, {})", self.0.x, self.0.y),
+            z => write!(f, "({}, {}, {})", self.0.x, self.0.y, z),
+        }
+    }
+}
+
+struct Size<'a>(&'a Vector3<usize>);
+
+impl fmt::Display for Size<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.0.z {
+            1 => write!(f, "{}x{}", self.0.x, self.0.y),
+            z => write!(f, "{}x{}x{}", self.0.x, self.0.y, z),
+        }
+    }
+}
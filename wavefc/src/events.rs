@@ -0,0 +1,109 @@
+use crate::{CollapseRun, CollapseStats, Wave, WaveError};
+use cgmath::Vector3;
+use std::hash::Hash;
+
+/// Something which happened while collapsing a `Wave`, as yielded by `Wave::steps`.
+///
+/// Positions are those of the wave's elements, where each element covers one chunk of the output. Their z is always 0 unless the wave was filled with a volume. Values are pattern ids, and the sample values they stand for can be found with `Wave::pattern_contents`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CollapseEvent {
+    /// An element was collapsed to a single pattern.
+    Observed {
+        position: Vector3<usize>,
+        pattern: usize,
+    },
+    /// Patterns were removed from an element, either while propagating or by a constraint.
+    Pruned {
+        position: Vector3<usize>,
+        removed: Vec<usize>,
+    },
+    /// An element ran out of patterns.
+    Contradiction { position: Vector3<usize> },
+    /// The most recent collapse that could be undone was rolled back, and the pattern it chose was banned from its element.
+    ///
+    /// The elements are restored to how they were before that collapse, so anything shown should be redrawn from `Wave::current_rep`.
+    Backtracked {
+        position: Vector3<usize>,
+        banned: usize,
+    },
+    /// The wave was filled again after a contradiction, and is starting over.
+    Restarted,
+    /// Every element has been collapsed. This is always the last event.
+    Completed(CollapseStats),
+}
+
+/// An iterator over the events of collapsing a `Wave`, created with `Wave::steps`.
+///
+/// Each collapse (and any backtracking or restarting it leads to) happens as the iterator is advanced, and the events that came of it are yielded one at a time. If the wave can't be collapsed or the max number of contradictions is reached, an error is yielded and the iterator ends.
+pub struct Steps<'a, T> {
+    wave: &'a mut Wave<T>,
+    max_contradictions: usize,
+    run: CollapseRun,
+    started: bool,
+    finished: bool,
+    /// An error which is yielded once the events before it have been.
+    error: Option<WaveError>,
+}
+
+impl<'a, T: Eq + Hash + Clone> Steps<'a, T> {
+    pub(crate) fn new(wave: &'a mut Wave<T>, max_contradictions: usize) -> Self {
+        wave.events = Some(Default::default());
+
+        Self {
+            wave,
+            max_contradictions,
+            run: CollapseRun::default(),
+            started: false,
+            finished: false,
+            error: None,
+        }
+    }
+}
+
+impl<T: Eq + Hash + Clone> Iterator for Steps<'_, T> {
+    type Item = Result<CollapseEvent, WaveError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(event) = self.wave.events.as_mut().and_then(|e| e.pop_front()) {
+                return Some(Ok(event));
+            }
+
+            if let Some(error) = self.error.take() {
+                return Some(Err(error));
+            }
+
+            if self.finished {
+                return None;
+            }
+
+            if !self.started {
+                self.started = true;
+
+                if let Err(e) = self.wave.check_collapsible() {
+                    self.finished = true;
+                    return Some(Err(e));
+                }
+            }
+
+            if self.wave.completely_collapsed() {
+                self.finished = true;
+                let stats = self.run.finish(self.wave.iterations);
+                self.wave.emit(CollapseEvent::Completed(stats));
+                continue;
+            }
+
+            if let Err(e) = self.wave.advance(&mut self.run, self.max_contradictions) {
+                self.finished = true;
+                self.error = Some(e);
+            }
+        }
+    }
+}
+
+impl<T> Drop for Steps<'_, T> {
+    fn drop(&mut self) {
+        // events are only gathered while stepping
+        self.wave.events = None;
+    }
+}
@@ -0,0 +1,278 @@
+use crate::helpers::{volume, BorderMode};
+use crate::topology::Topology;
+use crate::{Symmetry, Wave, WaveError};
+use cgmath::{Vector2, Vector3};
+use std::hash::Hash;
+
+#[cfg(feature = "serde")]
+use serde::{de::Error as _, Deserialize, Serialize};
+
+/// The number of labels a graph can have, as they're numbered with a `u8`.
+const MAX_LABELS: usize = u8::MAX as usize + 1;
+
+/// An arbitrary graph of nodes joined by labelled edges, such as the regions of a Voronoi diagram or the rooms of a dungeon.
+///
+/// Labels take the place of directions on a grid. Each one has an opposite, which is the label of the same edge seen from the other end. A label can be its own opposite for edges with no sense of direction, or come in a pair like north and south.
+///
+/// As a `Topology`, the wave has one element per node, where node `n` is at the position `(n, 0, 0)`. Graphs have no symmetries, and aren't learned from samples, so their values and which of them can be joined by each label are declared with a `GraphModel`.
+#[derive(Debug, Clone, Default)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct Graph {
+    /// The opposite of each label.
+    opposites: Vec<u8>,
+    /// The edges leaving each node, as their label and the node they lead to.
+    edges: Vec<Vec<(u8, usize)>>,
+}
+
+impl Graph {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a node without any edges, returning its index.
+    pub fn add_node(&mut self) -> usize {
+        self.edges.push(vec![]);
+        self.edges.len() - 1
+    }
+
+    /// Adds a label which is its own opposite, for edges which look the same from either end.
+    ///
+    /// An error is returned if the graph already has the 256 labels it can have.
+    pub fn add_label(&mut self) -> Result<u8, WaveError> {
+        if self.opposites.len() >= MAX_LABELS {
+            return Err(WaveError::TooManyLabels);
+        }
+
+        let label = self.opposites.len() as u8;
+        self.opposites.push(label);
+
+        Ok(label)
+    }
+
+    /// Adds two labels which are each other's opposite, for edges with a direction to them.
+    ///
+    /// An error is returned if the graph doesn't have room for two more of the 256 labels it can have.
+    pub fn add_label_pair(&mut self) -> Result<(u8, u8), WaveError> {
+        if self.opposites.len() + 2 > MAX_LABELS {
+            return Err(WaveError::TooManyLabels);
+        }
+
+        let label = self.opposites.len() as u8;
+        self.opposites.extend([label + 1, label]);
+
+        Ok((label, label + 1))
+    }
+
+    /// Joins two nodes with an edge, where `to` lies across `label` from `from`, and `from` lies across the opposite label from `to`.
+    ///
+    /// An error is returned if either node or the label hasn't been added.
+    pub fn connect(&mut self, from: usize, to: usize, label: u8) -> Result<(), WaveError> {
+        if let Some(&node) = [from, to].iter().find(|&&n| n >= self.edges.len()) {
+            return Err(WaveError::UnknownNode { node });
+        }
+
+        let opposite = self.opposite_label(label)?;
+        self.edges[from].push((label, to));
+        self.edges[to].push((opposite, from));
+
+        Ok(())
+    }
+
+    /// The edges leaving the node, as their label and the node they lead to.
+    pub fn edges(&self, node: usize) -> &[(u8, usize)] {
+        &self.edges[node]
+    }
+
+    /// The number of nodes.
+    pub fn len(&self) -> usize {
+        self.edges.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.edges.is_empty()
+    }
+
+    /// The number of labels.
+    pub fn labels(&self) -> usize {
+        self.opposites.len()
+    }
+
+    fn opposite_label(&self, label: u8) -> Result<u8, WaveError> {
+        self.opposites
+            .get(label as usize)
+            .copied()
+            .ok_or(WaveError::UnknownLabel { label })
+    }
+}
+
+/// The fields of a serialized graph, which are checked for consistency before they become a `Graph`.
+#[cfg(feature = "serde")]
+#[derive(Deserialize)]
+#[serde(rename = "Graph")]
+struct GraphFields {
+    opposites: Vec<u8>,
+    edges: Vec<Vec<(u8, usize)>>,
+}
+
+#[cfg(feature = "serde")]
+impl<'de> Deserialize<'de> for Graph {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        let fields = GraphFields::deserialize(deserializer)?;
+        let labels = fields.opposites.len();
+
+        if labels > MAX_LABELS {
+            return Err(D::Error::custom(format!(
+                "the graph has {} labels, but it can have at most {}",
+                labels, MAX_LABELS
+            )));
+        }
+
+        // every label must have an opposite whose opposite is the label again
+        let paired = fields.opposites.iter().enumerate().all(|(label, &opposite)| {
+            fields.opposites.get(opposite as usize) == Some(&(label as u8))
+        });
+
+        if !paired {
+            return Err(D::Error::custom(
+                "the graph has a label whose opposite doesn't lead back to it",
+            ));
+        }
+
+        for (node, edges) in fields.edges.iter().enumerate() {
+            for &(label, target) in edges {
+                if label as usize >= labels {
+                    return Err(D::Error::custom(format!(
+                        "node {} has an edge with label {}, which isn't in the graph",
+                        node, label
+                    )));
+                }
+
+                if target >= fields.edges.len() {
+                    return Err(D::Error::custom(format!(
+                        "node {} has an edge to node {}, which isn't in the graph",
+                        node, target
+                    )));
+                }
+            }
+        }
+
+        Ok(Graph {
+            opposites: fields.opposites,
+            edges: fields.edges,
+        })
+    }
+}
+
+impl Topology for Graph {
+    fn directions(&self) -> usize {
+        self.opposites.len()
+    }
+
+    fn opposite(&self, direction: u8) -> u8 {
+        self.opposites[direction as usize]
+    }
+
+    fn transform(&self, direction: u8, _mirror: bool, _rotations: usize) -> u8 {
+        direction
+    }
+
+    fn transforms(&self, _symmetry: Symmetry) -> Vec<(bool, usize)> {
+        vec![]
+    }
+
+    fn neighbours(
+        &self,
+        position: Vector3<usize>,
+        _size: Vector3<usize>,
+        _periodic: Vector2<bool>,
+    ) -> Vec<(u8, Vector3<usize>)> {
+        self.edges[position.x]
+            .iter()
+            .map(|&(label, node)| (label, Vector3::new(node, 0, 0)))
+            .collect()
+    }
+
+    fn adjacencies(
+        &self,
+        _sample: Vec<Vec<usize>>,
+        _chunk_size: Vector2<usize>,
+        _border_mode: BorderMode,
+    ) -> Vec<volume::Adjacency<usize>> {
+        vec![]
+    }
+}
+
+/// The values a `Graph` can be collapsed into, and which of them can be joined by each label.
+///
+/// Values are chosen in proportion to their weights, and each allowed pair also allows the reverse pair across the opposite label. Once it's set up, `wave` creates a `Wave` with an element for every node, which is collapsed as usual.
+#[derive(Debug, Clone)]
+pub struct GraphModel<T> {
+    graph: Graph,
+    values: Vec<T>,
+    weights: Vec<f64>,
+    /// Triples of a value, a label and the value which can lie across that label from it, as indexes into the values.
+    rules: Vec<(usize, u8, usize)>,
+}
+
+impl<T: Eq + Hash + Clone> GraphModel<T> {
+    pub fn new(graph: Graph) -> Self {
+        Self {
+            graph,
+            values: vec![],
+            weights: vec![],
+            rules: vec![],
+        }
+    }
+
+    pub fn graph(&self) -> &Graph {
+        &self.graph
+    }
+
+    /// Adds a value, returning its index. Adding a value which is already there replaces its weight, which must be positive and finite.
+    pub fn add_value(&mut self, value: T, weight: f64) -> usize {
+        if let Some(index) = self.values.iter().position(|v| *v == value) {
+            self.weights[index] = weight;
+            return index;
+        }
+
+        self.values.push(value);
+        self.weights.push(weight);
+
+        self.values.len() - 1
+    }
+
+    /// Allows the value at index `neighbour` to lie across `label` from the value at index `value`.
+    ///
+    /// An error is returned if either value or the label hasn't been added.
+    pub fn allow(&mut self, value: usize, label: u8, neighbour: usize) -> Result<(), WaveError> {
+        if let Some(&id) = [value, neighbour].iter().find(|&&v| v >= self.values.len()) {
+            return Err(WaveError::UnknownValue { id });
+        }
+
+        self.graph.opposite_label(label)?;
+        self.rules.push((value, label, neighbour));
+
+        Ok(())
+    }
+
+    /// Creates a wave on the graph with the declared values and rules, already filled with an element for every node.
+    ///
+    /// An error is returned if a value's weight isn't positive and finite.
+    pub fn wave(&self) -> Result<Wave<T>, WaveError> {
+        if let Some(id) = self.weights.iter().position(|w| !(w.is_finite() && *w > 0.)) {
+            return Err(WaveError::InvalidValueWeight { id });
+        }
+
+        let mut wave = Wave::new();
+        wave.set_topology(self.graph.clone());
+        wave.load_tiles(self.values.clone(), &self.weights, &self.rules);
+
+        // the graph only has the one axis, as every node is its own element
+        wave.fill_volume(Vector3::new(self.graph.len(), 1, 1))?;
+
+        Ok(wave)
+    }
+}
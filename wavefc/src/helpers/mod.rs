@@ -1,14 +1,15 @@
 #[cfg(test)]
 mod tests;
+pub mod volume;
 
 extern crate cgmath;
 use cgmath::Vector2;
 
 /// This function finds the width and height of the given 2D array.
-pub fn dimensions_of<T>(input: &Vec<Vec<T>>) -> Vector2<usize> {
+pub fn dimensions_of<T>(input: &[Vec<T>]) -> Vector2<usize> {
     Vector2::new(
         input.len(),
-        if input.len() > 0 { input[0].len() } else { 0 },
+        if !input.is_empty() { input[0].len() } else { 0 },
     )
 }
 
@@ -22,29 +23,26 @@ pub fn roll<T>(input: &mut Vec<Vec<T>>, shifts: usize, x_axis: bool, y_axis: boo
 where
     T: Clone,
 {
-    let input_size = dimensions_of(&input);
+    let input_size = dimensions_of(input);
 
     for _ in 0..shifts {
         let input_copy = input.to_owned();
 
         for (i_r, row) in input.iter_mut().enumerate() {
             for (i_c, element) in row.iter_mut().enumerate() {
-                let s_r: usize;
-                let s_c: usize;
-
                 // row
-                if y_axis {
-                    s_r = if i_r == 0 { input_size.y - 1 } else { i_r - 1 };
+                let s_r = if y_axis {
+                    if i_r == 0 { input_size.y - 1 } else { i_r - 1 }
                 } else {
-                    s_r = i_r;
-                }
+                    i_r
+                };
 
                 // column
-                if x_axis {
-                    s_c = if i_c == 0 { input_size.x - 1 } else { i_c - 1 };
+                let s_c = if x_axis {
+                    if i_c == 0 { input_size.x - 1 } else { i_c - 1 }
                 } else {
-                    s_c = i_c;
-                }
+                    i_c
+                };
 
                 let swap_element = input_copy[s_r][s_c].clone();
                 *element = swap_element;
@@ -53,7 +51,7 @@ where
     }
 }
 
-/// Returns all the possible shifted possibilities of the given 2D array.
+// /// Returns all the possible shifted possibilities of the given 2D array.
 // pub fn all_possible_shifts<T>(input: Vec<Vec<T>>) -> Vec<Vec<Vec<T>>>
 // where
 // T: Clone,
@@ -89,10 +87,10 @@ pub fn arrayify<T>(input: Vec<(T, Vector2<usize>)>, size: &Vector2<usize>) -> Ve
 
     for i in 0..size.x {
         formatted.push(vec![]);
-        formatted[i as usize].reserve(size.y.into());
+        formatted[i].reserve(size.y);
     }
 
-    debug_assert_eq!(formatted.len() as usize, size.x);
+    debug_assert_eq!(formatted.len(), size.x);
 
     for element in input {
         let loc = element.1;
@@ -101,29 +99,29 @@ pub fn arrayify<T>(input: Vec<(T, Vector2<usize>)>, size: &Vector2<usize>) -> Ve
         debug_assert_eq!(
             {
                 formatted
-                    .get(loc.x as usize)
-                    .map(|row| row.get(loc.y as usize))
+                    .get(loc.x)
+                    .map(|row| row.get(loc.y))
                     .unwrap_or(None)
                     .map(|_t| false)
             },
             None
         );
 
-        formatted[loc.x as usize].push(element.0);
+        formatted[loc.x].push(element.0);
     }
 
     formatted
 }
 
 /// Various modes for analyzing adjacencies at the border of a 2d array.
-#[derive(PartialEq)]
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
 pub enum BorderMode {
     /// Don't include border chunks.
     Exclude,
     /// Include border chunks and their neighbours.
     Clamp,
-    // /// Include border chunks and all their neighbours wrapping across the input.
-    // Wrap,
+    /// Include border chunks and all their neighbours wrapping across the input, treating it as a seamless (toroidal) texture.
+    Wrap,
 }
 
 /// Adjacency information and data about a given chunk.
@@ -174,11 +172,11 @@ impl<T> Adjacency<T> {
 // vec![]
 // }
 
-/// Converts the 2-dimensional array into chunked, square patterns of the specified size.
-///
-/// # Arguments
-///
-/// If `allow_slims` is false, the function will panic if the input's size is not a factor of `n_size`.
+// /// Converts the 2-dimensional array into chunked, square patterns of the specified size.
+// ///
+// /// # Arguments
+// ///
+// /// If `allow_slims` is false, the function will panic if the input's size is not a factor of `n_size`.
 // fn chunkify<T>(
 // input: Vec<Vec<T>>,
 // chunk_size: Vector2<usize>,
@@ -265,16 +263,20 @@ where
             let point = Vector2::new(x, y);
             let edge = point + chunk_size - Vector2::new(1, 1);
 
-            // check if the chunk exists and is in bounds
-            if edge.x > size_indexed.x || edge.y > size_indexed.y {
-                continue;
-            }
+            let content = if border_mode == BorderMode::Wrap {
+                // every point has a chunk, which might continue across the edges of the input
+                wrapped_chunk(&input, &chunk_points, point.cast::<isize>().unwrap(), size)
+            } else {
+                // check if the chunk exists and is in bounds
+                if edge.x > size_indexed.x || edge.y > size_indexed.y {
+                    continue;
+                }
 
-            let content = chunk_points
-                .to_owned()
-                .into_iter()
-                .map(|v| (input[v.x + x][v.y + y].to_owned(), v))
-                .collect();
+                chunk_points
+                    .iter()
+                    .map(|&v| (input[v.x + x][v.y + y].to_owned(), v))
+                    .collect()
+            };
             let arr = arrayify(content, &chunk_size);
             let mut adjacency = Adjacency::new(arr);
 
@@ -300,8 +302,7 @@ where
 
             let mut scrap_chunk = false;
 
-            for i in 0..4 {
-                let origin = adjac_origins[i];
+            for (i, origin) in adjac_origins.into_iter().enumerate() {
                 let chunk_size_i = chunk_size.cast::<isize>().unwrap();
                 let org_edge = origin + chunk_size_i - Vector2::new(1, 1);
 
@@ -310,50 +311,16 @@ where
                 let bottom_right_o = origin.x > size_indexed_i.x - chunk_size_i.x
                     || origin.y > size_indexed_i.y - chunk_size_i.y;
 
-                if top_left_e || bottom_right_o {
-                    if border_mode == BorderMode::Exclude {
-                        // don't include this chunk at all
-                        scrap_chunk = true;
-                        break;
-                    }
-
-                    // if border_mode == BorderMode::Wrap {
-                    // let mut content: Vec<(T, Vector2<usize>)> = vec![];
-
-                    // for iy in 0..chunk_size.y {
-                    // for ix in 0..chunk_size.x {
-                    // let sum = origin + Vector2::new(ix as isize, iy as isize);
-                    // let index: Vector2<usize>;
-
-                    // if sum.x < 0 || sum.y < 0 {
-                    // index = Vector2::new(
-                    // size_indexed_i.x - sum.x,
-                    // size_indexed_i.y - sum.y,
-                    // )
-                    // .cast::<usize>()
-                    // .unwrap();
-                    // } else if sum.x > size_indexed_i.x || sum.y > size_indexed_i.y {
-                    // index = Vector2::new(
-                    // sum.x - size_indexed_i.x,
-                    // sum.y - size_indexed_i.y,
-                    // )
-                    // .cast::<usize>()
-                    // .unwrap();
-                    // } else {
-                    // index = Vector2::new(ix, iy).cast::<usize>().unwrap();
-                    // }
-
-                    // content.push((input[index.x][index.y].to_owned(), index));
-                    // }
-                    // }
-
-                    // debug_assert_eq!(content.len(), chunk_size.x * chunk_size.y);
-
-                    // let formatted = arrayify(content, &chunk_size);
-                    // adjacency.neighbours[i] = Some(formatted);
-
-                    // continue;
-                    // }
+                if (top_left_e || bottom_right_o) && border_mode == BorderMode::Exclude {
+                    // don't include this chunk at all
+                    scrap_chunk = true;
+                    break;
+                }
+
+                if border_mode == BorderMode::Wrap {
+                    let content = wrapped_chunk(&input, &chunk_points, origin, size);
+                    adjacency.neighbours_content[i] = Some(arrayify(content, &chunk_size));
+                    continue;
                 }
 
                 let origin_invalid = origin.x < 0
@@ -373,9 +340,8 @@ where
 
                 // BorderMode::Clamp
                 let content: Vec<(T, Vector2<usize>)> = chunk_points
-                    .to_owned()
-                    .into_iter()
-                    .map(|v| {
+                    .iter()
+                    .map(|&v| {
                         (
                             input[v.x + origin.x as usize][v.y + origin.y as usize].to_owned(),
                             v,
@@ -397,24 +363,49 @@ where
     list
 }
 
-// pub fn rotate_ninety<T>(mut input: Vec<Vec<T>>, count: usize) -> Vec<Vec<T>>
-// where
-// T: Clone,
-// {
-// for _ in 0..count {
-// for i in 0..input.len() {
-// for j in 0..i {
-// let tmp = input[i][j].to_owned();
-// input[i][j] = input[j][i].to_owned();
-// input[j][i] = tmp;
-// }
-// }
+/// Collects the contents of the chunk at `origin`, wrapping any points outside the input around to the other side.
+fn wrapped_chunk<T>(
+    input: &[Vec<T>],
+    chunk_points: &[Vector2<usize>],
+    origin: Vector2<isize>,
+    size: Vector2<usize>,
+) -> Vec<(T, Vector2<usize>)>
+where
+    T: Clone,
+{
+    chunk_points
+        .iter()
+        .map(|&v| {
+            let x = (origin.x + v.x as isize).rem_euclid(size.x as isize) as usize;
+            let y = (origin.y + v.y as isize).rem_euclid(size.y as isize) as usize;
+
+            (input[x][y].to_owned(), v)
+        })
+        .collect()
+}
 
-// input.reverse();
-// }
+/// Rotates the 2d array by 90° `count` times.
+///
+/// With the array indexed as `[x][y]`, each rotation moves the value at `(x, y)` to `(height - 1 - y, x)`. The width and height are swapped for arrays that aren't square.
+pub fn rotate_ninety<T>(mut input: Vec<Vec<T>>, count: usize) -> Vec<Vec<T>>
+where
+    T: Clone,
+{
+    for _ in 0..count % 4 {
+        let width = input.len();
+        let height = input.first().map_or(0, |c| c.len());
+
+        input = (0..height)
+            .map(|x| {
+                (0..width)
+                    .map(|y| input[y][height - 1 - x].to_owned())
+                    .collect()
+            })
+            .collect();
+    }
 
-// input
-// }
+    input
+}
 
 pub fn xy_swap<T>(input: Vec<Vec<T>>) -> Vec<Vec<T>>
 where
@@ -425,9 +416,9 @@ where
     let rlen = input.len();
     let clen = input[0].len();
 
-    for r in 0..rlen {
-        for c in 0..clen {
-            list.push((input[r][c].to_owned(), Vector2::new(c, r)));
+    for (r, row) in input.iter().enumerate() {
+        for (c, element) in row.iter().enumerate() {
+            list.push((element.to_owned(), Vector2::new(c, r)));
         }
     }
 
@@ -466,11 +457,8 @@ pub fn noneg_neighbours(origin: &Vector2<usize>) -> Vec<Vector2<usize>> {
 }
 
 pub fn remove_indexes<T>(vec: &mut Vec<T>, indexes: Vec<usize>) {
-    let mut removed = 0usize;
-
-    for i in indexes {
+    for (removed, i) in indexes.into_iter().enumerate() {
         vec.remove(i - removed);
-        removed += 1;
     }
 }
 
@@ -483,9 +471,18 @@ pub fn orthog_direction(origin: &Vector2<usize>, point: &Vector2<usize>) -> u8 {
         1
     } else if diff.y < 0 {
         2
-    } else if diff.y > 0 {
-        0
     } else {
+        // covers both a positive y difference and no difference at all
         0
     }
 }
+
+/// The direction opposite to the one given, using the same numbering as `orthog_direction` (and `volume::orthog_direction` for the two directions along the z axis).
+pub fn opposite_direction(direction: u8) -> u8 {
+    if direction < 4 {
+        (direction + 2) % 4
+    } else {
+        // 4 and 5 swap
+        direction ^ 1
+    }
+}
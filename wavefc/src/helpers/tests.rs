@@ -1,4 +1,5 @@
 use super::*;
+use cgmath::Vector3;
 
 #[test]
 fn arrayify_works() {
@@ -117,63 +118,80 @@ fn overlapping_adjacencies_works() {
     assert_eq!(adjac.len(), 1);
 }
 
-// #[test]
-// fn rotate_ninety_works() {
-// let i0 = vec![
-// vec![0, 1],
-// vec![2, 3],
-// ];
-
-// let e0 = vec![
-// vec![1, 3],
-// vec![0, 2],
-// ];
-
-// let r0 = rotate_ninety(i0.to_owned(), 1);
-// assert_ne!(r0, i0);
-// assert_eq!(r0, e0);
-
-// let i1 = vec![
-// vec![0, 1, 0],
-// vec![1, 0, 1],
-// vec![0, 0, 0],
-// ];
-
-// let e1 = vec![
-// vec![0, 1, 0],
-// vec![1, 0, 0],
-// vec![0, 1, 0],
-// ];
-
-// let r1 = rotate_ninety(i1.to_owned(), 1);
-// assert_ne!(r1, i1);
-// assert_eq!(r1, e1);
-
-// let i2 = vec![
-// vec![0, 1, 0, 1],
-// vec![1, 0, 1, 0],
-// vec![0, 0, 0, 0],
-// vec![1, 1, 1, 1],
-// ];
-
-// let e2 = vec![
-// vec![1, 0, 0, 1],
-// vec![0, 1, 0, 1],
-// vec![1, 0, 0, 1],
-// vec![0, 1, 0, 1],
-// ];
-
-// let r2 = rotate_ninety(i2.to_owned(), 1);
-// assert_ne!(r2, i2);
-// assert_eq!(r2, e2);
-
-// let i3 = vec![
-// vec![1],
-// ];
-
-// let r3 = rotate_ninety(i3.to_owned(), 1);
-// assert_eq!(i3, r3);
-// }
+#[test]
+fn overlapping_adjacencies_wrap() {
+    let input = vec![
+        vec![0, 1, 1, 2],
+        vec![0, 0, 1, 1],
+        vec![1, 0, 0, 0],
+        vec![1, 1, 1, 1],
+    ];
+
+    let formatted_input = xy_swap(input);
+    let result = overlapping_adjacencies(formatted_input, Vector2::new(2, 2), BorderMode::Wrap);
+
+    // every point has a chunk, and every chunk has all of its neighbours
+    assert_eq!(result.len(), 16);
+    assert!(result
+        .iter()
+        .all(|a| a.neighbours_content.iter().all(|n| n.is_some())));
+
+    // the left neighbour of the first chunk wraps around to the right side
+    let left = xy_swap(result[0].neighbours_content[3].to_owned().unwrap());
+    assert_eq!(left, vec![vec![1, 2], vec![1, 1]]);
+
+    // the chunk at x = 3 continues over to the first column
+    let origin = xy_swap(result[12].origin_content.to_owned());
+    assert_eq!(origin, vec![vec![2, 0], vec![1, 0]]);
+}
+
+#[test]
+fn rotate_ninety_works() {
+    let i0 = vec![vec![0, 1], vec![2, 3]];
+    let e0 = vec![vec![1, 3], vec![0, 2]];
+
+    let r0 = rotate_ninety(i0.to_owned(), 1);
+    assert_ne!(r0, i0);
+    assert_eq!(r0, e0);
+
+    let i1 = vec![vec![0, 1, 0], vec![1, 0, 1], vec![0, 0, 0]];
+    let e1 = vec![vec![0, 1, 0], vec![1, 0, 0], vec![0, 1, 0]];
+
+    let r1 = rotate_ninety(i1.to_owned(), 1);
+    assert_ne!(r1, i1);
+    assert_eq!(r1, e1);
+
+    let i2 = vec![
+        vec![0, 1, 0, 1],
+        vec![1, 0, 1, 0],
+        vec![0, 0, 0, 0],
+        vec![1, 1, 1, 1],
+    ];
+
+    let e2 = vec![
+        vec![1, 0, 0, 1],
+        vec![0, 1, 0, 1],
+        vec![1, 0, 0, 1],
+        vec![0, 1, 0, 1],
+    ];
+
+    let r2 = rotate_ninety(i2.to_owned(), 1);
+    assert_ne!(r2, i2);
+    assert_eq!(r2, e2);
+
+    let i3 = vec![vec![1]];
+
+    let r3 = rotate_ninety(i3.to_owned(), 1);
+    assert_eq!(i3, r3);
+
+    // four rotations bring the array back around, and rectangles swap their width and height
+    let i4 = vec![vec![0, 1, 2], vec![3, 4, 5]];
+    assert_eq!(rotate_ninety(i4.to_owned(), 4), i4);
+    assert_eq!(
+        rotate_ninety(i4.to_owned(), 1),
+        vec![vec![2, 5], vec![1, 4], vec![0, 3]]
+    );
+}
 
 #[test]
 fn neighbours_works() {
@@ -191,3 +209,53 @@ fn remove_indexes_works() {
     remove_indexes(&mut input, to_remove);
     assert_eq!(input, vec![10, 20, 30]);
 }
+
+#[test]
+fn volume_overlapping_adjacencies_work() {
+    // indexed as [x][y][z], with 0 to 7 numbering the points of a 2x2x2 cube
+    let input = vec![vec![vec![0, 1], vec![2, 3]], vec![vec![4, 5], vec![6, 7]]];
+
+    let adjacencies =
+        volume::overlapping_adjacencies(input.to_owned(), Vector3::new(1, 1, 1), BorderMode::Clamp);
+    assert_eq!(adjacencies.len(), 8);
+
+    let origin = &adjacencies[0];
+    assert_eq!(origin.origin_content, vec![vec![vec![0]]]);
+    assert_eq!(
+        origin.neighbours_content,
+        [
+            Some(vec![vec![vec![2]]]),
+            Some(vec![vec![vec![4]]]),
+            None,
+            None,
+            Some(vec![vec![vec![1]]]),
+            None,
+        ]
+    );
+
+    let excluded = volume::overlapping_adjacencies(
+        input.to_owned(),
+        Vector3::new(1, 1, 1),
+        BorderMode::Exclude,
+    );
+    assert!(excluded.is_empty());
+
+    let wrapped = volume::overlapping_adjacencies(input, Vector3::new(2, 2, 1), BorderMode::Wrap);
+    assert_eq!(wrapped.len(), 8);
+    assert_eq!(
+        wrapped[1].neighbours_content[4],
+        Some(vec![vec![vec![0], vec![2]], vec![vec![4], vec![6]]])
+    );
+
+    assert_eq!(
+        volume::noneg_neighbours(&Vector3::new(0, 0, 1)),
+        vec![
+            Vector3::new(1, 0, 1),
+            Vector3::new(0, 1, 1),
+            Vector3::new(0, 0, 0),
+            Vector3::new(0, 0, 2),
+        ]
+    );
+    assert_eq!(opposite_direction(4), 5);
+    assert_eq!(opposite_direction(1), 3);
+}
@@ -0,0 +1,219 @@
+//! The helpers of the parent module, generalized to three dimensions for voxel samples.
+//!
+//! Volumes are indexed as `[x][y][z]`. The first four directions are the same as in two dimensions, and the z axis adds two more: 4 for adding z and 5 for subtracting z.
+
+use super::BorderMode;
+use cgmath::{Vector2, Vector3};
+
+/// The offset to the neighbour in each direction: up (adding y), right (adding x), down (subtracting y), left (subtracting x), adding z and subtracting z.
+pub const DIRECTION_OFFSETS: [(isize, isize, isize); 6] = [
+    (0, 1, 0),
+    (1, 0, 0),
+    (0, -1, 0),
+    (-1, 0, 0),
+    (0, 0, 1),
+    (0, 0, -1),
+];
+
+/// This function finds the width, height and depth of the given 3D array.
+pub fn dimensions_of<T>(input: &[Vec<Vec<T>>]) -> Vector3<usize> {
+    let height = input.first().map_or(0, |c| c.len());
+    let depth = input.first().and_then(|c| c.first()).map_or(0, |c| c.len());
+
+    Vector3::new(input.len(), height, depth)
+}
+
+/// Converts the given flat vector of `T` and `Vector3` pairs into a 3-dimensional vector of just `T`.
+///
+/// Every position within `size` should be given exactly once. Duplicate positions are only checked for in debug mode.
+pub fn arrayify<T>(mut input: Vec<(T, Vector3<usize>)>, size: &Vector3<usize>) -> Vec<Vec<Vec<T>>> {
+    input.sort_by_key(|(_, p)| (p.x, p.y, p.z));
+    debug_assert!(input.windows(2).all(|w| w[0].1 != w[1].1));
+
+    let mut formatted: Vec<Vec<Vec<T>>> = (0..size.x)
+        .map(|_| (0..size.y).map(|_| Vec::with_capacity(size.z)).collect())
+        .collect();
+
+    for (value, loc) in input {
+        formatted[loc.x][loc.y].push(value);
+    }
+
+    formatted
+}
+
+/// Adjacency information and data about a given chunk of a volume.
+#[derive(Debug)]
+pub struct Adjacency<T> {
+    pub origin_content: Vec<Vec<Vec<T>>>,
+    /// The neighbouring chunks, in the order of `DIRECTION_OFFSETS`.
+    pub neighbours_content: [Option<Vec<Vec<Vec<T>>>>; 6],
+}
+
+impl<T> Adjacency<T> {
+    pub fn new(origin: Vec<Vec<Vec<T>>>) -> Self {
+        Self {
+            origin_content: origin,
+            neighbours_content: Default::default(),
+        }
+    }
+}
+
+/// A planar adjacency is a volume adjacency a single layer deep, without any neighbours along the z axis.
+impl<T> From<super::Adjacency<T>> for Adjacency<T> {
+    fn from(adjacency: super::Adjacency<T>) -> Self {
+        let lift = |grid: Vec<Vec<T>>| -> Vec<Vec<Vec<T>>> {
+            grid.into_iter()
+                .map(|column| column.into_iter().map(|v| vec![v]).collect())
+                .collect()
+        };
+        let [up, right, down, left] = adjacency.neighbours_content;
+
+        Self {
+            origin_content: lift(adjacency.origin_content),
+            neighbours_content: [
+                up.map(lift),
+                right.map(lift),
+                down.map(lift),
+                left.map(lift),
+                None,
+                None,
+            ],
+        }
+    }
+}
+
+/// This function finds all possible boxes of the specified `chunk_size` in the input, and then finds the boxes next to them along each axis (if they exist).
+///
+/// With `BorderMode::Exclude`, only the chunks whose six neighbours all lie within the input are kept, so the input has to be larger than the chunk along every axis for it to find anything.
+pub fn overlapping_adjacencies<T>(
+    input: Vec<Vec<Vec<T>>>,
+    chunk_size: Vector3<usize>,
+    border_mode: BorderMode,
+) -> Vec<Adjacency<T>>
+where
+    T: Clone,
+{
+    if chunk_size.x < 1 || chunk_size.y < 1 || chunk_size.z < 1 {
+        return vec![];
+    }
+
+    let size = dimensions_of(&input);
+    let wrap = border_mode == BorderMode::Wrap;
+    let chunk_size_i = chunk_size.cast::<isize>().unwrap();
+    let mut list = vec![];
+
+    for x in 0..size.x {
+        for y in 0..size.y {
+            for z in 0..size.z {
+                let point = Vector3::new(x, y, z).cast::<isize>().unwrap();
+
+                let Some(content) = chunk_at(&input, point, chunk_size, size, wrap) else {
+                    continue;
+                };
+
+                let mut adjacency = Adjacency::new(content);
+                let mut scrap_chunk = false;
+
+                for (i, (dx, dy, dz)) in DIRECTION_OFFSETS.into_iter().enumerate() {
+                    let origin = Vector3::new(
+                        point.x + dx * chunk_size_i.x,
+                        point.y + dy * chunk_size_i.y,
+                        point.z + dz * chunk_size_i.z,
+                    );
+
+                    match chunk_at(&input, origin, chunk_size, size, wrap) {
+                        Some(neighbour) => adjacency.neighbours_content[i] = Some(neighbour),
+                        None if border_mode == BorderMode::Exclude => {
+                            scrap_chunk = true;
+                            break;
+                        }
+                        None => {}
+                    }
+                }
+
+                if !scrap_chunk {
+                    list.push(adjacency);
+                }
+            }
+        }
+    }
+
+    list
+}
+
+/// Collects the contents of the chunk at `origin`, wrapping around the edges of the input if `wrap` is set. Returns `None` if the chunk doesn't fit in the input otherwise.
+fn chunk_at<T>(
+    input: &[Vec<Vec<T>>],
+    origin: Vector3<isize>,
+    chunk_size: Vector3<usize>,
+    size: Vector3<usize>,
+    wrap: bool,
+) -> Option<Vec<Vec<Vec<T>>>>
+where
+    T: Clone,
+{
+    let fits =
+        |start: isize, length: usize, size: usize| start >= 0 && start as usize + length <= size;
+
+    let in_bounds = fits(origin.x, chunk_size.x, size.x)
+        && fits(origin.y, chunk_size.y, size.y)
+        && fits(origin.z, chunk_size.z, size.z);
+
+    if !wrap && !in_bounds {
+        return None;
+    }
+
+    let axis = |start: isize, offset: usize, size: usize| {
+        (start + offset as isize).rem_euclid(size as isize) as usize
+    };
+
+    Some(
+        (0..chunk_size.x)
+            .map(|cx| {
+                (0..chunk_size.y)
+                    .map(|cy| {
+                        (0..chunk_size.z)
+                            .map(|cz| {
+                                input[axis(origin.x, cx, size.x)][axis(origin.y, cy, size.y)]
+                                    [axis(origin.z, cz, size.z)]
+                                .to_owned()
+                            })
+                            .collect()
+                    })
+                    .collect()
+            })
+            .collect(),
+    )
+}
+
+/// The neighbours of the given point which don't have a negative coordinate, in the same order as the two dimensional `noneg_neighbours`, followed by those along the z axis.
+pub fn noneg_neighbours(origin: &Vector3<usize>) -> Vec<Vector3<usize>> {
+    let cast = origin.cast::<isize>().unwrap();
+    let val = vec![
+        Vector3::new(cast.x, cast.y - 1, cast.z),
+        Vector3::new(cast.x + 1, cast.y, cast.z),
+        Vector3::new(cast.x, cast.y + 1, cast.z),
+        Vector3::new(cast.x - 1, cast.y, cast.z),
+        Vector3::new(cast.x, cast.y, cast.z - 1),
+        Vector3::new(cast.x, cast.y, cast.z + 1),
+    ];
+
+    val.into_iter()
+        .filter(|v| v.x >= 0 && v.y >= 0 && v.z >= 0)
+        .map(|v| v.cast::<usize>().unwrap())
+        .collect()
+}
+
+/// The direction of `point` from `origin`, using the numbering of `DIRECTION_OFFSETS`.
+pub fn orthog_direction(origin: &Vector3<usize>, point: &Vector3<usize>) -> u8 {
+    if point.z > origin.z {
+        4
+    } else if point.z < origin.z {
+        5
+    } else {
+        super::orthog_direction(
+            &Vector2::new(origin.x, origin.y),
+            &Vector2::new(point.x, point.y),
+        )
+    }
+}
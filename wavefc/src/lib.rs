@@ -1,18 +1,46 @@
+mod bitset;
+pub mod chunked;
+mod counts;
+mod error;
+pub mod events;
+pub mod graph;
 pub mod helpers;
+pub mod palette;
 pub mod prelude;
+pub mod replay;
+pub mod ruleset;
 pub mod stringtools;
+pub mod tiled;
+pub mod topology;
 
+pub use chunked::ChunkedGenerator;
+pub use error::WaveError;
+pub use events::{CollapseEvent, Steps};
+pub use graph::{Graph, GraphModel};
 pub use helpers::BorderMode;
-
-use cgmath::Vector2;
+pub use palette::Palette;
+pub use replay::{Decision, GenerationLog};
+pub use ruleset::{RulePattern, Ruleset};
+pub use tiled::{TileSymmetry, TileVariant, TiledModel};
+pub use topology::{Hex, Square, Topology};
+
+use bitset::BitSet;
+use counts::ValueCounts;
+use cgmath::{Vector2, Vector3};
 use helpers::*;
 use rand::prelude::*;
 use rand::thread_rng;
+use rand_chacha::ChaCha12Rng;
 use std::clone::Clone;
+use std::collections::{HashMap, VecDeque};
+use std::hash::Hash;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
+use std::thread;
+use std::time::Instant;
 
 #[cfg(feature = "serde")]
-use serde::{de::Visitor, ser::SerializeStruct, Deserialize, Serialize};
+use serde::{ser::SerializeStruct, Deserialize, Serialize};
 
 #[cfg(test)]
 mod tests;
@@ -22,37 +50,220 @@ mod tests;
 #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub enum Flags {
     NoWeights = 1,
-    NoTransforms,
     NoHistory,
     PruneDeadweight,
 }
 
+/// The strategies available for choosing which element to collapse next.
+#[derive(Debug, Default, PartialEq, Eq, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum SelectionHeuristic {
+    /// Chooses the element with the lowest Shannon entropy over the weights of its remaining patterns. This is the standard heuristic for the algorithm.
+    #[default]
+    MinEntropy,
+    /// Chooses the element with the fewest remaining patterns (minimum remaining values).
+    Mrv,
+    /// Chooses the first uncollapsed element, going column by column through the grid.
+    Scanline,
+    /// Chooses any uncollapsed element at random.
+    Random,
+}
+
+/// The transformations of the sample's patterns which are added to the analysis, as a subgroup of the symmetries of a square (the dihedral group D4).
+///
+/// Rotations by 90° and 270° only apply when the chunk size is square, as a rotated chunk wouldn't fit the grid otherwise.
+#[derive(Debug, Default, PartialEq, Eq, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum Symmetry {
+    /// Only the patterns found in the sample are used.
+    None,
+    /// Patterns are also mirrored horizontally (across the x axis).
+    MirrorX,
+    /// Patterns are also mirrored vertically (across the y axis).
+    MirrorY,
+    /// Patterns are mirrored horizontally, vertically, and both at once (which is the same as rotating them by 180°).
+    #[default]
+    MirrorXY,
+    /// Patterns are also rotated by 90°, 180° and 270°, but never mirrored.
+    Rotations,
+    /// Every rotation and mirroring of the patterns is used.
+    Full,
+}
+
+impl Symmetry {
+    /// The transforms in the group, each as whether the pattern is first mirrored horizontally and then the number of 90° rotations. The identity is left out.
+    fn transforms(&self) -> Vec<(bool, usize)> {
+        match self {
+            Symmetry::None => vec![],
+            Symmetry::MirrorX => vec![(true, 0)],
+            // mirroring horizontally and then rotating by 180° mirrors vertically
+            Symmetry::MirrorY => vec![(true, 2)],
+            Symmetry::MirrorXY => vec![(true, 0), (true, 2), (false, 2)],
+            Symmetry::Rotations => vec![(false, 1), (false, 2), (false, 3)],
+            Symmetry::Full => vec![
+                (false, 1),
+                (false, 2),
+                (false, 3),
+                (true, 0),
+                (true, 1),
+                (true, 2),
+                (true, 3),
+            ],
+        }
+    }
+}
+
+/// Limits for the backtracking solver, set with `Wave::set_backtracking`.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct BacktrackLimits {
+    /// The number of most recent collapses which can be rolled back. Older collapses are committed to.
+    pub max_depth: usize,
+    /// The total number of backtracks allowed during a call to `collapse_all` (or `inpaint` or `steps`). Once they're used up, any further contradictions restart the wave as they would without backtracking.
+    pub max_backtracks: usize,
+}
+
+impl Default for BacktrackLimits {
+    fn default() -> Self {
+        Self {
+            max_depth: 64,
+            max_backtracks: 1000,
+        }
+    }
+}
+
+/// Limits on how long a single call to `Wave::collapse_all`, `Wave::inpaint` or `Wave::steps` can run for, set with `Wave::set_budget`.
+///
+/// The limits are checked between collapses, so once one of them is reached the wave is left consistent and can be collapsed further with another call. By default there are no limits.
+#[derive(Debug, Default, PartialEq, Eq, Clone, Copy)]
+pub struct CollapseBudget {
+    /// The point in time after which no more collapses are made.
+    pub deadline: Option<Instant>,
+    /// The number of collapses which can be made during the call, including any which are later rolled back or lost to a restart.
+    pub max_observations: Option<usize>,
+}
+
+/// Statistics from a successful `Wave::collapse_all` run.
+#[derive(Debug, Default, PartialEq, Eq, Clone, Copy)]
+pub struct CollapseStats {
+    /// The number of collapses made during the final, successful attempt.
+    pub iterations: usize,
+    /// The number of contradictions which caused the wave to restart.
+    pub contradictions: usize,
+    /// The number of collapses which were rolled back by the backtracking solver.
+    pub backtracks: usize,
+}
+
+/// The progress of a `collapse_all` (or `steps`) run between collapses.
+#[derive(Default)]
+struct CollapseRun {
+    stats: CollapseStats,
+    /// Collapses made during the run, counted against the wave's `CollapseBudget`.
+    observations: usize,
+}
+
+impl CollapseRun {
+    fn finish(&self, iterations: usize) -> CollapseStats {
+        CollapseStats {
+            iterations,
+            ..self.stats
+        }
+    }
+}
+
 /// Encapsulation for the Wave Function Collapse implementation.
+///
+/// The wave works with samples of any type of value which can be hashed, such as characters or pixel colours. The values are interned into the wave's `Palette` when analyzing, and patterns are made of their ids from then on.
 #[derive(Clone)]
-pub struct Wave {
+pub struct Wave<T = usize> {
     pub flags: Vec<Flags>,
+    /// The distinct values of the analyzed sample.
+    palette: Palette<T>,
     patterns: Vec<Pattern>,
     patterns_total: usize,
+    /// For each pattern (by id) and direction, the ids of the patterns which are allowed to neighbour it.
+    propagator: Vec<Vec<BitSet>>,
+    /// For each element (by index), its neighbours alongside the direction they lie in. These are looked up whenever an element changes, so they're found once when the wave is filled.
+    links: Vec<Vec<(u8, usize)>>,
+    /// The weights of the patterns (by id), calculated when filling.
+    weights: Vec<PatternWeight>,
+    heuristic: SelectionHeuristic,
+    symmetry: Symmetry,
+    topology: Arc<dyn Topology>,
+    backtracking: Option<BacktrackLimits>,
+    /// Whether the output wraps around on the x and y axes, so opposite edges are constrained against each other.
+    periodic: Vector2<bool>,
+    /// Restrictions on the values of single cells, which are reapplied whenever the wave is filled.
+    constraints: Vec<CellConstraint>,
+    /// Limits on the number of cells of the whole output which can be each value, enforced whenever anything is removed from the elements.
+    count_limits: Vec<CountLimit>,
+    /// The palette id of the value whose count limit can no longer be met, which is a contradiction like an element running out of patterns.
+    broken_count: Option<usize>,
+    /// The cells which are or could still be each limited value, counted when the limits are first enforced and kept up to date as patterns are removed and put back. It's dropped whenever the elements or the limits are replaced, to be counted again.
+    counts: Option<ValueCounts>,
+    /// The events which haven't been yielded yet, only gathered while stepping with `steps`.
+    events: Option<VecDeque<CollapseEvent>>,
+    budget: CollapseBudget,
+    /// A flag which cancels the collapse once it's set, given by the caller with `set_cancel_token`.
+    cancel_token: Option<Arc<AtomicBool>>,
+    /// A flag which is set once a worker of `collapse_parallel` has succeeded, stopping the others.
+    parallel_stop: Option<Arc<AtomicBool>>,
     elements: Vec<Element>,
-    chunk_size: Vector2<usize>,
-    chunk_fill_size: Vector2<usize>,
+    /// The size of the patterns. Their depth is 1 unless a volume was analyzed.
+    chunk_size: Vector3<usize>,
+    chunk_fill_size: Vector3<usize>,
     history: Vec<Record>,
+    /// Patterns banned by backtracking out of the first collapse, which come before every record. Later bans are kept by the records.
+    bans: Vec<([usize; 3], usize)>,
     iterations: usize,
     debug: bool,
+    seed: u64,
+    rng: ChaCha12Rng,
 }
 
-impl Wave {
+impl<T: Eq + Hash + Clone> Default for Wave<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: Eq + Hash + Clone> Wave<T> {
+    /// Creates an empty wave with a randomly chosen seed.
+    ///
+    /// The seed chosen can be retrieved with `seed` to reproduce the run later on.
     pub fn new() -> Self {
+        let seed = thread_rng().gen();
+
         Wave {
             flags: vec![],
+            palette: Palette::new(),
             patterns: vec![],
             patterns_total: 0,
+            propagator: vec![],
+            links: vec![],
+            weights: vec![],
+            heuristic: SelectionHeuristic::default(),
+            symmetry: Symmetry::default(),
+            topology: Arc::new(Square),
+            backtracking: None,
+            periodic: Vector2::new(false, false),
+            constraints: vec![],
+            count_limits: vec![],
+            broken_count: None,
+            counts: None,
+            events: None,
+            budget: CollapseBudget::default(),
+            cancel_token: None,
+            parallel_stop: None,
             elements: vec![],
-            chunk_size: Vector2::new(0, 0),
-            chunk_fill_size: Vector2::new(0, 0),
+            chunk_size: Vector3::new(0, 0, 0),
+            chunk_fill_size: Vector3::new(0, 0, 0),
             history: vec![],
+            bans: vec![],
             iterations: 0,
             debug: false,
+            seed,
+            rng: ChaCha12Rng::seed_from_u64(seed),
         }
     }
 
@@ -61,6 +272,75 @@ impl Wave {
         self.debug = true;
     }
 
+    /// Seeds the wave's random number generator, resetting it to the start of the seed's sequence.
+    ///
+    /// Given the same sample, chunk size, flags, output size and seed, a wave will always produce the same result. The seed should be set before calling `collapse_all` or `collapse_once`.
+    pub fn set_seed(&mut self, seed: u64) {
+        self.seed = seed;
+        self.rng = ChaCha12Rng::seed_from_u64(seed);
+    }
+
+    /// The seed the wave's random number generator was last seeded with.
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    /// Sets the strategy used to choose which element to collapse next. By default this is `SelectionHeuristic::MinEntropy`.
+    pub fn set_heuristic(&mut self, heuristic: SelectionHeuristic) {
+        self.heuristic = heuristic;
+    }
+
+    /// Sets which transformations of the sample's patterns are added during `analyze`. By default this is `Symmetry::MirrorXY`.
+    ///
+    /// This must be set before calling `analyze` for it to be registered.
+    pub fn set_symmetry(&mut self, symmetry: Symmetry) {
+        self.symmetry = symmetry;
+    }
+
+    /// Sets the shape of the grid, which decides how elements neighbour each other. By default this is `Square`.
+    ///
+    /// This must be set before calling `analyze` for it to be registered, as patterns learn their rules in the directions of the topology.
+    pub fn set_topology(&mut self, topology: impl Topology + 'static) {
+        self.topology = Arc::new(topology);
+        self.link_elements();
+    }
+
+    /// The shape of the grid the wave works on.
+    pub fn topology(&self) -> &dyn Topology {
+        self.topology.as_ref()
+    }
+
+    /// Sets whether the output wraps around on each axis. By default neither axis does.
+    ///
+    /// On a periodic axis, the elements on one edge of the output are neighbours of the elements on the opposite edge, so a collapsed result can be tiled along that axis without any seams. This should be set before calling `collapse_all` or `collapse_once`.
+    pub fn set_periodic(&mut self, x: bool, y: bool) {
+        self.periodic = Vector2::new(x, y);
+        self.link_elements();
+    }
+
+    /// Enables the backtracking solver with the given limits, or disables it with `None`.
+    ///
+    /// By default, a contradiction causes `collapse_all` to throw away the whole grid and start again. When backtracking, the wave instead rolls back its most recent collapse, bans the value it chose for that element, and carries on from there. Only once the limits are exceeded (or there's nothing left to roll back) does it restart.
+    ///
+    /// Backtracking relies on the wave's history, so collapses are recorded even if `Flags::NoHistory` is set.
+    pub fn set_backtracking(&mut self, limits: Option<BacktrackLimits>) {
+        self.backtracking = limits;
+    }
+
+    /// Sets the limits on how long each call to `collapse_all`, `inpaint` or `steps` can run for.
+    ///
+    /// Once a limit is reached, the call stops with `WaveError::DeadlineExceeded` or `WaveError::ObservationLimitReached`, leaving the wave ready to carry on collapsing with another call (after the deadline has been moved, in its case).
+    pub fn set_budget(&mut self, budget: CollapseBudget) {
+        self.budget = budget;
+    }
+
+    /// Sets a flag which cancels the collapse once it's set, such as from another thread when its result is no longer needed.
+    ///
+    /// The flag is checked between collapses, and the call collapsing the wave stops with `WaveError::Cancelled`, leaving the wave ready to carry on collapsing once the flag is cleared. Copies of the wave share the flag, including the workers of `collapse_parallel`.
+    pub fn set_cancel_token(&mut self, token: Option<Arc<AtomicBool>>) {
+        self.cancel_token = token;
+    }
+
     /// Collapses continuously until the wave function either completely collapses or the max number of contradictions (attempts has been reached).
     ///
     /// # Arguments
@@ -69,54 +349,272 @@ impl Wave {
     /// # Notes
     ///
     /// * The number of iterations resets after a failed attempt. The number of failures is never reset.
-    /// * The current representation (`Vec<Vec<Vec<usize>>>`) is provided in the callback as its not available while the function is borrowing the `Wave`.
-    /// * No final perfect result is returned from a successful run as to avoid doing extra work in case the caller doesn't need the final representation.
+    /// * Contradictions which are resolved by backtracking (see `set_backtracking`) don't count as failures.
+    /// * The current representation (`Vec<Vec<Vec<T>>>`) is provided in the callback as its not available while the function is borrowing the `Wave`.
+    /// * No final perfect result is returned from a successful run as to avoid doing extra work in case the caller doesn't need the final representation. Some statistics about the run are returned instead.
     /// * When an error is returned, the final state at which the error occurred is preserved in the wave.
     pub fn collapse_all<F>(
         &mut self,
         max_contradictions: usize,
         callback: Option<F>,
-    ) -> Result<(), String>
+    ) -> Result<CollapseStats, WaveError>
     where
-        F: Fn(usize, usize, Vec<Vec<Vec<usize>>>),
+        F: Fn(usize, usize, Vec<Vec<Vec<T>>>),
     {
+        self.check_collapsible()?;
+
+        let mut run = CollapseRun::default();
+
+        while !self.completely_collapsed() {
+            self.advance(&mut run, max_contradictions)?;
+
+            if let Some(cb) = &callback {
+                cb(
+                    self.iterations,
+                    run.stats.contradictions,
+                    self.current_rep(),
+                );
+            }
+        }
+
+        Ok(run.finish(self.iterations))
+    }
+
+    /// Collapses copies of the wave on several threads at once, keeping the first one to succeed.
+    ///
+    /// `run` is called on each worker's copy with the worker's index, and is usually `collapse_all` or `inpaint`. The first worker keeps the wave's seed, and the others get their own seeds derived from it, so each one makes different choices. As soon as one of them succeeds the rest are cancelled, and the wave is replaced by the successful copy. Its seed can be set on a wave to reproduce the result on a single thread.
+    ///
+    /// If every worker fails, the wave is left as it was and the first worker's error is returned.
+    pub fn collapse_parallel<F>(&mut self, threads: usize, run: F) -> Result<CollapseStats, WaveError>
+    where
+        T: Send,
+        F: Fn(usize, &mut Wave<T>) -> Result<CollapseStats, WaveError> + Sync,
+    {
+        let stop = Arc::new(AtomicBool::new(false));
+
+        let mut outcomes: Vec<_> = thread::scope(|scope| {
+            let handles: Vec<_> = (0..threads.max(1))
+                .map(|worker| {
+                    let mut wave = self.clone();
+                    wave.parallel_stop = Some(stop.clone());
+
+                    if worker > 0 {
+                        wave.set_seed(mix_seed(self.seed, worker as u64));
+                    }
+
+                    let (run, stop) = (&run, &stop);
+
+                    scope.spawn(move || {
+                        let result = run(worker, &mut wave);
+                        // only the first success wins, even if another one finishes right after it
+                        let won = result.is_ok()
+                            && stop
+                                .compare_exchange(false, true, Ordering::Relaxed, Ordering::Relaxed)
+                                .is_ok();
+
+                        (wave, result, won)
+                    })
+                })
+                .collect();
+
+            handles
+                .into_iter()
+                .map(|h| h.join().unwrap_or_else(|e| std::panic::resume_unwind(e)))
+                .collect()
+        });
+
+        if let Some(index) = outcomes.iter().position(|(_, _, won)| *won) {
+            let (mut wave, result, _) = outcomes.swap_remove(index);
+            wave.parallel_stop = None;
+            *self = wave;
+
+            return result;
+        }
+
+        outcomes.swap_remove(0).1
+    }
+
+    /// Collapses the wave the same way as `collapse_all`, but one step at a time, yielding what happens along the way as `CollapseEvent`s.
+    ///
+    /// Unlike the callback of `collapse_all`, the events only describe what changed, so they can be used to follow along with the generation without rebuilding the whole representation every step.
+    pub fn steps(&mut self, max_contradictions: usize) -> Steps<'_, T> {
+        Steps::new(self, max_contradictions)
+    }
+
+    fn check_collapsible(&self) -> Result<(), WaveError> {
         if self.patterns.is_empty() {
-            return Err("The number of rules identified was zero. The input was flawed or the wave was configured incorrectly.".to_owned());
+            return Err(WaveError::NoPatterns);
         }
 
-        if self.chunk_fill_size.x == 0 || self.chunk_fill_size.y == 0 {
-            return Err("The superpositions are empty or were not filled properly".to_owned());
+        if self.chunk_fill_size.x == 0
+            || self.chunk_fill_size.y == 0
+            || self.chunk_fill_size.z == 0
+        {
+            return Err(WaveError::NotFilled);
         }
 
-        let mut failures = 0;
+        Ok(())
+    }
 
-        while !self.completely_collapsed() {
-            self.collapse_once();
+    /// Makes a single collapse, and then backtracks or restarts until any contradiction it caused is resolved.
+    fn advance(&mut self, run: &mut CollapseRun, max_contradictions: usize) -> Result<(), WaveError> {
+        let is_set = |flag: &Option<Arc<AtomicBool>>| {
+            flag.as_ref().is_some_and(|f| f.load(Ordering::Relaxed))
+        };
 
-            if self.contradiction_occurred() {
-                failures += 1;
+        if is_set(&self.cancel_token) || is_set(&self.parallel_stop) {
+            return Err(WaveError::Cancelled);
+        }
 
-                if failures == max_contradictions {
-                    return Err("The max number of contradictions has been reached".to_owned());
-                }
+        if self.budget.deadline.is_some_and(|d| Instant::now() >= d) {
+            return Err(WaveError::DeadlineExceeded);
+        }
+
+        if self.budget.max_observations == Some(run.observations) {
+            return Err(WaveError::ObservationLimitReached);
+        }
 
-                self.fill(self.true_size())?;
-                self.clear_history();
-                self.iterations = 0;
-            } else {
-                self.iterations += 1;
+        run.observations += 1;
+
+        self.collapse_once();
+
+        while self.contradiction_occurred() {
+            let can_backtrack = self
+                .backtracking
+                .is_some_and(|limits| run.stats.backtracks < limits.max_backtracks);
+
+            if can_backtrack && self.backtrack() {
+                run.stats.backtracks += 1;
+                continue;
             }
 
-            if let Some(cb) = &callback {
-                cb(self.iterations, failures, self.current_rep());
+            run.stats.contradictions += 1;
+
+            if run.stats.contradictions == max_contradictions {
+                return Err(WaveError::MaxContradictions(run.finish(self.iterations)));
+            }
+
+            self.emit(CollapseEvent::Restarted);
+            self.fill_volume(self.true_size())?;
+        }
+
+        Ok(())
+    }
+
+    fn emit(&mut self, event: CollapseEvent) {
+        if let Some(events) = &mut self.events {
+            events.push_back(event);
+        }
+    }
+
+    /// Regenerates the masked cells of an existing output, keeping the rest of it as is.
+    ///
+    /// The wave is filled to the size of `existing`, which is indexed the same way as the result of `perfect_rep`. Every cell that isn't set in `mask` is pinned to its existing value with `constrain`, and then the rest is collapsed with `collapse_all`. The arguments and the result are the same as `collapse_all`'s.
+    ///
+    /// # Notes
+    ///
+    /// * The existing values must be compatible with the analyzed rules. An error is returned if the cells around the mask can't be satisfied, or if an unmasked value isn't in the sample at all.
+    /// * Constraints added beforehand still apply, but the pins for the unmasked cells are removed again once this returns.
+    pub fn inpaint<F>(
+        &mut self,
+        existing: &[Vec<T>],
+        mask: &[Vec<bool>],
+        max_contradictions: usize,
+        callback: Option<F>,
+    ) -> Result<CollapseStats, WaveError>
+    where
+        F: Fn(usize, usize, Vec<Vec<Vec<T>>>),
+    {
+        let size = dimensions_of(existing);
+        let mask_size = dimensions_of(mask);
+
+        if mask_size != size {
+            return Err(WaveError::MaskSizeMismatch {
+                expected: size,
+                found: mask_size,
+            });
+        }
+
+        let previous_constraints = self.constraints.len();
+        self.fill(size)?;
+
+        let result = self
+            .pin_unmasked(existing, mask)
+            .and_then(|_| self.collapse_all(max_contradictions, callback));
+        self.constraints.truncate(previous_constraints);
+
+        result
+    }
+
+    fn pin_unmasked(&mut self, existing: &[Vec<T>], mask: &[Vec<bool>]) -> Result<(), WaveError> {
+        for (x, (column, mask_column)) in existing.iter().zip(mask.iter()).enumerate() {
+            for (y, (value, &masked)) in column.iter().zip(mask_column.iter()).enumerate() {
+                if !masked {
+                    self.constrain(Vector2::new(x, y), std::slice::from_ref(value))?;
+                }
             }
         }
 
         Ok(())
     }
 
+    /// Rolls back the most recent collapse that can still be undone, bans the value which was chosen for its element, and propagates that ban.
+    ///
+    /// Returns false if there was no collapse left to roll back. The ban itself might cause another contradiction, in which case the caller is expected to backtrack again.
+    fn backtrack(&mut self) -> bool {
+        let Some(index) = self.history.iter().rposition(|r| !r.undone) else {
+            return false;
+        };
+
+        if self.history[index].committed {
+            return false;
+        }
+
+        let record = self.history.remove(index);
+        // the records after it were undone already, and can't be redone from the restored state
+        self.history.truncate(index);
+
+        self.restore_trail(&record.trail);
+        self.iterations = record.iteration;
+
+        if self.debug {
+            println!(
+                "Backtracking to iteration {} and banning pattern {} at {:?}.",
+                record.iteration,
+                record.chosen_pattern_id,
+                record.location()
+            );
+        }
+
+        self.emit(CollapseEvent::Backtracked {
+            position: record.location(),
+            banned: record.chosen_pattern_id,
+        });
+
+        let element_index = self.element_index(record.location());
+        self.ban_pattern(element_index, record.chosen_pattern_id);
+
+        true
+    }
+
+    /// Removes a pattern from an element for good and propagates the change, as backtracking does after rolling back a collapse to it.
+    fn ban_pattern(&mut self, element_index: usize, pattern: usize) {
+        let position = self.elements[element_index].position;
+        self.elements[element_index].remove(pattern, &self.weights);
+
+        // the ban belongs to the state before the rolled back collapse, so it joins the record of the one before that
+        self.record_removal(element_index, &[pattern]);
+
+        match self.history.iter_mut().rev().find(|r| !r.undone) {
+            Some(record) => record.bans.push((position.into(), pattern)),
+            None => self.bans.push((position.into(), pattern)),
+        }
+
+        self.settle(element_index);
+    }
+
     fn contradiction_occurred(&self) -> bool {
-        self.elements.iter().filter(|e| e.values.is_empty()).count() != 0
+        self.broken_count.is_some() || self.elements.iter().any(|e| e.values.is_empty())
     }
 
     fn completely_collapsed(&self) -> bool {
@@ -125,104 +623,170 @@ impl Wave {
 
     /// Returns the perfect representation of the current internal state of the wave.
     ///
+    /// This function will throw an error if the internal wave isn't completely collapsed. For a volume, this is only its bottom layer (where z is 0), and `perfect_volume` returns the whole of it.
+    pub fn perfect_rep(&self) -> Result<Vec<Vec<T>>, WaveError> {
+        self.perfect_volume().map(bottom_layer)
+    }
+
+    /// Returns the perfect representation of the wave as a volume, indexed as `[x][y][z]`. The output of a planar wave is a single layer deep.
+    ///
     /// This function will throw an error if the internal wave isn't completely collapsed.
-    pub fn perfect_rep(&self) -> Result<Vec<Vec<usize>>, String> {
+    pub fn perfect_volume(&self) -> Result<Vec<Vec<Vec<T>>>, WaveError> {
         if self.elements.is_empty() {
-            return Err("There are no superpositions to create a representation from".to_owned());
+            return Err(WaveError::NotFilled);
         }
 
-        if self.contradiction_occurred() {
-            return Err(
-                "A contradiction occurred preventing the formation of a perfect representation"
-                    .to_owned(),
-            );
+        if let Some(element) = self.elements.iter().find(|e| e.values.is_empty()) {
+            return Err(WaveError::Contradiction {
+                position: element.position,
+            });
         }
 
         if !self.completely_collapsed() {
-            return Err("The superpositions aren't completely collapsed yet".to_owned());
+            return Err(WaveError::NotCollapsed);
         }
 
-        let mut pairs: Vec<(usize, Vector2<usize>)> = vec![];
+        Ok(self.map_cells(|element, offset| {
+            let contents = &self.patterns[element.values.first().unwrap()].contents;
+            let id = contents[offset.x][offset.y][offset.z];
 
-        for element in self.elements.iter() {
-            let real_origin = Vector2 {
-                x: element.position.x * self.chunk_size.x,
-                y: element.position.y * self.chunk_size.y,
-            };
+            self.palette.values()[id].clone()
+        }))
+    }
+
+    fn true_size(&self) -> Vector3<usize> {
+        Vector3 {
+            x: self.chunk_fill_size.x * self.chunk_size.x,
+            y: self.chunk_fill_size.y * self.chunk_size.y,
+            z: self.chunk_fill_size.z * self.chunk_size.z,
+        }
+    }
 
-            let contents = &element.values.first().unwrap().contents;
+    /// Maps every cell of the output to a value, given the element covering the cell and the cell's offset within that element's chunk.
+    fn map_cells<V>(&self, cell: impl Fn(&Element, Vector3<usize>) -> V) -> Vec<Vec<Vec<V>>> {
+        let size = self.true_size();
+
+        (0..size.x)
+            .map(|x| {
+                (0..size.y)
+                    .map(|y| {
+                        (0..size.z)
+                            .map(|z| self.map_cell(Vector3::new(x, y, z), &cell))
+                            .collect()
+                    })
+                    .collect()
+            })
+            .collect()
+    }
 
-            for (x, col) in contents.iter().enumerate() {
-                for (y, id) in col.iter().enumerate() {
-                    let real_pos = Vector2 {
-                        x: real_origin.x + x,
-                        y: real_origin.y + y,
-                    };
+    /// Maps the cells of the bottom layer of the output (where z is 0) like `map_cells`, without building the rest of the volume.
+    fn map_layer<V>(&self, cell: impl Fn(&Element, Vector3<usize>) -> V) -> Vec<Vec<V>> {
+        let size = self.true_size();
+
+        (0..size.x)
+            .map(|x| {
+                (0..size.y)
+                    .map(|y| self.map_cell(Vector3::new(x, y, 0), &cell))
+                    .collect()
+            })
+            .collect()
+    }
 
-                    pairs.push((*id, real_pos));
-                }
-            }
-        }
+    /// Maps the output cell at the given position like `map_cells`.
+    fn map_cell<V>(
+        &self,
+        cell_position: Vector3<usize>,
+        cell: &impl Fn(&Element, Vector3<usize>) -> V,
+    ) -> V {
+        let chunk = self.chunk_size;
+        let position = Vector3::new(
+            cell_position.x / chunk.x,
+            cell_position.y / chunk.y,
+            cell_position.z / chunk.z,
+        );
+        let offset = Vector3::new(
+            cell_position.x % chunk.x,
+            cell_position.y % chunk.y,
+            cell_position.z % chunk.z,
+        );
 
-        let result = arrayify(pairs, &self.true_size());
-        Ok(result)
+        cell(&self.elements[self.element_index(position)], offset)
     }
 
-    fn true_size(&self) -> Vector2<usize> {
-        Vector2 {
-            x: self.chunk_fill_size.x * self.chunk_size.x,
-            y: self.chunk_fill_size.y * self.chunk_size.y,
-        }
+    /// The distinct values of the analyzed sample, which the patterns' contents are ids into.
+    pub fn palette(&self) -> &Palette<T> {
+        &self.palette
     }
 
-    pub fn current_rep(&self) -> Vec<Vec<Vec<usize>>> {
+    /// The sample values making up the pattern with the given id, indexed as `[x][y]` within its chunk. For a volume, this is only the bottom layer of the pattern.
+    pub fn pattern_contents(&self, id: usize) -> Option<Vec<Vec<T>>> {
+        self.pattern_volume(id).map(bottom_layer)
+    }
+
+    /// The sample values making up the pattern with the given id, indexed as `[x][y][z]` within its chunk.
+    pub fn pattern_volume(&self, id: usize) -> Option<Vec<Vec<Vec<T>>>> {
+        self.patterns.get(id).map(|p| {
+            p.contents
+                .iter()
+                .map(|column| {
+                    column
+                        .iter()
+                        .map(|stack| {
+                            stack
+                                .iter()
+                                .map(|&v| self.palette.values()[v].clone())
+                                .collect()
+                        })
+                        .collect()
+                })
+                .collect()
+        })
+    }
+
+    /// Returns the sample values each cell of the output can still be, in the order they were first seen in the sample. For a volume, this is only its bottom layer.
+    pub fn current_rep(&self) -> Vec<Vec<Vec<T>>> {
         if self.elements.is_empty() {
             return vec![];
         }
 
-        let mut pairs: Vec<(Vec<usize>, Vector2<usize>)> = vec![];
+        self.map_layer(|element, offset| self.possible_values(element, offset))
+    }
 
-        for element in &self.elements {
-            let real_origin = Vector2 {
-                x: element.position.x * self.chunk_size.x,
-                y: element.position.y * self.chunk_size.y,
-            };
+    /// Returns the sample values each cell of the output can still be, indexed as `[x][y][z]`.
+    pub fn current_volume(&self) -> Vec<Vec<Vec<Vec<T>>>> {
+        if self.elements.is_empty() {
+            return vec![];
+        }
 
-            for cx in 0..self.chunk_size.x {
-                for cy in 0..self.chunk_size.y {
-                    let mut new_pair = (
-                        vec![],
-                        Vector2 {
-                            x: real_origin.x + cx,
-                            y: real_origin.y + cy,
-                        },
-                    );
-
-                    for value in &element.values {
-                        new_pair.0.push(value.contents[cx][cy]);
-                    }
+        self.map_cells(|element, offset| self.possible_values(element, offset))
+    }
 
-                    // deduplication is required because when working with the overlapping tiled
-                    // model the patterns are set up such that they can at times have duplicated
-                    // contents, which is a little bit disorienting and doesn't make sense to the
-                    // caller of this function.
-                    new_pair.0.sort();
-                    new_pair.0.dedup();
+    /// The sample values the cell at the given offset of the element's chunk can still be.
+    fn possible_values(&self, element: &Element, offset: Vector3<usize>) -> Vec<T> {
+        // deduplication is required because when working with the overlapping tiled
+        // model the patterns are set up such that they can at times have duplicated
+        // contents, which is a little bit disorienting and doesn't make sense to the
+        // caller of this function. Gathering the ids in a set also keeps them in order.
+        let mut ids = BitSet::empty(self.palette.len());
 
-                    pairs.push(new_pair);
-                }
+        for value in element.values.iter() {
+            ids.insert(self.patterns[value].contents[offset.x][offset.y][offset.z]);
+
+            if ids.len() == self.palette.len() {
+                break;
             }
         }
 
-        let result = arrayify(pairs, &self.true_size());
-        result
+        ids.iter()
+            .map(|id| self.palette.values()[id].clone())
+            .collect()
     }
 
     /// Causing the wave to perform one collapse. This will also cause consequent propagation.
     ///
     /// # Notes
     ///
-    /// * The wave doesn't stop propogating until its completely iterated over the entire superposition grid. It does this as, although on the first run it doesn't make much sense, on future runs it will propagate out changes between the sites of different collapses.
+    /// * Propagation continues until no element changes anymore, so the wave is arc-consistent after each collapse. Elements which can't be reached by a change are never visited.
     /// * After this function is called, it saves what it did to a private history log.
     ///     * This can be disabled using the `NoHistory` flag.
     /// * If the internal superposition grid is empty, this function will do nothing.
@@ -231,295 +795,674 @@ impl Wave {
             return;
         }
 
-        let mut selected_elements = vec![0usize];
-        let mut greatest_entropy = 0.;
+        let Some(selected_element_index) = self.select_element() else {
+            return;
+        };
 
-        for (i, element) in self.elements.iter().enumerate() {
-            if element.is_collapsed() {
-                continue;
-            }
+        if self.debug {
+            println!("Chosen element to collapse.");
+        }
 
-            let entropy = element.entropy();
+        // Propagation keeps every element consistent with its neighbours, so any of the element's remaining values is a valid choice here.
+        let values: Vec<usize> = self.elements[selected_element_index].values.iter().collect();
 
-            if entropy == 0. {
-                continue;
-            }
+        let choice = *values
+            .choose_weighted(&mut self.rng, |v| self.weights[*v].weight)
+            .unwrap();
+
+        if self.debug {
+            println!("Chosen element to collapse too.");
+        }
 
-            if entropy > greatest_entropy {
-                greatest_entropy = entropy;
-                selected_elements = vec![i];
-            } else if entropy == greatest_entropy {
-                selected_elements.push(i);
+        self.observe(selected_element_index, choice);
+    }
+
+    /// Collapses an element to the given pattern, records it and propagates the change.
+    fn observe(&mut self, element_index: usize, choice: usize) {
+        let position = self.elements[element_index].position;
+        let values: Vec<usize> = self.elements[element_index].values.iter().collect();
+
+        // The backtracking solver rolls collapses back with their records, so they're kept even without history.
+        if !self.flags.contains(&Flags::NoHistory) || self.backtracking.is_some() {
+            if self.debug {
+                println!("Creating history record.");
             }
+
+            // a new collapse leaves nothing to redo
+            self.history.retain(|r| !r.undone);
+            self.history.push(Record::new(position, choice, self.iterations));
+        }
+
+        // finish collapse!
+        self.elements[element_index].collapse_to(choice, &self.weights);
+        let removed: Vec<usize> = values.into_iter().filter(|&v| v != choice).collect();
+
+        if !removed.is_empty() {
+            self.record_removal(element_index, &removed);
         }
 
-        debug_assert!(!selected_elements.is_empty());
+        self.iterations += 1;
+        self.commit_old_records();
 
-        let mut rng = thread_rng();
-        let selected_element_index = selected_elements.choose(&mut rng).unwrap();
+        self.emit(CollapseEvent::Observed {
+            position,
+            pattern: choice,
+        });
+
+        // propogate changes
+        self.settle(element_index);
+    }
+
+    /// Propagates pattern changes from a center element outwards, pruning values from
+    /// neighbouring elements which are no longer supported by any of the values next to them.
+    ///
+    /// Elements that change are queued up and propagated from in turn, so this continues until
+    /// nothing changes anymore or an element runs out of values (a contradiction).
+    pub fn propagate(&mut self, center_element: usize) {
+        if self.elements[center_element].values.is_empty() {
+            if self.debug {
+                println!("The collapse must have failed, because propogation was called on a center element that contains no values.");
+            }
+
+            self.emit(CollapseEvent::Contradiction {
+                position: self.elements[center_element].position,
+            });
+
+            return;
+        }
 
         if self.debug {
-            println!("Chosen element to collapse.");
+            println!(
+                "Propagating from {:?}",
+                self.elements[center_element].position
+            );
         }
 
-        // I believe this little code below has been VASTLY improved as of writing this. It now considers its choice of collapse value based on the possible pattern values that already surround the chosen collapse point. Thus, the algorithm isn't randomly deciding a collapse value and going through the huge amount of code to propogate a collapse that was invalid to begin with.
-        // This shouldn't make the results of the collapse the same, though, as their is still the RNG (truly random or weighted random).
+        let mut queued = vec![false; self.elements.len()];
+        let mut worklist = vec![center_element];
+        queued[center_element] = true;
+
+        // reused between elements to avoid reallocating
+        let mut supported = BitSet::empty(self.patterns.len());
+
+        while let Some(current) = worklist.pop() {
+            queued[current] = false;
+
+            for link in 0..self.neighbours(current).len() {
+                let (direction, neighbour) = self.neighbours(current)[link];
+                supported.clear();
+
+                for value in self.elements[current].values.iter() {
+                    supported.union_with(&self.propagator[value][direction as usize]);
+                }
+
+                let element = &mut self.elements[neighbour];
+                let unsupported = element.values.difference(&supported);
+
+                if unsupported.is_empty() {
+                    continue;
+                }
+
+                for &value in &unsupported {
+                    element.remove(value, &self.weights);
+                }
+
+                let position = element.position;
+                let contradicted = element.values.is_empty();
+
+                self.record_removal(neighbour, &unsupported);
+                self.emit(CollapseEvent::Pruned {
+                    position,
+                    removed: unsupported,
+                });
 
-        let element_position = self.elements[*selected_element_index].position.clone();
-        let neighbour_locations = noneg_neighbours(&element_position);
-        let mut neighbour_indexes: Vec<usize> = vec![];
+                if contradicted {
+                    if self.debug {
+                        println!("Contradiction reached at {:?}", position);
+                    }
 
-        for neighbour_location in neighbour_locations {
-            for i in 0..self.elements.len() {
-                if self.elements[i].position == neighbour_location {
-                    neighbour_indexes.push(i);
+                    self.emit(CollapseEvent::Contradiction { position });
+                    return;
+                }
+
+                if !queued[neighbour] {
+                    queued[neighbour] = true;
+                    worklist.push(neighbour);
                 }
             }
         }
 
-        let mut valid_values: Vec<Arc<Pattern>> = vec![];
-        
-        // Retains only pattern values that are present in all specified neighbour elements.
-        // Iterates through the neighbour indexes, initializing valid_values to the first neighbour's values.
-        // Then retains only values that are also present in each subsequent neighbour.
-        for (i, neighbour_index) in neighbour_indexes.iter().enumerate() {
-            if i == 0 {
-                valid_values = self.elements[*neighbour_index].values.clone();
-                continue;
-            }
+        if self.debug {
+            println!("Finished propagating.");
+        }
+    }
 
-            valid_values.retain(|value| self.elements[*neighbour_index].values.contains(value));
+    /// Takes removals out of the counts of the limited values, and adds them to the trail of the most recent collapse which hasn't been undone, so undoing it puts them back as well.
+    ///
+    /// Removals made before the first record is kept are part of the filled wave, and aren't recorded.
+    fn record_removal(&mut self, element_index: usize, removed: &[usize]) {
+        if let Some(counts) = &mut self.counts {
+            for &id in removed {
+                counts.remove(element_index, id);
+            }
         }
 
-        let borrow = &mut self.elements[*selected_element_index];
-        let mut refined_values = borrow.values.clone();
-        refined_values.retain(|value| {
-            valid_values.contains(value)
-        });
+        if let Some(record) = self.history.iter_mut().rev().find(|r| !r.undone) {
+            record.trail.push((element_index, removed.to_vec()));
+        }
+    }
 
-        let choice = if self.flags.contains(&Flags::NoWeights) {
-            refined_values.choose(&mut rng).unwrap()
-        } else {
-            refined_values
-                .choose_weighted(&mut rng, |v| v.count)
-                .unwrap()
+    /// Commits to the oldest records once there are more than the backtracking limits allow rolling back.
+    ///
+    /// Committed records can still be undone by hand, unless they were only kept for backtracking because of `Flags::NoHistory`, in which case they're dropped.
+    fn commit_old_records(&mut self) {
+        let Some(limits) = self.backtracking else {
+            return;
         };
-        
-        if self.debug {
-            println!("Chosen element to collapse too.");
+
+        let open = self.history.iter().rev().take_while(|r| !r.committed).count();
+
+        if open <= limits.max_depth {
+            return;
         }
 
+        let end = self.history.len() - limits.max_depth;
+
         if self.flags.contains(&Flags::NoHistory) {
-            if self.debug {
-                println!("Creating history record.");
+            self.history.drain(..end);
+        } else {
+            for record in &mut self.history[..end] {
+                record.committed = true;
             }
+        }
+    }
 
-            let previous_pattern_ids = borrow.values.iter().map(|p| p.id).collect();
-            let new_record = Record::new(
-                borrow.position.clone(),
-                choice.id,
-                previous_pattern_ids,
-                self.iterations,
-            );
-            self.history.push(new_record);
+    /// Puts back every removal of a trail, latest first, leaving the elements as they were before it.
+    fn restore_trail(&mut self, trail: &[(usize, Vec<usize>)]) {
+        for (element_index, removed) in trail.iter().rev() {
+            self.elements[*element_index].restore(removed, &self.weights);
+
+            if let Some(counts) = &mut self.counts {
+                for &id in removed {
+                    counts.restore(*element_index, id);
+                }
+            }
         }
 
-        // finish collapse!
-        let choice_value = choice.clone();
-        borrow.values.clear();
-        borrow.values.push(choice_value);
+        // the count limits were met before the removals, as they're enforced after every change
+        self.broken_count = None;
+    }
 
-        // propogate changes
-        self.propagate(*selected_element_index);
+    /// Removes patterns from an element, without propagating the change.
+    fn prune(&mut self, element_index: usize, removed: Vec<usize>) {
+        for &id in &removed {
+            self.elements[element_index].remove(id, &self.weights);
+        }
+
+        self.record_removal(element_index, &removed);
+        self.emit(CollapseEvent::Pruned {
+            position: self.elements[element_index].position,
+            removed,
+        });
     }
 
-    /// Propagates pattern changes from a center element to its neighbours,
-    /// pruning invalid pattern values based on consistency with the rules
-    /// of the collapsed center element.
-    pub fn propagate(&mut self, center_element: usize) {
-        let center = &self.elements[center_element];
+    /// Propagates a change to an element, and then enforces the count limits on what's left.
+    fn settle(&mut self, element_index: usize) {
+        self.propagate(element_index);
 
-        if center.values.is_empty() {
-            if self.debug {
-                println!("The collapse must have failed, because propogation was called on a center element that contains no values.");
-            }
+        // a limit which can't be met is left as a contradiction for the caller to deal with, like any other
+        let _ = self.enforce_counts();
+    }
 
-            return;
+    /// Bans each limited value from the cells which could still be it once its maximum is reached, and forces it onto them once they're just enough to reach its minimum, propagating the changes until every limit is settled.
+    ///
+    /// An error is returned if a limit can no longer be met, or if enforcing one leads to a contradiction.
+    fn enforce_counts(&mut self) -> Result<(), WaveError> {
+        self.broken_count = None;
+
+        if self.count_limits.is_empty() {
+            return Ok(());
         }
 
-        let center_pos = center.position.clone();
+        loop {
+            if self.elements.iter().any(|e| e.values.is_empty()) {
+                return Ok(());
+            }
 
-        if self.debug {
-            println!("Propagating from {:?}", center_pos);
-        }
+            let counts = match &self.counts {
+                Some(counts) => counts,
+                None => {
+                    let values: Vec<usize> = self.count_limits.iter().map(|l| l.value).collect();
+                    let counts =
+                        ValueCounts::new(&values, &self.patterns, self.chunk_size, &self.elements);
 
-        let mut current_locs = noneg_neighbours(&center_pos);
+                    self.counts.insert(counts)
+                }
+            };
 
-        let first_reference = (
-            center_pos.clone(),
-            center.values.iter().map(|v| v.rules.clone()).collect(),
-        );
+            let mut enforced = None;
 
-        let mut references: Vec<(Vector2<usize>, Vec<Vec<Rule>>)> = vec![first_reference];
-        let mut banned_locs: Vec<Vec<Vector2<usize>>> = vec![vec![center_pos.clone()]];
+            for (slot, limit) in self.count_limits.iter().enumerate() {
+                let decided = counts.decided(slot);
+                let possible = counts.possible(slot);
+                let max = limit.max.unwrap_or(usize::MAX);
 
-        loop {
-            let mut indexes = vec![];
+                if decided > max || decided + possible < limit.min {
+                    self.broken_count = Some(limit.value);
+                    return Err(WaveError::UnsatisfiableCount { id: limit.value });
+                }
 
-            for loc in &current_locs {
-                for i in 0..self.elements.len() {
-                    if self.elements[i].position == *loc {
-                        indexes.push(i);
-                    }
+                if possible == 0 {
+                    continue;
                 }
-            }
 
-            if indexes.is_empty() {
-                // propagation finished!
+                if decided == max {
+                    enforced = Some((limit.value, false));
+                    break;
+                }
 
-                if self.debug {
-                    println!("Finished propagating.");
+                if decided + possible == limit.min {
+                    enforced = Some((limit.value, true));
+                    break;
                 }
+            }
+
+            let Some((value, forced)) = enforced else {
+                return Ok(());
+            };
 
-                return;
+            let mut changed = vec![];
+
+            for (element_index, offset) in self.possible_cells(value) {
+                let removed: Vec<usize> = self.elements[element_index]
+                    .values
+                    .iter()
+                    .filter(|&id| {
+                        let is_value = self.patterns[id].contents[offset.x][offset.y][offset.z] == value;
+                        is_value != forced
+                    })
+                    .collect();
+
+                // an earlier cell of the same element may have already settled this one
+                if !removed.is_empty() {
+                    self.prune(element_index, removed);
+                    changed.push(element_index);
+                }
             }
 
-            let mut new_references = vec![];
-            let mut new_locs = vec![];
+            changed.dedup();
 
-            if self.debug {
-                println!("Propagating output to {:?}", current_locs);
+            for element_index in changed {
+                self.propagate(element_index);
             }
 
-            // *** Value Pruning ***
-            for i in indexes {
-                let element = &mut self.elements[i];
-                let element_neighbours = noneg_neighbours(&element.position);
-                let mut values_to_remove = vec![];
-
-                for (value_idx, value) in element.values.iter().enumerate() {
-                    let mut valid = false;
-
-                    // now we search for a valid reason to keep the element!
-                    'refloop: for reference in &references {
-                        if element_neighbours.contains(&reference.0) {
-                            let direction = orthog_direction(&reference.0, &element.position);
-
-                            for rule_set in &reference.1 {
-                                for rule in rule_set {
-                                    if rule.content == value.contents && rule.direction == direction
-                                    {
-                                        valid = true;
-                                        break 'refloop;
-                                    }
-                                }
-                            }
+            if self.elements.iter().any(|e| e.values.is_empty()) {
+                return Err(WaveError::UnsatisfiableCount { id: value });
+            }
+        }
+    }
+
+    /// Finds the cells of the output which could still become the value, alongside others, as the index of their element and their offset within its chunk.
+    fn possible_cells(&self, value: usize) -> Vec<(usize, Vector3<usize>)> {
+        let mut possible = vec![];
+
+        for (element_index, element) in self.elements.iter().enumerate() {
+            for x in 0..self.chunk_size.x {
+                for y in 0..self.chunk_size.y {
+                    for z in 0..self.chunk_size.z {
+                        let mut cell = element
+                            .values
+                            .iter()
+                            .map(|id| self.patterns[id].contents[x][y][z] == value);
+
+                        // an element which has run out of patterns can't be anything
+                        let Some(first) = cell.next() else {
+                            continue;
+                        };
+
+                        let (can, only) = cell.fold((first, first), |(can, only), is_value| {
+                            (can || is_value, only && is_value)
+                        });
+
+                        if can && !only {
+                            possible.push((element_index, Vector3::new(x, y, z)));
                         }
                     }
+                }
+            }
+        }
+
+        possible
+    }
+
+    /// Chooses the next element to collapse according to the wave's `SelectionHeuristic`.
+    ///
+    /// Collapsed elements and elements which have hit a contradiction are never chosen. `None` is returned if there is nothing left to choose.
+    fn select_element(&mut self) -> Option<usize> {
+        let mut candidates = self
+            .elements
+            .iter()
+            .enumerate()
+            .filter(|(_, e)| !e.is_collapsed() && !e.values.is_empty());
+
+        match self.heuristic {
+            SelectionHeuristic::Scanline => candidates.next().map(|(i, _)| i),
+            SelectionHeuristic::Random => {
+                let indexes: Vec<usize> = candidates.map(|(i, _)| i).collect();
+                indexes.choose(&mut self.rng).copied()
+            }
+            SelectionHeuristic::MinEntropy | SelectionHeuristic::Mrv => {
+                let mut selected = None;
+                let mut lowest = f64::MAX;
+
+                for (i, element) in candidates {
+                    let score = if self.heuristic == SelectionHeuristic::Mrv {
+                        element.values.len() as f64
+                    } else {
+                        element.entropy()
+                    };
+
+                    // A little noise breaks ties between equally good elements at random.
+                    // It's kept small enough to never reorder elements which actually differ.
+                    let noised = score + self.rng.gen::<f64>() * 1e-6;
 
-                    if !valid {
-                        values_to_remove.push(value_idx);
+                    if noised < lowest {
+                        lowest = noised;
+                        selected = Some(i);
                     }
                 }
 
-                remove_indexes(&mut element.values, values_to_remove);
+                selected
+            }
+        }
+    }
 
-                let reference = (
-                    element.position.clone(),
-                    element
-                        .values
-                        .iter()
-                        .map(|v| v.rules.clone())
-                        .collect::<Vec<Vec<Rule>>>(),
-                );
+    /// The index of the element at the given position in the chunk grid.
+    fn element_index(&self, position: Vector3<usize>) -> usize {
+        (position.x * self.chunk_fill_size.y + position.y) * self.chunk_fill_size.z + position.z
+    }
 
-                new_references.push(reference);
+    /// The neighbours of the element at the given index alongside the direction they lie in, as laid out by the wave's topology.
+    fn neighbours(&self, index: usize) -> &[(u8, usize)] {
+        &self.links[index]
+    }
 
-                new_locs.push(noneg_neighbours(&element.position));
-            }
+    /// Finds the neighbours of every element with the wave's topology, which needs doing again whenever the elements, the topology or the wrapping change.
+    fn link_elements(&mut self) {
+        self.links = self
+            .elements
+            .iter()
+            .map(|element| {
+                self.topology
+                    .neighbours(element.position, self.chunk_fill_size, self.periodic)
+                    .into_iter()
+                    .map(|(direction, position)| (direction, self.element_index(position)))
+                    .collect()
+            })
+            .collect();
+    }
 
-            // *** Clean Up and Preparation ***
-            references = new_references;
-            banned_locs.push(current_locs.clone());
+    pub fn fill(&mut self, size: Vector2<usize>) -> Result<(), WaveError> {
+        self.fill_volume(size.extend(1))
+    }
 
-            if banned_locs.len() == 3 {
-                banned_locs.remove(0);
-            }
+    /// Fills the wave with a volume of the given size, to be collapsed after analyzing a volume with `analyze_volume`.
+    ///
+    /// Each element covers a chunk of the volume, and is constrained by its neighbours along all three axes. Filling with a depth of 1 is the same as calling `fill`. Filling clears the wave's history, as it only applies to the elements it was recorded on.
+    pub fn fill_volume(&mut self, size: Vector3<usize>) -> Result<(), WaveError> {
+        if self.debug {
+            println!("Filling superpositions with the following size: {:?}", size);
+        }
+
+        if !size.x.is_multiple_of(self.chunk_size.x)
+            || !size.y.is_multiple_of(self.chunk_size.y)
+            || !size.z.is_multiple_of(self.chunk_size.z)
+        {
+            return Err(WaveError::SizeNotMultipleOfChunk {
+                size,
+                chunk_size: self.chunk_size,
+            });
+        }
 
-            current_locs.clear();
+        // the history of the previous elements doesn't apply to the new ones
+        self.elements.clear();
+        self.broken_count = None;
+        self.counts = None;
+        self.history.clear();
+        self.bans.clear();
+        self.iterations = 0;
 
-            for neighbour_set in new_locs {
-                for loc in neighbour_set {
-                    // check if banned
-                    if banned_locs.iter().find(|s| s.contains(&loc)).is_some() {
-                        continue;
-                    }
+        self.calculate_pattern_weights();
+
+        let template = Element::new(
+            BitSet::full(self.patterns.len()),
+            Vector3::new(0, 0, 0),
+            &self.weights,
+        );
 
-                    current_locs.push(loc);
+        let chunk_fill_size = Vector3 {
+            x: size.x / self.chunk_size.x,
+            y: size.y / self.chunk_size.y,
+            z: size.z / self.chunk_size.z,
+        };
+
+        for x in 0..chunk_fill_size.x {
+            for y in 0..chunk_fill_size.y {
+                for z in 0..chunk_fill_size.z {
+                    let mut element = template.clone();
+                    element.position = Vector3::new(x, y, z);
+                    self.elements.push(element);
                 }
             }
+        }
+
+        self.chunk_fill_size = chunk_fill_size;
+        self.link_elements();
+
+        // the constraints are taken out while they're applied, as applying them needs the wave mutably
+        let constraints = std::mem::take(&mut self.constraints);
+        let applied = constraints
+            .iter()
+            .try_for_each(|constraint| self.apply_constraint(constraint));
+        self.constraints = constraints;
+
+        applied?;
+        self.enforce_counts()
+    }
+
+    /// Restricts the cell at the given position of the output to the given sample values, and propagates the restriction right away.
+    ///
+    /// Constraints work on single cells rather than whole chunks, so any pattern with one of the values at that spot in the chunk remains possible. Values which aren't in the sample can never be allowed. They are kept by the wave and reapplied every time it's filled (including after a contradiction in `collapse_all`), until `clear_constraints` is called.
+    ///
+    /// An error is returned if the wave hasn't been filled, the position is out of bounds, or the constraint can't be satisfied alongside the previous ones. The wave is left in its contradicted state in the latter case.
+    pub fn constrain(
+        &mut self,
+        position: Vector2<usize>,
+        allowed_values: &[T],
+    ) -> Result<(), WaveError> {
+        self.constrain_voxel(position.extend(0), allowed_values)
+    }
+
+    /// Restricts the cell at the given position of a volume to the given sample values, the same way as `constrain`.
+    pub fn constrain_voxel(
+        &mut self,
+        position: Vector3<usize>,
+        allowed_values: &[T],
+    ) -> Result<(), WaveError> {
+        self.add_constraint(CellConstraint {
+            position,
+            values: self.palette_ids(allowed_values),
+            banned: false,
+        })
+    }
+
+    /// Prevents the cell at the given position of the output from being any of the given sample values, and propagates the ban right away.
+    ///
+    /// This behaves the same way as `constrain`, and returns the same errors.
+    pub fn ban(&mut self, position: Vector2<usize>, values: &[T]) -> Result<(), WaveError> {
+        self.ban_voxel(position.extend(0), values)
+    }
+
+    /// Prevents the cell at the given position of a volume from being any of the given sample values, the same way as `ban`.
+    pub fn ban_voxel(&mut self, position: Vector3<usize>, values: &[T]) -> Result<(), WaveError> {
+        self.add_constraint(CellConstraint {
+            position,
+            values: self.palette_ids(values),
+            banned: true,
+        })
+    }
 
-            current_locs.dedup();
+    /// The ids of the values which are in the palette, skipping those which aren't.
+    fn palette_ids(&self, values: &[T]) -> Vec<usize> {
+        values.iter().filter_map(|v| self.palette.id(v)).collect()
+    }
+
+    /// Limits the number of cells of the whole output which can be the given sample value, to at least `min` and at most `max` if one is given. Setting the limits of a value again replaces them.
+    ///
+    /// The cells which could still be the value are kept track of as the wave collapses. Once the maximum is reached the value is banned from all of them, and once they're only just enough to reach the minimum they're all forced to be it. A limit that can no longer be met is a contradiction, which is backtracked or restarted from as usual. Like the constraints on single cells, the limits are kept by the wave and enforced every time it's filled, until `clear_constraints` is called.
+    ///
+    /// An error is returned if the limits can't be satisfied, either because `min` is above `max`, the value isn't in the sample and `min` isn't 0, or (if the wave has been filled) the cells and constraints of the wave don't allow for them. The wave is left in its contradicted state in the latter case.
+    pub fn limit_count(&mut self, value: &T, min: usize, max: Option<usize>) -> Result<(), WaveError> {
+        let Some(id) = self.palette.id(value) else {
+            return match min {
+                0 => Ok(()),
+                _ => Err(WaveError::UnknownCountValue { min }),
+            };
+        };
+
+        if max.is_some_and(|max| min > max) {
+            return Err(WaveError::UnsatisfiableCount { id });
         }
+
+        self.count_limits.retain(|limit| limit.value != id);
+        self.count_limits.push(CountLimit { value: id, min, max });
+        self.counts = None;
+
+        if self.elements.is_empty() {
+            return Ok(());
+        }
+
+        self.enforce_counts()
     }
 
-    pub fn fill(&mut self, size: Vector2<usize>) -> Result<(), String> {
-        if self.debug {
-            println!("Filling superpositions with the following size: {:?}", size);
+    /// Forgets every constraint, including the count limits. This does not undo their effects on the current elements until the wave is filled again.
+    pub fn clear_constraints(&mut self) {
+        self.constraints.clear();
+        self.count_limits.clear();
+        self.broken_count = None;
+        self.counts = None;
+    }
+
+    fn add_constraint(&mut self, constraint: CellConstraint) -> Result<(), WaveError> {
+        if self.elements.is_empty() {
+            return Err(WaveError::NotFilled);
         }
 
-        if size.x % self.chunk_size.x != 0 {
-            return Err("The output width must be a factor of the chunk size".to_owned());
+        self.apply_constraint(&constraint)?;
+        self.constraints.push(constraint);
+
+        Ok(())
+    }
+
+    /// Removes the patterns which break the constraint from its element, and propagates the change.
+    fn apply_constraint(&mut self, constraint: &CellConstraint) -> Result<(), WaveError> {
+        let position = constraint.position;
+        let chunk = Vector3::new(
+            position.x / self.chunk_size.x,
+            position.y / self.chunk_size.y,
+            position.z / self.chunk_size.z,
+        );
+
+        if chunk.x >= self.chunk_fill_size.x
+            || chunk.y >= self.chunk_fill_size.y
+            || chunk.z >= self.chunk_fill_size.z
+        {
+            return Err(WaveError::OutOfBounds { position });
+        }
+
+        let offset = Vector3::new(
+            position.x % self.chunk_size.x,
+            position.y % self.chunk_size.y,
+            position.z % self.chunk_size.z,
+        );
+        let index = self.element_index(chunk);
+
+        let broken: Vec<usize> = self.elements[index]
+            .values
+            .iter()
+            .filter(|&id| {
+                let value = self.patterns[id].contents[offset.x][offset.y][offset.z];
+                constraint.values.contains(&value) == constraint.banned
+            })
+            .collect();
+
+        if broken.is_empty() {
+            return Ok(());
         }
 
-        if size.y % self.chunk_size.y != 0 {
-            return Err("The output height must be a factor of the chunk size".to_owned());
-        }
+        self.prune(index, broken);
+        self.settle(index);
 
-        self.elements.clear();
+        if let Some(id) = self.broken_count {
+            return Err(WaveError::UnsatisfiableCount { id });
+        }
 
-        let values_preset: Vec<Arc<Pattern>> = self
-            .patterns
-            .clone()
-            .into_iter()
-            .map(|p| Arc::new(p))
-            .collect();
+        if self.contradiction_occurred() {
+            return Err(WaveError::UnsatisfiableConstraint { position });
+        }
 
-        let chunk_fill_size = Vector2 {
-            x: size.x / self.chunk_size.x,
-            y: size.y / self.chunk_size.y,
-        };
+        Ok(())
+    }
 
-        for x in 0..chunk_fill_size.x {
-            for y in 0..chunk_fill_size.y {
-                let values = values_preset.clone();
-                let position = Vector2::new(x, y);
-                let element = Element::new(values, position);
-                self.elements.push(element);
-            }
+    /// Please note, the `Symmetry` and `Topology` must be set at this point for them to be registered.
+    ///
+    /// The values of the sample are interned into the wave's palette, replacing those of any previous analysis.
+    ///
+    /// An error is returned if the topology can't split the sample into chunks of the given size, such as a `Hex` grid with chunks bigger than a cell. The wave is left as it was in that case.
+    pub fn analyze(
+        &mut self,
+        input: Vec<Vec<T>>,
+        chunk_size: Vector2<usize>,
+        border_mode: BorderMode,
+    ) -> Result<(), WaveError> {
+        if !self.topology.supports_chunk_size(chunk_size) {
+            return Err(WaveError::UnsupportedChunkSize { chunk_size });
         }
 
-        self.chunk_fill_size = chunk_fill_size;
+        self.palette.clear();
+        let input = self.palette.intern_grid(input);
+
+        let adjacencies = self.topology.adjacencies(input, chunk_size, border_mode);
+        self.learn(adjacencies, chunk_size.extend(1));
 
         Ok(())
     }
 
-    /// Please note, the flag `Flag::NoTransforms` must be set at this point for it to be registered.
-    pub fn analyze(
+    /// Analyzes a volume the same way as `analyze`, with the sample indexed as `[x][y][z]` and split into chunks of the given size along all three axes.
+    ///
+    /// Patterns are learned with neighbours along the z axis as well, and the `Symmetry` only transforms them around the z axis, so up stays up. The wave is then filled with `fill_volume`.
+    pub fn analyze_volume(
         &mut self,
-        input: Vec<Vec<usize>>,
-        chunk_size: Vector2<usize>,
+        input: Vec<Vec<Vec<T>>>,
+        chunk_size: Vector3<usize>,
         border_mode: BorderMode,
     ) {
-        let adjacencies = overlapping_adjacencies(input.to_owned(), chunk_size, border_mode);
+        self.palette.clear();
+        let input = self.palette.intern_volume(input);
+
+        let adjacencies = volume::overlapping_adjacencies(input, chunk_size, border_mode);
+        self.learn(adjacencies, chunk_size);
+    }
+
+    /// Turns the adjacencies found in a sample into the wave's patterns, adding their transforms and building the propagator.
+    fn learn(&mut self, adjacencies: Vec<volume::Adjacency<usize>>, chunk_size: Vector3<usize>) {
         let initial_count = adjacencies.len();
 
-        let mut patterns = vec![];
-        patterns.reserve(adjacencies.len());
+        let mut patterns = Vec::with_capacity(adjacencies.len());
         let mut id_counter = 0usize;
 
         for adjacency in adjacencies {
-            let mut pattern = Pattern::new(id_counter, adjacency.origin_content.to_owned());
+            let mut pattern = Pattern::new(id_counter, adjacency.origin_content);
             id_counter += 1;
 
             for (i, neighbour) in adjacency.neighbours_content.into_iter().enumerate() {
@@ -535,95 +1478,142 @@ impl Wave {
         count_patterns(&mut patterns);
         dedup_patterns(&mut patterns);
 
-        if !self.flags.contains(&Flags::NoTransforms) {
-            let mut new_patterns: Vec<Pattern> = vec![];
+        let square = chunk_size.x == chunk_size.y;
+        let mut new_patterns: Vec<Pattern> = vec![];
 
-            // transform time!
-            for pattern in patterns.iter() {
-                let mut mirrored_x = pattern.to_owned();
-                mirrored_x.is_transform = true;
-                mirrored_x.id = id_counter;
-                id_counter += 1;
+        // transform time!
+        for pattern in patterns.iter() {
+            for (mirror, rotations) in self.topology.transforms(self.symmetry) {
+                if rotations % 2 == 1 && !square {
+                    continue;
+                }
 
-                let mut mirrored_y = pattern.to_owned();
-                mirrored_y.is_transform = true;
-                mirrored_y.id = id_counter;
+                let mut transformed =
+                    pattern.transformed(mirror, rotations, self.topology.as_ref());
+                transformed.id = id_counter;
                 id_counter += 1;
 
-                let mut combination = pattern.to_owned();
-                combination.is_transform = true;
-                combination.id = id_counter;
-                id_counter += 1;
+                new_patterns.push(transformed);
+            }
+        }
 
-                mirrored_x.contents.reverse();
+        patterns.append(&mut new_patterns);
 
-                for x_val in mirrored_y.contents.iter_mut() {
-                    x_val.reverse();
-                }
+        // Some patterns might have already been transformations of each others, and their transformations might have been transformations of other transformations. Suffice it to say, we'll dedup again.
+        dedup_patterns(&mut patterns);
 
-                combination.contents.reverse();
-                for x_val in combination.contents.iter_mut() {
-                    x_val.reverse();
-                }
+        self.patterns = patterns;
+        self.patterns_total = initial_count;
+        self.chunk_size = chunk_size;
 
-                let x_transf = |rule: &mut Rule| {
-                    // rule content must also be mirrored: AB CD EF -> FE DC BA
-                    rule.content.reverse();
+        if self.flags.contains(&Flags::PruneDeadweight) {
+            self.prune_lone_patterns();
+        }
 
-                    // only horizontal rules are reversed in place
-                    if rule.direction == 1 {
-                        rule.direction = 3
-                    } else if rule.direction == 3 {
-                        rule.direction = 1
-                    }
-                };
+        // Pattern ids are reassigned to their index so they can be used for lookups from here on out.
+        for (i, pattern) in self.patterns.iter_mut().enumerate() {
+            pattern.id = i;
+        }
 
-                let y_transf = |rule: &mut Rule| {
-                    // mirror rule content on y-axis
-                    for row in rule.content.iter_mut() {
-                        row.reverse();
-                    }
+        self.build_propagator();
+    }
 
-                    // swap top and bottom rules
-                    if rule.direction == 0 {
-                        rule.direction = 2
-                    } else if rule.direction == 2 {
-                        rule.direction = 0
-                    }
-                };
+    /// Sets the wave up with explicit tiles of a single cell each, rather than analyzing a sample. This is how a `TiledModel` creates its wave.
+    ///
+    /// Each value is given the weight at the same index, and each rule `(tile, direction, neighbour)` allows the neighbour in that direction of the tile, where both are indexes into the values.
+    pub(crate) fn load_tiles(
+        &mut self,
+        values: Vec<T>,
+        weights: &[f64],
+        rules: &[(usize, u8, usize)],
+    ) {
+        self.palette.clear();
 
-                for rule in &mut mirrored_x.rules {
-                    x_transf(rule);
-                }
+        let mut patterns: Vec<Pattern> = values
+            .into_iter()
+            .zip(weights)
+            .map(|(value, &weight)| {
+                let id = self.palette.intern(value);
+                let mut pattern = Pattern::new(id, vec![vec![vec![id]]]);
+                pattern.weight = Some(weight);
+                pattern
+            })
+            .collect();
 
-                for rule in &mut mirrored_y.rules {
-                    y_transf(rule);
-                }
+        for &(tile, direction, neighbour) in rules {
+            patterns[tile]
+                .rules
+                .push(Rule::new(direction, vec![vec![vec![neighbour]]]));
+        }
 
-                for rule in &mut combination.rules {
-                    x_transf(rule);
-                    y_transf(rule);
-                }
+        self.patterns_total = patterns.len();
+        self.patterns = patterns;
+        self.chunk_size = Vector3::new(1, 1, 1);
+        self.build_propagator();
+    }
 
-                new_patterns.append(&mut (vec![mirrored_x, mirrored_y, combination]));
-            }
+    /// Takes the rules the wave has learned, so they can be saved and loaded into another wave with `from_ruleset` later on.
+    pub fn ruleset(&self) -> Ruleset<T> {
+        let patterns = self
+            .patterns
+            .iter()
+            .zip(&self.propagator)
+            .map(|(pattern, directions)| RulePattern {
+                contents: pattern.contents.clone(),
+                count: pattern.count,
+                weight: pattern.weight,
+                compatible: directions.iter().map(|set| set.iter().collect()).collect(),
+            })
+            .collect();
 
-            patterns.append(&mut new_patterns);
+        Ruleset {
+            values: self.palette.values().to_vec(),
+            patterns,
+            chunk_size: self.chunk_size,
+            symmetry: self.symmetry,
+            topology: self.topology.clone(),
         }
+    }
 
-        // Some patterns might have already been transformations of each others, and their transformations might have been transformations of other transformations. Suffice it to say, we'll dedup again.
-        dedup_patterns(&mut patterns);
+    /// Creates a wave with the rules of a ruleset, as if it had analyzed the sample they were learned from. The wave is filled as usual afterwards.
+    ///
+    /// An error is returned if the ruleset is inconsistent, such as a pattern which allows a pattern that doesn't exist.
+    pub fn from_ruleset(ruleset: Ruleset<T>) -> Result<Self, WaveError> {
+        ruleset.validate()?;
 
-        self.patterns = patterns;
-        self.patterns_total = initial_count;
-        self.chunk_size = chunk_size;
+        let mut wave = Wave::new();
 
-        if self.flags.contains(&Flags::PruneDeadweight) {
-            self.prune_lone_patterns();
+        for value in ruleset.values {
+            wave.palette.intern(value);
+        }
+
+        let contents: Vec<_> = ruleset.patterns.iter().map(|p| p.contents.clone()).collect();
+        let mut propagator = Vec::with_capacity(ruleset.patterns.len());
+
+        for (id, rule_pattern) in ruleset.patterns.into_iter().enumerate() {
+            let mut pattern = Pattern::new(id, rule_pattern.contents);
+            pattern.count = rule_pattern.count;
+            pattern.weight = rule_pattern.weight;
+
+            // the rules are only kept for serializing the wave, as the propagator is loaded as is
+            for (direction, compatibles) in rule_pattern.compatible.iter().enumerate() {
+                for &compatible in compatibles {
+                    let rule = Rule::new(direction as u8, contents[compatible].clone());
+                    pattern.rules.push(rule);
+                }
+            }
+
+            wave.patterns.push(pattern);
+            propagator.push(rule_pattern.compatible);
         }
 
-        // Calculate pattern entropies ahead of time so it's not done repeatedly later.
-        self.calculate_pattern_entropies();
+        wave.patterns_total = wave.patterns.len();
+        wave.chunk_size = ruleset.chunk_size;
+        wave.symmetry = ruleset.symmetry;
+        wave.topology = ruleset.topology;
+        wave.load_propagator(propagator);
+
+        Ok(wave)
     }
 
     // Looking back on this function a year later, I'm not quite sure what it was intended to do.
@@ -638,34 +1628,85 @@ impl Wave {
         for (i, pattern) in self.patterns.iter().enumerate() {
             if pattern.rules.len() < 5 {
                 indexes_to_remove.push(i);
-                continue;
             }
+        }
+
+        for (removed, i) in indexes_to_remove.into_iter().enumerate() {
+            self.patterns.remove(i - removed);
+        }
+    }
+
+    /// Precomputes which patterns can neighbour each other in every direction from the patterns' rules.
+    ///
+    /// Compatibility is made symmetric: if a pattern allows another to its right, the other also allows it to its left. This is what lets `propagate` check each pair of neighbours from either side.
+    fn build_propagator(&mut self) {
+        let mut by_contents: HashMap<&Vec<Vec<Vec<usize>>>, Vec<usize>> = HashMap::new();
+
+        for pattern in &self.patterns {
+            by_contents.entry(&pattern.contents).or_default().push(pattern.id);
+        }
 
-            let mut found_directions = [false, false, false, false];
+        let directions = self.topology.directions();
+        let mut propagator: Vec<Vec<Vec<usize>>> =
+            vec![vec![vec![]; directions]; self.patterns.len()];
 
+        for pattern in &self.patterns {
             for rule in &pattern.rules {
-                found_directions[rule.direction as usize] = true;
+                let Some(compatibles) = by_contents.get(&rule.content) else {
+                    continue;
+                };
 
-                if found_directions.iter().all(|b| *b) {
-                    break;
+                for compatible in compatibles {
+                    propagator[pattern.id][rule.direction as usize].push(*compatible);
+                    propagator[*compatible][self.topology.opposite(rule.direction) as usize]
+                        .push(pattern.id);
                 }
             }
         }
 
-        let mut removed = 0usize;
+        self.load_propagator(propagator);
+    }
 
-        for i in indexes_to_remove {
-            self.patterns.remove(i - removed);
-            removed += 1;
-        }
+    /// Sets the propagator from lists of the compatible pattern ids of each pattern and direction.
+    fn load_propagator(&mut self, propagator: Vec<Vec<Vec<usize>>>) {
+        let total = self.patterns.len();
+
+        self.propagator = propagator
+            .into_iter()
+            .map(|directions| {
+                directions
+                    .into_iter()
+                    .map(|compatibles| {
+                        let mut set = BitSet::empty(total);
+
+                        for compatible in compatibles {
+                            set.insert(compatible);
+                        }
+
+                        set
+                    })
+                    .collect()
+            })
+            .collect();
     }
 
-    fn calculate_pattern_entropies(&mut self) {
-        let number_of_patterns = self.patterns.len();
+    /// Calculates the weights of the patterns ahead of time so it's not done repeatedly when calculating entropies later.
+    ///
+    /// Patterns are weighted by the number of times they occurred in the sample, unless `Flags::NoWeights` is set, in which case they're all weighted equally.
+    fn calculate_pattern_weights(&mut self) {
+        let use_weights = !self.flags.contains(&Flags::NoWeights);
 
-        for pattern in self.patterns.iter_mut() {
-            pattern.calculated_entropy = Some(pattern.calculate_entropy(number_of_patterns));
-        }
+        self.weights = self
+            .patterns
+            .iter()
+            .map(|p| {
+                PatternWeight::new(if use_weights {
+                    p.weight.unwrap_or(p.count as f64)
+                } else {
+                    1.
+                })
+            })
+            .collect();
     }
 }
 
@@ -688,10 +1729,8 @@ fn count_patterns(patterns: &mut Vec<Pattern>) {
                 continue;
             }
 
-            if pattern.contents == patcopy.contents {
-                if pattern.id != patcopy.id {
-                    pattern.count += 1;
-                }
+            if pattern.contents == patcopy.contents && pattern.id != patcopy.id {
+                pattern.count += 1;
             }
         }
     }
@@ -702,14 +1741,12 @@ fn dedup_patterns(patterns: &mut Vec<Pattern>) {
 
     for pattern in patterns.iter_mut() {
         for patcopy in &copy {
-            if pattern.contents == patcopy.contents {
-                if pattern.id != patcopy.id {
-                    // check duplicate's rules
-                    for rule in &patcopy.rules {
-                        // push the new rule
-                        if !pattern.rules.contains(&rule) {
-                            pattern.rules.push(rule.to_owned());
-                        }
+            if pattern.contents == patcopy.contents && pattern.id != patcopy.id {
+                // check duplicate's rules
+                for rule in &patcopy.rules {
+                    // push the new rule
+                    if !pattern.rules.contains(rule) {
+                        pattern.rules.push(rule.to_owned());
                     }
                 }
             }
@@ -728,7 +1765,7 @@ fn dedup_patterns(patterns: &mut Vec<Pattern>) {
 }
 
 // History Related Functions and Code
-impl Wave {
+impl<T: Eq + Hash + Clone> Wave<T> {
     /// Clears the wave's internal history log.
     pub fn clear_history(&mut self) {
         self.history.clear();
@@ -736,34 +1773,27 @@ impl Wave {
 
     /// Undo's the last collapse undertaken by the algorithm.
     ///
+    /// Everything the collapse removed is put back, including what its propagation pruned and anything else removed before the next collapse (such as constraints added in between), so the wave is left exactly as it was before it.
+    ///
     /// # Parameters:
     ///
-    /// * `remove_record`: Determines whether the operation being undone should be forgotten in the internal history log or remembered.
+    /// * `remove_record`: Determines whether the operation being undone should be forgotten in the internal history log or remembered so it can be redone.
     ///
     /// # Notes:
     ///
-    /// * If the internal history log for the `Wave` is empty, this function does nothing.
-    /// * If `remove_record` is set to true, if an error is returned, **DO NOT** attempt another undo.
-    ///     * In this situation, the last undo will have been marked as undone but an error occurred during this process. Undoing again will likely result in an error for a variety of reasons.
-    pub fn undo_collapse(&mut self, remove_record: bool) -> Result<(), String> {
-        // Don't include undone records in the eventuality `remove_record` was marked false.
-        let last_record = self
-            .history
-            .iter_mut()
-            .enumerate()
-            .filter(|r| !r.1.undone)
-            .last();
-
-        if let Some(record_info) = last_record {
-            let index = record_info.0;
+    /// * If there is nothing left to undo in the internal history log for the `Wave`, this function does nothing.
+    /// * Forgetting a record also forgets the records which were undone before it, as they can't be redone without it.
+    /// * An error is returned if the record doesn't fit the wave, in which case nothing is changed.
+    pub fn undo_collapse(&mut self, remove_record: bool) -> Result<(), WaveError> {
+        // Records which were undone and remembered are skipped, as their effects are already gone.
+        let Some(index) = self.history.iter().rposition(|r| !r.undone) else {
+            return Ok(());
+        };
 
-            record_info.1.undone = true;
-            let clone = record_info.1.clone();
-            self.reverse_record(clone)?;
+        self.reverse_record(index)?;
 
-            if remove_record {
-                self.history.remove(index);
-            }
+        if remove_record {
+            self.history.truncate(index);
         }
 
         Ok(())
@@ -773,98 +1803,238 @@ impl Wave {
     ///
     /// # Notes:
     ///
-    /// *
-    pub fn redo_collapse(&mut self) -> Result<(), String> {
-        let last_undone = self.history.iter_mut().find(|r| r.undone);
-
-        if let Some(undone_record) = last_undone {
-            undone_record.undone = false;
-            let copy = undone_record.clone();
-            self.execute_record(copy)?;
-        } else if let Some(last_record) = self.history.last_mut() {
-            let copy = last_record.clone();
-            self.execute_record(copy)?;
+    /// * The removals of the collapse are reapplied exactly as they were recorded, after which the count limits are enforced again as they are after any collapse. The wave is left the same as it was before the undo, unless the limits were changed in between.
+    /// * Collapsing again after an undo forgets the records that could have been redone.
+    pub fn redo_collapse(&mut self) -> Result<(), WaveError> {
+        if let Some(index) = self.history.iter().position(|r| r.undone) {
+            self.execute_record(index)?;
         }
 
         Ok(())
     }
 
-    /// Undos the previous collapse as described in the record.
+    /// Undos the collapse described by the record at the given index of the history, by restoring its trail.
     ///
     /// # Notes:
     ///
     /// * This function has the same quirks and behaviours that `execute_record` does due to their similar nature.
-    /// * This function decrements the internal iterations count.
-    fn reverse_record(&mut self, record: Record) -> Result<(), String> {
-        if record.iteration != self.iterations {
-            return Err(
-                "This record's iteration does not match the internal state of the Wave".to_string(),
-            );
+    /// * This function sets the internal iterations count back to the record's.
+    fn reverse_record(&mut self, index: usize) -> Result<(), WaveError> {
+        let record = &self.history[index];
+
+        if record.iteration + 1 != self.iterations {
+            return Err(WaveError::HistoryMismatch {
+                record_iteration: record.iteration,
+                wave_iteration: self.iterations,
+            });
         }
 
-        let element = self
-            .elements
-            .iter_mut()
-            .find(|e| e.position == record.location());
-        if element.is_none() {
-            return Err("Failed to find element at the specified location".to_string());
-        };
+        self.check_record(record)?;
 
-        let patterns: Vec<&Pattern> = self
-            .patterns
-            .iter()
-            .filter(|p| record.previous_pattern_ids.contains(&p.id))
-            .collect();
-        if patterns.is_empty() {
-            return Err("Failed to find any patterns for the record id. This is possible, but shouldn't happen with the `Wave` history functioning as intended.".to_string());
-        };
-        let references = patterns.into_iter().map(|p| Arc::new(p.clone())).collect();
+        // the trail is taken out while it's restored, as restoring needs the wave mutably
+        let trail = std::mem::take(&mut self.history[index].trail);
+        self.restore_trail(&trail);
 
-        element.unwrap().values = references;
-        self.iterations -= 1;
+        let record = &mut self.history[index];
+        record.trail = trail;
+        record.undone = true;
+        self.iterations = record.iteration;
 
         Ok(())
     }
 
-    /// Executes the given record by attempting to perform the predetermined collapse it contains.
+    /// Executes the record at the given index of the history by removing everything in its trail again.
     ///
     /// This function can fail and return an error if the given record has invalid values that don't match with the state of the wave.
     ///
     /// # Notes:
     ///
     /// * This function will fail if the record's iteration does not line up with the current iteration.
-    /// * This function has an inefficiency where, when searching for the pattern specified, it has to make a copy of it and create a new `Arc` to that copy.
-    ///     * This could be subverted by searching for a prexisting copy of a `Arc` to that specific pattern in the other elements. However, I'm guessing this would be time intensive. For now the memory trade-off is being prioritized over the processing trade-off.
-    fn execute_record(&mut self, record: Record) -> Result<(), String> {
+    /// * A limit which can't be met once the record is executed is left as a contradiction, like it is when collapsing.
+    fn execute_record(&mut self, index: usize) -> Result<(), WaveError> {
+        let record = &self.history[index];
+
         if record.iteration != self.iterations {
-            return Err(
-                "This record's iteration does not match the internal state of the Wave".to_string(),
-            );
+            return Err(WaveError::HistoryMismatch {
+                record_iteration: record.iteration,
+                wave_iteration: self.iterations,
+            });
         }
 
-        let element = self
-            .elements
-            .iter_mut()
-            .find(|e| e.position == record.location());
-        if element.is_none() {
-            return Err("Failed to find element at the specified location".to_string());
-        };
+        self.check_record(record)?;
 
-        let pattern = self
-            .patterns
-            .iter()
-            .find(|p| p.id == record.chosen_pattern_id);
-        if pattern.is_none() {
-            return Err("Failed to find pattern for the record id".to_string());
+        let record = &mut self.history[index];
+
+        for (element_index, removed) in &record.trail {
+            for &id in removed {
+                let was_present = self.elements[*element_index].remove(id, &self.weights);
+
+                if let (true, Some(counts)) = (was_present, &mut self.counts) {
+                    counts.remove(*element_index, id);
+                }
+            }
+        }
+
+        record.undone = false;
+        self.iterations = record.iteration + 1;
+
+        // the limits may have changed since the record was undone, and a broken one has to be found again either way
+        let _ = self.enforce_counts();
+
+        Ok(())
+    }
+
+    /// The decisions which led the wave to its current state since it was filled, which can be replayed later with `replay`.
+    ///
+    /// Collapses which have been undone are left out. An error is returned if the wave has no patterns or hasn't been filled, or if its history is disabled with the `NoHistory` flag.
+    pub fn generation_log(&self) -> Result<GenerationLog, WaveError> {
+        self.check_collapsible()?;
+
+        if self.flags.contains(&Flags::NoHistory) {
+            return Err(WaveError::HistoryDisabled);
+        }
+
+        let banned = |&(position, pattern): &([usize; 3], usize)| Decision::Banned {
+            position: position.into(),
+            pattern,
         };
-        let reference = Arc::new(pattern.unwrap().clone());
 
-        element.unwrap().values = vec![reference];
+        let mut decisions: Vec<Decision> = self.bans.iter().map(banned).collect();
+
+        for record in self.history.iter().take_while(|r| !r.undone) {
+            decisions.push(Decision::Observed {
+                position: record.location(),
+                pattern: record.chosen_pattern_id,
+            });
+            decisions.extend(record.bans.iter().map(banned));
+        }
+
+        Ok(GenerationLog {
+            seed: self.seed,
+            ruleset: self.ruleset().fingerprint(),
+            size: self.true_size(),
+            periodic: self.periodic.into(),
+            constraints: self.constraints.clone(),
+            count_limits: self.count_limits.clone(),
+            decisions,
+        })
+    }
+
+    /// Rebuilds the state a wave was in by replaying its `GenerationLog`, either completely or only its first `until` steps.
+    ///
+    /// The wave takes the seed, size, wrapping, constraints and count limits of the log and is filled with them, after which the decisions are made again in order, so the wave has to have the same rules as the one the log was taken from. The replayed collapses are recorded as usual, so they can be undone and the wave can carry on collapsing from the last step replayed.
+    ///
+    /// Any constraints and count limits the wave already had are replaced by those of the log rather than added to, as the decisions were only made under the log's. They can be added again after the replay to carry on collapsing with them.
+    ///
+    /// An error is returned if the rules' fingerprints don't match, if the wave can't be filled, or if a step can't be made on the wave as it is at that point.
+    pub fn replay(&mut self, log: &GenerationLog, until: Option<usize>) -> Result<(), WaveError> {
+        let fingerprint = self.ruleset().fingerprint();
+
+        if fingerprint != log.ruleset {
+            return Err(WaveError::RulesetMismatch {
+                expected: log.ruleset,
+                found: fingerprint,
+            });
+        }
+
+        self.set_seed(log.seed);
+        self.set_periodic(log.periodic[0], log.periodic[1]);
+        self.constraints = log.constraints.clone();
+        self.count_limits = log.count_limits.clone();
+        self.fill_volume(log.size)?;
+
+        let steps = until.map_or(log.len(), |until| until.min(log.len()));
+
+        for (step, decision) in log.decisions[..steps].iter().enumerate() {
+            let position = decision.position();
+            let fill = self.chunk_fill_size;
+            let in_bounds = position.x < fill.x && position.y < fill.y && position.z < fill.z;
+
+            if !in_bounds
+                || !self.elements[self.element_index(position)]
+                    .values
+                    .contains(decision.pattern())
+            {
+                return Err(WaveError::InvalidLogStep { step });
+            }
+
+            let index = self.element_index(position);
+
+            match *decision {
+                Decision::Observed { pattern, .. } => self.observe(index, pattern),
+                Decision::Banned { pattern, .. } => self.ban_pattern(index, pattern),
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Checks that the record only refers to elements and patterns which exist in the wave.
+    fn check_record(&self, record: &Record) -> Result<(), WaveError> {
+        let fits = record.chosen_pattern_id < self.patterns.len()
+            && record.trail.iter().all(|(element_index, removed)| {
+                *element_index < self.elements.len()
+                    && removed.iter().all(|&id| id < self.patterns.len())
+            });
+
+        if !fits {
+            return Err(WaveError::InvalidRecord {
+                position: record.location(),
+            });
+        }
 
         Ok(())
     }
 }
 
+/// A restriction on the sample values a single cell of the output can take.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+struct CellConstraint {
+    position: Vector3<usize>,
+    /// The palette ids of the values.
+    values: Vec<usize>,
+    /// Whether the values are banned from the cell, rather than being the only ones allowed.
+    banned: bool,
+}
+
+/// A limit on the number of cells of the output which can be a value.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+struct CountLimit {
+    /// The palette id of the value.
+    value: usize,
+    min: usize,
+    max: Option<usize>,
+}
+
+/// Mixes a part into a seed with the generator of splitmix64, so every part gives an unrelated seed. This is how workers of `collapse_parallel` and the pieces of a `ChunkedGenerator` get their seeds.
+pub(crate) fn mix_seed(seed: u64, part: u64) -> u64 {
+    let mut z = seed.wrapping_add(part.wrapping_mul(0x9e3779b97f4a7c15));
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+    z ^ (z >> 31)
+}
+
+/// Brings a coordinate back within `0..size`, wrapping it around if the axis is periodic. Returns `None` if it's out of bounds otherwise.
+fn wrap_axis(coordinate: isize, size: usize, periodic: bool) -> Option<usize> {
+    if periodic {
+        Some(coordinate.rem_euclid(size as isize) as usize)
+    } else if coordinate >= 0 && (coordinate as usize) < size {
+        Some(coordinate as usize)
+    } else {
+        None
+    }
+}
+
+/// The layer of a volume where z is 0, which is the whole of a planar wave's output.
+fn bottom_layer<V>(volume: Vec<Vec<Vec<V>>>) -> Vec<Vec<V>> {
+    volume
+        .into_iter()
+        .map(|column| column.into_iter().map(|mut stack| stack.swap_remove(0)).collect())
+        .collect()
+}
+
 #[derive(Debug, Clone)]
 #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 struct Pattern {
@@ -872,40 +2042,72 @@ struct Pattern {
     is_transform: bool,
     /// Count is representative of the number of occurences a pattern had in the original source input.
     count: usize,
-    contents: Vec<Vec<usize>>,
+    /// A weight given explicitly, which is used instead of the count. Only set for the tiles of a `TiledModel`.
+    weight: Option<f64>,
+    /// The palette ids of the pattern's chunk, indexed as `[x][y][z]`.
+    contents: Vec<Vec<Vec<usize>>>,
     rules: Vec<Rule>,
-    calculated_entropy: Option<f32>,
 }
 
 impl Pattern {
-    fn new(id: usize, contents: Vec<Vec<usize>>) -> Self {
+    fn new(id: usize, contents: Vec<Vec<Vec<usize>>>) -> Self {
         Pattern {
             id,
             is_transform: false,
             count: 1,
+            weight: None,
             contents,
             rules: vec![],
-            calculated_entropy: None,
         }
     }
 
-    fn calculate_entropy(&self, patterns_total: usize) -> f32 {
-        // https://arc.net/l/quote/zqcrryti
-            
-        // number of outcomes / total outcomes
-        // Must be constrained to the interval [0, 1]
-        let probability = self.count as f32 / patterns_total as f32;
+    /// Creates a transformed copy of this pattern, mirrored horizontally (if `mirror` is set) and then rotated `rotations` times, by 90° on a square grid.
+    ///
+    /// The contents of the rules are transformed in the same way, and their directions are remapped to match by the topology. Only square grids have chunks larger than a single cell, so the contents are always rotated as squares.
+    fn transformed(&self, mirror: bool, rotations: usize, topology: &dyn Topology) -> Self {
+        let transform = |mut contents: Vec<Vec<Vec<usize>>>| {
+            if mirror {
+                contents.reverse();
+            }
+
+            rotate_ninety(contents, rotations)
+        };
+
+        let mut pattern = self.to_owned();
+        pattern.is_transform = true;
+        pattern.contents = transform(pattern.contents);
+
+        for rule in pattern.rules.iter_mut() {
+            rule.content = transform(std::mem::take(&mut rule.content));
+            rule.direction = topology.transform(rule.direction, mirror, rotations);
+        }
+
+        pattern
+    }
+}
+
+/// A pattern's weight, alongside the weight multiplied by its own logarithm. Both are summed up by elements to work out their entropy.
+#[derive(Debug, Clone, Copy)]
+struct PatternWeight {
+    weight: f64,
+    weight_log_weight: f64,
+}
 
-        // H(x) = -p*log2(p)
-        // https://youtu.be/YtebGVx-Fxw?si=RXElFTvCOnrsnct9
-        // Just graph this H(x) to get a better idea of how this works.
-        probability * (probability).log2() * -1f32
+impl PatternWeight {
+    fn new(weight: f64) -> Self {
+        Self {
+            weight,
+            weight_log_weight: weight * weight.log2(),
+        }
     }
 }
 
 impl PartialEq for Pattern {
     fn eq(&self, other: &Self) -> bool {
-        self.contents == other.contents && self.rules == other.rules && self.count == other.count
+        self.contents == other.contents
+            && self.rules == other.rules
+            && self.count == other.count
+            && self.weight == other.weight
     }
 }
 
@@ -913,7 +2115,7 @@ impl Eq for Pattern {}
 
 impl PartialOrd for Pattern {
     fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
-        Some(self.contents.cmp(&other.contents))
+        Some(self.cmp(other))
     }
 }
 
@@ -930,48 +2132,102 @@ struct Rule {
     /// 0: up
     /// 1: right
     /// 2: down
+    /// 3: left
+    /// 4 and 5: adding and subtracting z, for volumes
+    ///
+    /// Topologies other than `Square` number their directions differently.
     direction: u8,
     /// The valid neighbour for the originating pattern of this rule.
-    content: Vec<Vec<usize>>,
+    content: Vec<Vec<Vec<usize>>>,
 }
 
 impl Rule {
-    fn new(direction: u8, content: Vec<Vec<usize>>) -> Self {
+    fn new(direction: u8, content: Vec<Vec<Vec<usize>>>) -> Self {
         Self { direction, content }
     }
 }
 
 #[derive(Clone)]
 struct Element {
-    values: Vec<Arc<Pattern>>,
-    position: Vector2<usize>,
+    /// The ids of the patterns this element could still become.
+    values: BitSet,
+    /// The summed weights of the patterns still in `values`, kept up to date as patterns are removed.
+    weight_sum: f64,
+    /// The summed `weight * log2(weight)` of the patterns still in `values`, kept up to date as patterns are removed.
+    weight_log_weight_sum: f64,
+    /// The Shannon entropy of the element, recalculated from the sums above whenever they change.
+    entropy: f64,
+    position: Vector3<usize>,
 }
 
 impl Element {
-    fn new(values: Vec<Arc<Pattern>>, position: Vector2<usize>) -> Self {
-        Self { values, position }
+    fn new(values: BitSet, position: Vector3<usize>, weights: &[PatternWeight]) -> Self {
+        let mut element = Self {
+            values: BitSet::empty(weights.len()),
+            weight_sum: 0.,
+            weight_log_weight_sum: 0.,
+            entropy: 0.,
+            position,
+        };
+
+        element.set_values(values, weights);
+        element
+    }
+
+    /// Replaces the element's values, recalculating its cached sums from scratch.
+    fn set_values(&mut self, values: BitSet, weights: &[PatternWeight]) {
+        self.weight_sum = values.iter().map(|v| weights[v].weight).sum();
+        self.weight_log_weight_sum = values.iter().map(|v| weights[v].weight_log_weight).sum();
+        self.values = values;
+        self.update_entropy();
+    }
+
+    /// Removes a single value from the element, returning whether it was present.
+    fn remove(&mut self, value: usize, weights: &[PatternWeight]) -> bool {
+        if !self.values.remove(value) {
+            return false;
+        }
+
+        self.weight_sum -= weights[value].weight;
+        self.weight_log_weight_sum -= weights[value].weight_log_weight;
+        self.update_entropy();
+
+        true
     }
 
-    // I am conflicted as to whether this is the best way to implement entropy into the algorithm.
-    // Should entropy be embedded as a value in Element, itself? Would that be more efficient?
-    // How can I reduce the number of entropy calculations during each collapse cycle.
-    // The problem is that the entropy of the element changes during each collapse.
-    // However, elements that have already been collapsed will have a fixed entropy of 
+    /// Puts values which were removed back into the element, recalculating its cached sums.
+    fn restore(&mut self, values: &[usize], weights: &[PatternWeight]) {
+        let mut restored = self.values.clone();
 
-    // Copying patterns directly or having it passed is more efficient than simply giving a reference,
-    // as the reference size and argument size are equal at usize.
-    fn entropy(&self) -> f32 {
-        if self.values.is_empty() {
-            return 0f32;
+        for &value in values {
+            restored.insert(value);
         }
 
-        let mut total = 0f32;
+        self.set_values(restored, weights);
+    }
+
+    /// Removes every value from the element except the one given.
+    fn collapse_to(&mut self, value: usize, weights: &[PatternWeight]) {
+        let mut values = BitSet::empty(weights.len());
+        values.insert(value);
+        self.set_values(values, weights);
+    }
 
-        for pattern in self.values.iter() {
-            total += pattern.calculated_entropy.expect("The pattern did not have a precalculated entropy value.");
+    // The entropy of the remaining patterns, where each pattern's probability is its weight over the summed weights (W):
+    // H = -sum(p * log2(p)) = log2(W) - sum(w * log2(w)) / W
+    // Caching the two sums means removing a pattern doesn't require going over all the others again.
+    fn update_entropy(&mut self) {
+        if self.values.len() <= 1 {
+            // Floating point error could otherwise leave a tiny, non-zero entropy behind.
+            self.entropy = 0.;
+            return;
         }
 
-        total
+        self.entropy = self.weight_sum.log2() - self.weight_log_weight_sum / self.weight_sum;
+    }
+
+    fn entropy(&self) -> f64 {
+        self.entropy
     }
 
     fn is_collapsed(&self) -> bool {
@@ -982,94 +2238,257 @@ impl Element {
 #[derive(Clone)]
 #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 struct Record {
-    element_location: [usize; 2],
+    element_location: [usize; 3],
     /// An index in the patterns of the Wave for the selected value
     chosen_pattern_id: usize,
-    previous_pattern_ids: Vec<usize>,
     /// The current `Wave` iteration when the record was made.
     iteration: usize,
     undone: bool,
+    /// Whether the collapse is older than the backtracking limits allow rolling back. It can still be undone by hand.
+    committed: bool,
+    /// Every removal made since the collapse, starting with the values it took out of its own element, as the index of an element and the pattern ids removed from it.
+    trail: Vec<(usize, Vec<usize>)>,
+    /// Patterns banned by backtracking out of later collapses, as the position of their element and the pattern id.
+    bans: Vec<([usize; 3], usize)>,
 }
 
 impl Record {
-    fn new(
-        location: Vector2<usize>,
-        chosen_pattern_id: usize,
-        previous_pattern_ids: Vec<usize>,
-        iteration: usize,
-    ) -> Self {
+    fn new(location: Vector3<usize>, chosen_pattern_id: usize, iteration: usize) -> Self {
         Record {
-            element_location: [location.x, location.y],
+            element_location: location.into(),
             chosen_pattern_id,
-            previous_pattern_ids,
             iteration,
             undone: false,
+            committed: false,
+            trail: vec![],
+            bans: vec![],
         }
     }
 
-    fn location(&self) -> Vector2<usize> {
-        Vector2::new(self.element_location[0], self.element_location[1])
+    fn location(&self) -> Vector3<usize> {
+        self.element_location.into()
     }
 }
 
 #[cfg(feature = "serde")]
 mod wave_serialization {
     use super::*;
+    use crate::ruleset::{check_chunk_size, check_pattern};
+    use crate::topology::{deserialize_topology, SerializedTopology};
+    use serde::de::Error as _;
+    use std::any::Any;
+
+    /// The part of an element which can't be worked out again from the patterns. Its cached sums are recalculated when deserializing.
+    #[derive(Serialize, Deserialize)]
+    struct SerializedElement {
+        values: BitSet,
+        position: Vector3<usize>,
+    }
 
-    impl Serialize for Wave {
+    impl<T: Serialize + Eq + Hash + Clone> Serialize for Wave<T> {
         fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
         where
             S: serde::Serializer,
         {
-            let mut state = serializer.serialize_struct("Wave", 7)?;
+            let elements: Vec<SerializedElement> = self
+                .elements
+                .iter()
+                .map(|e| SerializedElement {
+                    values: e.values.clone(),
+                    position: e.position,
+                })
+                .collect();
+
+            let mut state = serializer.serialize_struct("Wave", 20)?;
             state.serialize_field("flags", &self.flags)?;
+            state.serialize_field("palette", self.palette.values())?;
             state.serialize_field("patterns", &self.patterns)?;
             state.serialize_field("patterns_total", &self.patterns_total)?;
-            state.serialize_field("chunk_size", &[self.chunk_size.x, self.chunk_size.y])?;
-            state.serialize_field(
-                "chunk_fill_size",
-                &[self.chunk_fill_size.x, self.chunk_fill_size.y],
-            )?;
+            state.serialize_field("heuristic", &self.heuristic)?;
+            state.serialize_field("symmetry", &self.symmetry)?;
+            state.serialize_field("topology", &SerializedTopology(self.topology.as_ref()))?;
+            state.serialize_field("backtracking", &self.backtracking)?;
+            state.serialize_field("periodic", &[self.periodic.x, self.periodic.y])?;
+            state.serialize_field("constraints", &self.constraints)?;
+            state.serialize_field("count_limits", &self.count_limits)?;
+            state.serialize_field("elements", &elements)?;
+            let chunk_size: [usize; 3] = self.chunk_size.into();
+            let chunk_fill_size: [usize; 3] = self.chunk_fill_size.into();
+            state.serialize_field("chunk_size", &chunk_size)?;
+            state.serialize_field("chunk_fill_size", &chunk_fill_size)?;
             state.serialize_field("history", &self.history)?;
+            state.serialize_field("bans", &self.bans)?;
             state.serialize_field("iterations", &self.iterations)?;
+            state.serialize_field("debug", &self.debug)?;
+            state.serialize_field("seed", &self.seed)?;
+            state.serialize_field("rng", &self.rng)?;
             state.end()
         }
     }
 
-    struct WaveVisitor;
-
-    impl<'de> Visitor<'de> for WaveVisitor {
-        type Value = Wave;
-
-        fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
-            write!(formatter, "expecting wave object")
-        }
-    }
-
-    impl Default for WaveVisitor {
-        fn default() -> Self {
-            Self {}
-        }
+    /// The fields of a serialized wave, in the same order as they're serialized in so formats without field names can read them back.
+    #[derive(Deserialize)]
+    #[serde(rename = "Wave")]
+    struct WaveFields<T> {
+        flags: Vec<Flags>,
+        palette: Vec<T>,
+        patterns: Vec<Pattern>,
+        patterns_total: usize,
+        heuristic: SelectionHeuristic,
+        symmetry: Symmetry,
+        #[serde(deserialize_with = "deserialize_topology")]
+        topology: Arc<dyn Topology>,
+        backtracking: Option<BacktrackLimits>,
+        periodic: [bool; 2],
+        constraints: Vec<CellConstraint>,
+        count_limits: Vec<CountLimit>,
+        elements: Vec<SerializedElement>,
+        chunk_size: [usize; 3],
+        chunk_fill_size: [usize; 3],
+        history: Vec<Record>,
+        bans: Vec<([usize; 3], usize)>,
+        iterations: usize,
+        debug: bool,
+        seed: u64,
+        rng: ChaCha12Rng,
     }
 
-    impl<'de> Deserialize<'de> for Wave {
+    impl<'de, T> Deserialize<'de> for Wave<T>
+    where
+        T: Deserialize<'de> + Eq + Hash + Clone,
+    {
         fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
         where
             D: serde::Deserializer<'de>,
         {
-            deserializer.deserialize_struct(
-                "Wave",
-                &[
-                    "flags",
-                    "patterns",
-                    "patterns_total",
-                    "chunk_size",
-                    "chunk_fill_size",
-                    "history",
-                    "iterations",
-                ],
-                WaveVisitor::default(),
-            )
+            let fields = WaveFields::<T>::deserialize(deserializer)?;
+
+            let palette_len = fields.palette.len();
+            let mut palette = Palette::new();
+
+            for value in fields.palette {
+                palette.intern(value);
+            }
+
+            let mut wave = Wave {
+                flags: fields.flags,
+                palette,
+                patterns: fields.patterns,
+                patterns_total: fields.patterns_total,
+                propagator: vec![],
+                links: vec![],
+                weights: vec![],
+                heuristic: fields.heuristic,
+                symmetry: fields.symmetry,
+                topology: fields.topology,
+                backtracking: fields.backtracking,
+                periodic: Vector2::new(fields.periodic[0], fields.periodic[1]),
+                constraints: fields.constraints,
+                count_limits: fields.count_limits,
+                broken_count: None,
+                counts: None,
+                events: None,
+                budget: CollapseBudget::default(),
+                cancel_token: None,
+                parallel_stop: None,
+                elements: vec![],
+                chunk_size: fields.chunk_size.into(),
+                chunk_fill_size: fields.chunk_fill_size.into(),
+                history: fields.history,
+                bans: fields.bans,
+                iterations: fields.iterations,
+                debug: fields.debug,
+                seed: fields.seed,
+                rng: fields.rng,
+            };
+
+            if wave.palette.len() != palette_len {
+                return Err(D::Error::custom("the palette has the same value more than once"));
+            }
+
+            // the patterns are checked the same way as those of a ruleset, as the rest of the wave indexes into them without checking
+            check_chunk_size(wave.chunk_size).map_err(D::Error::custom)?;
+            let directions = wave.topology.directions();
+
+            for (i, pattern) in wave.patterns.iter().enumerate() {
+                if pattern.id != i {
+                    return Err(D::Error::custom("the patterns aren't in the order of their ids"));
+                }
+
+                let weight = pattern.weight.unwrap_or(pattern.count as f64);
+                check_pattern(i, &pattern.contents, weight, wave.chunk_size, wave.palette.len())
+                    .map_err(D::Error::custom)?;
+
+                let outside = pattern.rules.iter().find(|r| r.direction as usize >= directions);
+
+                if let Some(rule) = outside {
+                    return Err(D::Error::custom(format!(
+                        "pattern {} has a rule for direction {}, but the topology has {}",
+                        i, rule.direction, directions
+                    )));
+                }
+            }
+
+            // the propagator and weights are worked out from the patterns, the same way as when analyzing and filling
+            wave.build_propagator();
+            wave.calculate_pattern_weights();
+
+            let count = wave.patterns.len();
+            let fill = wave.chunk_fill_size;
+            let expected = fill.x.checked_mul(fill.y).and_then(|n| n.checked_mul(fill.z));
+
+            if expected != Some(fields.elements.len()) {
+                return Err(D::Error::invalid_length(
+                    fields.elements.len(),
+                    &"an element for every chunk of the wave",
+                ));
+            }
+
+            // a graph finds the neighbours of its elements by their node, so it needs an element for every node and no more
+            let topology: &dyn Any = wave.topology.as_ref();
+
+            if let Some(graph) = topology.downcast_ref::<Graph>() {
+                if !fields.elements.is_empty() && fill != Vector3::new(graph.len(), 1, 1) {
+                    return Err(D::Error::custom(
+                        "the wave doesn't have an element for every node of its graph",
+                    ));
+                }
+            }
+
+            for (index, element) in fields.elements.into_iter().enumerate() {
+                let position = element.position;
+                let inside = position.x < fill.x && position.y < fill.y && position.z < fill.z;
+
+                if !inside || wave.element_index(position) != index {
+                    let message = format!("element {} isn't at its own position", index);
+                    return Err(D::Error::custom(message));
+                }
+
+                // the set is rebuilt rather than trusted, as its cached length could be wrong
+                let mut values = BitSet::empty(count);
+
+                for id in element.values.iter() {
+                    if id >= count {
+                        let message = format!("an element has the unknown pattern {}", id);
+                        return Err(D::Error::custom(message));
+                    }
+
+                    values.insert(id);
+                }
+
+                let element = Element::new(values, element.position, &wave.weights);
+                wave.elements.push(element);
+            }
+
+            wave.link_elements();
+
+            if wave.history.iter().any(|r| wave.check_record(r).is_err()) {
+                return Err(D::Error::custom(
+                    "a history record refers to an element or pattern which doesn't exist",
+                ));
+            }
+
+            Ok(wave)
         }
     }
 }
@@ -0,0 +1,82 @@
+use std::collections::HashMap;
+use std::hash::Hash;
+
+/// The distinct values found in a sample, each given an id in the order they were first seen.
+///
+/// A `Wave` interns the values of its sample into its palette during `analyze`, and works with the ids from then on. The values are only looked up again at the edges, such as in `Wave::perfect_rep`.
+#[derive(Debug, Clone)]
+pub struct Palette<T> {
+    values: Vec<T>,
+    ids: HashMap<T, usize>,
+}
+
+impl<T> Default for Palette<T> {
+    fn default() -> Self {
+        Self {
+            values: vec![],
+            ids: HashMap::new(),
+        }
+    }
+}
+
+impl<T: Eq + Hash + Clone> Palette<T> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the id of the value, adding it to the palette if it isn't there yet.
+    pub fn intern(&mut self, value: T) -> usize {
+        if let Some(&id) = self.ids.get(&value) {
+            return id;
+        }
+
+        let id = self.values.len();
+        self.ids.insert(value.clone(), id);
+        self.values.push(value);
+
+        id
+    }
+
+    /// Interns every value of a grid, returning the grid of their ids.
+    pub fn intern_grid(&mut self, grid: Vec<Vec<T>>) -> Vec<Vec<usize>> {
+        grid.into_iter()
+            .map(|column| column.into_iter().map(|v| self.intern(v)).collect())
+            .collect()
+    }
+
+    /// Interns every value of a volume, returning the volume of their ids.
+    pub fn intern_volume(&mut self, volume: Vec<Vec<Vec<T>>>) -> Vec<Vec<Vec<usize>>> {
+        volume
+            .into_iter()
+            .map(|layer| self.intern_grid(layer))
+            .collect()
+    }
+
+    /// The id of the value, if it's in the palette.
+    pub fn id(&self, value: &T) -> Option<usize> {
+        self.ids.get(value).copied()
+    }
+
+    /// The value with the given id.
+    pub fn value(&self, id: usize) -> Option<&T> {
+        self.values.get(id)
+    }
+
+    /// The values, indexed by their ids.
+    pub fn values(&self) -> &[T] {
+        &self.values
+    }
+
+    pub fn len(&self) -> usize {
+        self.values.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.values.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.values.clear();
+        self.ids.clear();
+    }
+}
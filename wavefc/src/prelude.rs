@@ -2,7 +2,28 @@
 
 pub use crate::helpers::dimensions_of;
 pub use crate::stringtools::*;
+pub use crate::BacktrackLimits;
 pub use crate::BorderMode;
+pub use crate::ChunkedGenerator;
+pub use crate::CollapseBudget;
+pub use crate::CollapseEvent;
+pub use crate::CollapseStats;
+pub use crate::Decision;
 pub use crate::Flags;
+pub use crate::GenerationLog;
+pub use crate::Graph;
+pub use crate::GraphModel;
+pub use crate::Hex;
+pub use crate::Palette;
+pub use crate::RulePattern;
+pub use crate::Ruleset;
+pub use crate::SelectionHeuristic;
+pub use crate::Square;
+pub use crate::Symmetry;
+pub use crate::TileSymmetry;
+pub use crate::TileVariant;
+pub use crate::TiledModel;
+pub use crate::Topology;
 pub use crate::Wave;
-pub use cgmath::Vector2;
+pub use crate::WaveError;
+pub use cgmath::{Vector2, Vector3};
@@ -0,0 +1,85 @@
+use crate::{CellConstraint, CountLimit};
+use cgmath::Vector3;
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+/// The decisions which led a `Wave` from being filled to its current state, so the same output can be rebuilt from the same rules without collapsing it again.
+///
+/// A log is taken from a wave with `Wave::generation_log`, which needs the wave's history, and is replayed with `Wave::replay`, either all the way or up to any of its steps. Along with the decisions, it keeps the seed, the size the wave was filled with, whether it wraps around, its constraints and count limits, as well as the fingerprint of the rules (see `Ruleset::fingerprint`) so it's only ever replayed with the rules it was made with. With the `serde` feature enabled, it can be saved to a file like a `Ruleset`.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct GenerationLog {
+    pub(crate) seed: u64,
+    pub(crate) ruleset: u64,
+    pub(crate) size: Vector3<usize>,
+    pub(crate) periodic: [bool; 2],
+    pub(crate) constraints: Vec<CellConstraint>,
+    pub(crate) count_limits: Vec<CountLimit>,
+    pub(crate) decisions: Vec<Decision>,
+}
+
+/// A single step of a `GenerationLog`.
+///
+/// Positions are those of the wave's elements (one per chunk of the output), and patterns are ids into the rules.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum Decision {
+    /// An element was collapsed to a pattern.
+    Observed {
+        position: Vector3<usize>,
+        pattern: usize,
+    },
+    /// A pattern was banned from an element after backtracking out of a collapse to it.
+    Banned {
+        position: Vector3<usize>,
+        pattern: usize,
+    },
+}
+
+impl GenerationLog {
+    /// The seed of the wave the log was taken from. Replaying sets it on the wave, so collapsing any further after a partial replay is seeded as well.
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    /// The fingerprint of the rules the log was made with.
+    pub fn ruleset_fingerprint(&self) -> u64 {
+        self.ruleset
+    }
+
+    /// The size of the output in cells.
+    pub fn size(&self) -> Vector3<usize> {
+        self.size
+    }
+
+    /// The decisions in the order they were made.
+    pub fn decisions(&self) -> &[Decision] {
+        &self.decisions
+    }
+
+    /// The number of steps, which is the number of decisions.
+    pub fn len(&self) -> usize {
+        self.decisions.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.decisions.is_empty()
+    }
+}
+
+impl Decision {
+    /// The position of the element the decision was made for.
+    pub fn position(&self) -> Vector3<usize> {
+        match *self {
+            Decision::Observed { position, .. } | Decision::Banned { position, .. } => position,
+        }
+    }
+
+    /// The pattern which was chosen or banned.
+    pub fn pattern(&self) -> usize {
+        match *self {
+            Decision::Observed { pattern, .. } | Decision::Banned { pattern, .. } => pattern,
+        }
+    }
+}
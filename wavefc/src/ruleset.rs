@@ -0,0 +1,262 @@
+use crate::{Symmetry, Topology, WaveError};
+use cgmath::Vector3;
+use std::collections::HashSet;
+use std::hash::{Hash, Hasher};
+use std::sync::Arc;
+
+#[cfg(feature = "serde")]
+use crate::topology::{deserialize_topology, SerializedTopology};
+#[cfg(feature = "serde")]
+use serde::{de::Error as _, ser::SerializeStruct, Deserialize, Serialize};
+
+/// The version of the serialized form of a `Ruleset`, which is raised whenever it changes. Rulesets of other versions can't be deserialized.
+pub const RULESET_VERSION: u32 = 1;
+
+/// The rules a `Wave` has learned, separate from the wave itself, so they can be saved and loaded again without analyzing the sample every time.
+///
+/// A ruleset is taken from a wave with `Wave::ruleset`, and turned back into one with `Wave::from_ruleset`. With the `serde` feature enabled, it can be serialized to any format serde supports, such as JSON for reading it or CBOR for keeping it small. The serialized form carries a version, and is checked for consistency when it's read back in.
+#[derive(Debug, Clone)]
+pub struct Ruleset<T> {
+    pub(crate) values: Vec<T>,
+    pub(crate) patterns: Vec<RulePattern>,
+    pub(crate) chunk_size: Vector3<usize>,
+    pub(crate) symmetry: Symmetry,
+    pub(crate) topology: Arc<dyn Topology>,
+}
+
+/// A pattern of a `Ruleset`, along with the patterns which can neighbour it.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct RulePattern {
+    /// The palette ids of the pattern's chunk, indexed as `[x][y][z]`.
+    pub contents: Vec<Vec<Vec<usize>>>,
+    /// The number of times the pattern occurred in the sample.
+    pub count: usize,
+    /// A weight given explicitly, which is used instead of the count.
+    pub weight: Option<f64>,
+    /// For each direction of the topology, the indexes of the patterns which are allowed next to this one in that direction.
+    pub compatible: Vec<Vec<usize>>,
+}
+
+impl<T> Ruleset<T> {
+    /// The distinct values of the sample, indexed by their palette ids.
+    pub fn values(&self) -> &[T] {
+        &self.values
+    }
+
+    pub fn patterns(&self) -> &[RulePattern] {
+        &self.patterns
+    }
+
+    pub fn chunk_size(&self) -> Vector3<usize> {
+        self.chunk_size
+    }
+
+    /// The symmetry the sample was analyzed with. Its transforms are already part of the patterns.
+    pub fn symmetry(&self) -> Symmetry {
+        self.symmetry
+    }
+
+    pub fn topology(&self) -> &dyn Topology {
+        self.topology.as_ref()
+    }
+
+    /// A hash of the rules, for telling whether two rulesets would generate the same outputs, such as when replaying a `GenerationLog`.
+    ///
+    /// The hash doesn't change between runs of the same build, but it isn't guaranteed to stay the same on other platforms.
+    pub fn fingerprint(&self) -> u64
+    where
+        T: Hash,
+    {
+        let mut hasher = Fnv1a::default();
+
+        self.values.hash(&mut hasher);
+        self.chunk_size.x.hash(&mut hasher);
+        self.chunk_size.y.hash(&mut hasher);
+        self.chunk_size.z.hash(&mut hasher);
+        // the symmetry is left out, as it only matters for the patterns it added
+        format!("{:?}", self.topology).hash(&mut hasher);
+
+        for pattern in &self.patterns {
+            pattern.contents.hash(&mut hasher);
+            pattern.count.hash(&mut hasher);
+            pattern.weight.map(f64::to_bits).hash(&mut hasher);
+            pattern.compatible.hash(&mut hasher);
+        }
+
+        hasher.finish()
+    }
+
+    /// Checks that the values are distinct, and that the patterns fit the chunk size, have a usable weight and only refer to values and patterns which exist.
+    pub(crate) fn validate(&self) -> Result<(), WaveError>
+    where
+        T: Eq + Hash,
+    {
+        self.check()
+            .map_err(|reason| WaveError::InvalidRuleset { reason })
+    }
+
+    fn check(&self) -> Result<(), String>
+    where
+        T: Eq + Hash,
+    {
+        let directions = self.topology.directions();
+        let mut seen = HashSet::new();
+
+        if !self.values.iter().all(|value| seen.insert(value)) {
+            return Err("the ruleset has the same value more than once".to_string());
+        }
+
+        check_chunk_size(self.chunk_size)?;
+
+        for (i, pattern) in self.patterns.iter().enumerate() {
+            let weight = pattern.weight.unwrap_or(pattern.count as f64);
+            check_pattern(i, &pattern.contents, weight, self.chunk_size, self.values.len())?;
+
+            if pattern.compatible.len() != directions {
+                return Err(format!(
+                    "pattern {} has rules for {} directions, but the topology has {}",
+                    i,
+                    pattern.compatible.len(),
+                    directions
+                ));
+            }
+
+            if pattern
+                .compatible
+                .iter()
+                .flatten()
+                .any(|&id| id >= self.patterns.len())
+            {
+                return Err(format!(
+                    "pattern {} allows a pattern which isn't in the ruleset",
+                    i
+                ));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Checks that chunks of the given size have at least one cell.
+pub(crate) fn check_chunk_size(size: Vector3<usize>) -> Result<(), String> {
+    if size.x == 0 || size.y == 0 || size.z == 0 {
+        return Err("the chunk size can't be empty".to_string());
+    }
+
+    Ok(())
+}
+
+/// Checks that the pattern at the given index fits the chunk size, only holds palette ids below `values`, and has a weight it can be chosen in proportion to.
+///
+/// Waves index into the contents of their patterns and the palette without checking, so this is done for patterns which come from outside the crate, in rulesets and serialized waves.
+pub(crate) fn check_pattern(
+    index: usize,
+    contents: &[Vec<Vec<usize>>],
+    weight: f64,
+    size: Vector3<usize>,
+    values: usize,
+) -> Result<(), String> {
+    let fits = contents.len() == size.x
+        && contents.iter().all(|column| column.len() == size.y)
+        && contents.iter().flatten().all(|stack| stack.len() == size.z);
+
+    if !fits {
+        return Err(format!("pattern {} doesn't match the chunk size", index));
+    }
+
+    if contents.iter().flatten().flatten().any(|&id| id >= values) {
+        return Err(format!("pattern {} has a value which isn't in the palette", index));
+    }
+
+    if !(weight.is_finite() && weight > 0.) {
+        return Err(format!(
+            "pattern {} has a weight which isn't a positive, finite number",
+            index
+        ));
+    }
+
+    Ok(())
+}
+
+/// The 64 bit FNV-1a hash, which unlike the standard library's hasher is the same in every version of Rust.
+struct Fnv1a(u64);
+
+impl Default for Fnv1a {
+    fn default() -> Self {
+        Self(0xcbf29ce484222325)
+    }
+}
+
+impl Hasher for Fnv1a {
+    fn finish(&self) -> u64 {
+        self.0
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        for &byte in bytes {
+            self.0 = (self.0 ^ byte as u64).wrapping_mul(0x100000001b3);
+        }
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<T: Serialize> Serialize for Ruleset<T> {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        let mut state = serializer.serialize_struct("Ruleset", 6)?;
+        state.serialize_field("version", &RULESET_VERSION)?;
+        state.serialize_field("values", &self.values)?;
+        state.serialize_field("chunk_size", &self.chunk_size)?;
+        state.serialize_field("symmetry", &self.symmetry)?;
+        state.serialize_field("topology", &SerializedTopology(self.topology.as_ref()))?;
+        state.serialize_field("patterns", &self.patterns)?;
+        state.end()
+    }
+}
+
+/// The fields of a serialized ruleset, in the order they're serialized in.
+#[cfg(feature = "serde")]
+#[derive(Deserialize)]
+#[serde(rename = "Ruleset")]
+struct RulesetFields<T> {
+    version: u32,
+    values: Vec<T>,
+    chunk_size: Vector3<usize>,
+    symmetry: Symmetry,
+    #[serde(deserialize_with = "deserialize_topology")]
+    topology: Arc<dyn Topology>,
+    patterns: Vec<RulePattern>,
+}
+
+#[cfg(feature = "serde")]
+impl<'de, T: Deserialize<'de> + Eq + Hash> Deserialize<'de> for Ruleset<T> {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        let fields = RulesetFields::<T>::deserialize(deserializer)?;
+
+        if fields.version != RULESET_VERSION {
+            return Err(D::Error::custom(format!(
+                "the ruleset is version {}, but only version {} is supported",
+                fields.version, RULESET_VERSION
+            )));
+        }
+
+        let ruleset = Ruleset {
+            values: fields.values,
+            patterns: fields.patterns,
+            chunk_size: fields.chunk_size,
+            symmetry: fields.symmetry,
+            topology: fields.topology,
+        };
+
+        ruleset.validate().map_err(D::Error::custom)?;
+
+        Ok(ruleset)
+    }
+}
@@ -1,46 +1,125 @@
-use crate::helpers::xy_swap;
+use crate::helpers::volume;
+use crate::helpers::{dimensions_of, xy_swap};
+use cgmath::Vector2;
 use rand::prelude::*;
-use rand::thread_rng;
+use rand_chacha::ChaCha12Rng;
 
-pub fn deconstruct_string(
-    input: &String,
-    use_whitespace: bool,
-) -> (Vec<Vec<usize>>, Vec<(usize, char)>) {
-    // convert string input into a usable bitset-based sample
-    let mut sample: Vec<Vec<usize>> = vec![];
-    sample.reserve(input.lines().count());
-    let mut source_map: Vec<(usize, char)> = vec![];
-    let mut id_counter = 0usize;
+/// Converts a string into a sample of its characters, indexed as `[x][y]`.
+pub fn deconstruct_string(input: &str, use_whitespace: bool) -> Vec<Vec<char>> {
+    let mut sample: Vec<Vec<char>> = Vec::with_capacity(input.lines().count());
 
     for (row, line) in input.lines().enumerate() {
         if sample.len() < row + 1 {
             sample.push(vec![]);
         }
 
-        for (_, ch) in line.chars().enumerate() {
-            if !use_whitespace {
-                if ch.is_whitespace() {
-                    continue;
-                }
+        for ch in line.chars() {
+            if !use_whitespace && ch.is_whitespace() {
+                continue;
             }
 
-            if let Some(translation) = source_map.iter().find(|t| t.1 == ch) {
-                sample[row].push(translation.0);
-            } else {
-                source_map.push((id_counter, ch));
-                sample[row].push(id_counter);
-                id_counter += 1;
-            }
+            sample[row].push(ch);
         }
     }
 
-    (xy_swap(sample), source_map)
+    xy_swap(sample)
 }
 
-pub fn construct_wip_string(
-    input: Vec<Vec<Vec<usize>>>,
-    source_map: &Vec<(usize, char)>,
-) -> String {
+/// Converts a string of layers into a voxel sample of their characters, indexed as `[x][y][z]`.
+///
+/// Each layer is written the same way as a sample for `deconstruct_string`, from the bottom layer (where z is 0) upwards, with an empty line between each of them. An error is returned if the layers aren't all the same size.
+pub fn deconstruct_layers(
+    input: &str,
+    use_whitespace: bool,
+) -> Result<Vec<Vec<Vec<char>>>, String> {
+    let mut layers: Vec<Vec<Vec<char>>> = vec![];
+    let mut current = String::new();
+
+    for line in input.lines().chain(std::iter::once("")) {
+        let separator = if use_whitespace {
+            line.is_empty()
+        } else {
+            line.trim().is_empty()
+        };
+
+        if !separator {
+            current.push_str(line);
+            current.push('\n');
+        } else if !current.is_empty() {
+            layers.push(deconstruct_string(&current, use_whitespace));
+            current.clear();
+        }
+    }
+
+    let size = layers
+        .first()
+        .map(|l| dimensions_of(l))
+        .unwrap_or(Vector2::new(0, 0));
+
+    for (z, layer) in layers.iter().enumerate() {
+        let found = dimensions_of(layer);
+
+        if found != size || layer.iter().any(|column| column.len() != size.y) {
+            return Err(format!(
+                "Layer {} isn't the same size as the first layer ({}x{})",
+                z, size.x, size.y
+            ));
+        }
+    }
+
+    Ok((0..size.x)
+        .map(|x| {
+            (0..size.y)
+                .map(|y| layers.iter().map(|layer| layer[x][y]).collect())
+                .collect()
+        })
+        .collect())
+}
+
+/// Converts a collapsed volume back into layers of its characters, in the same format read by `deconstruct_layers`.
+pub fn reconstruct_layers(input: &[Vec<Vec<char>>]) -> String {
+    let size = volume::dimensions_of(input);
+
+    (0..size.z)
+        .map(|z| {
+            (0..size.y)
+                .map(|y| (0..size.x).map(|x| input[x][y][z]).collect::<String>())
+                .collect::<Vec<String>>()
+                .join("\n")
+        })
+        .collect::<Vec<String>>()
+        .join("\n\n")
+}
+
+/// Converts a string of hexagons in offset rows into a sample of their characters, indexed as `[column][row]` for the `Hex` topology.
+///
+/// Every character other than whitespace is a cell. Odd rows are shifted half a cell to the right, which is usually written by indenting them with a space and putting a space between the cells, as `reconstruct_hex` does. For example:
+///
+/// ```text
+/// a b a b
+///  c c c c
+/// b a b a
+/// ```
+pub fn deconstruct_hex(input: &str) -> Vec<Vec<char>> {
+    deconstruct_string(input, false)
+}
+
+/// Converts a collapsed result on the `Hex` topology back into offset rows of its characters, in the same format read by `deconstruct_hex`.
+pub fn reconstruct_hex(input: Vec<Vec<char>>) -> String {
+    xy_swap(input)
+        .iter()
+        .enumerate()
+        .map(|(r, row)| {
+            let cells: Vec<String> = row.iter().map(|ch| ch.to_string()).collect();
+            let indent = if r % 2 == 1 { " " } else { "" };
+
+            format!("{}{}", indent, cells.join(" "))
+        })
+        .collect::<Vec<String>>()
+        .join("\n")
+}
+
+pub fn construct_wip_string(input: Vec<Vec<Vec<char>>>) -> String {
     let space_for_unfounds = true;
 
     let swapped = xy_swap(input);
@@ -64,10 +143,7 @@ pub fn construct_wip_string(
         }
 
         for vals in row {
-            let mut mapped: Vec<char> = vals
-                .iter()
-                .map(|v| source_map.iter().find(|s| s.0 == *v).unwrap().1)
-                .collect();
+            let mut mapped = vals.clone();
             mapped.sort();
 
             let mut string = "(".to_string();
@@ -88,11 +164,14 @@ pub fn construct_wip_string(
     output
 }
 
+/// Converts a collapsed result back into its characters.
+///
+/// When `use_color` is set, characters without a fixed colour are given one chosen using the `seed` provided, so the same seed always colours a result the same way.
 pub fn reconstruct_string(
-    input: Vec<Vec<usize>>,
-    source_map: &Vec<(usize, char)>,
+    input: Vec<Vec<char>>,
     use_color: bool,
     bold: bool,
+    seed: u64,
 ) -> String {
     let swapped = xy_swap(input);
     let mut output = "".to_string();
@@ -103,8 +182,10 @@ pub fn reconstruct_string(
 
     let mut lines = 1;
 
-    let colors = vec!["31", "32", "33", "34", "35", "36"];
+    let colors = ["31", "32", "33", "34", "35", "36"];
     let mut color_map: Vec<(char, &str)> = vec![];
+    // unlike StdRng, ChaCha12 stays the same across versions of rand, so a seed always gives the same colours
+    let mut rng = ChaCha12Rng::seed_from_u64(seed);
 
     for (r, row) in swapped.iter().enumerate() {
         if lines < r + 1 {
@@ -112,9 +193,7 @@ pub fn reconstruct_string(
             lines += 1;
         }
 
-        for id in row {
-            let real_val = source_map.iter().find(|s| s.0 == *id).unwrap().1;
-
+        for &real_val in row {
             if use_color {
                 if let Some(color) = color_map.iter().find(|r| r.0 == real_val) {
                     output.push_str(&format!("\x1b[{}m", color.1));
@@ -125,13 +204,13 @@ pub fn reconstruct_string(
                         'L' => "32",
                         _ => colors
                             .iter()
-                            .filter(|c| color_map.iter().find(|r| r.1 == **c).is_none())
-                            .choose(&mut thread_rng())
+                            .filter(|c| !color_map.iter().any(|r| r.1 == **c))
+                            .choose(&mut rng)
                             .map_or("0", |y| *y),
                     };
 
                     color_map.push((real_val, choice));
-                    output.push_str(&format!("\x1b[{}m", choice.to_string()));
+                    output.push_str(&format!("\x1b[{}m", choice));
                 }
             }
 
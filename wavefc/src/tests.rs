@@ -1,12 +1,60 @@
 use super::*;
 use std::collections::hash_map::DefaultHasher;
 use std::hash::Hasher;
+use crate::stringtools::{deconstruct_hex, deconstruct_layers, reconstruct_hex, reconstruct_layers};
+
+/// A 5x5 sample where neighbouring values always differ, so a random collapse regularly runs into contradictions.
+fn alternating_sample() -> Vec<Vec<usize>> {
+    vec![
+        vec![0, 1, 0, 2, 1],
+        vec![2, 0, 1, 0, 2],
+        vec![1, 2, 0, 1, 0],
+        vec![0, 1, 2, 0, 1],
+        vec![2, 0, 1, 2, 0],
+    ]
+}
+
+/// A wave seeded with `seed` which has learned the sample cell by cell with the given symmetry, the way most of these tests start out.
+fn analyzed_wave<T: Eq + Hash + Clone>(sample: Vec<Vec<T>>, symmetry: Symmetry, seed: u64) -> Wave<T> {
+    let mut wave = Wave::new();
+    wave.set_seed(seed);
+    wave.set_symmetry(symmetry);
+    wave.analyze(sample, Vector2::new(1, 1), BorderMode::Clamp).expect("Analysis failed.");
+    wave
+}
+
+/// An `analyzed_wave` filled to the given size, ready to be collapsed.
+fn filled_wave<T: Eq + Hash + Clone>(
+    sample: Vec<Vec<T>>,
+    symmetry: Symmetry,
+    seed: u64,
+    size: Vector2<usize>,
+) -> Wave<T> {
+    let mut wave = analyzed_wave(sample, symmetry, seed);
+    wave.fill(size).expect("Fill failed.");
+    wave
+}
+
+/// A filled wave of the alternating sample which collapses its cells in a random order, so it regularly runs into contradictions.
+fn contradicting_wave(seed: u64, size: Vector2<usize>) -> Wave {
+    let mut wave = filled_wave(alternating_sample(), Symmetry::None, seed, size);
+    wave.set_heuristic(SelectionHeuristic::Random);
+    wave
+}
+
+/// The callback of `collapse_all`, given the number of iterations and failures so far and the current representation.
+type Progress<T> = fn(usize, usize, Vec<Vec<Vec<T>>>);
+
+/// Stands in for the progress callback of `collapse_all` when a test doesn't need one.
+fn no_callback<T>() -> Option<Progress<T>> {
+    None
+}
 
 #[test]
 fn wave_collapse_once_works() {
     let mut wave = Wave::new();
     let test_sample = vec![vec![0, 1, 2]];
-    wave.analyze(test_sample, Vector2::new(1, 1), BorderMode::Clamp);
+    wave.analyze(test_sample, Vector2::new(1, 1), BorderMode::Clamp).expect("Analysis failed.");
     wave.fill(Vector2::new(3, 1)).expect("Fill failed.");
     wave.collapse_once();
 
@@ -21,25 +69,25 @@ fn dedup_and_count_patterns_works() {
             is_transform: false,
             id: 0,
             count: 1,
-            contents: vec![vec![0]],
-            rules: vec![Rule::new(0, vec![vec![1]])],
-            calculated_entropy: None,
+            weight: None,
+            contents: vec![vec![vec![0]]],
+            rules: vec![Rule::new(0, vec![vec![vec![1]]])],
         },
         Pattern {
             is_transform: false,
             id: 1,
             count: 1,
-            contents: vec![vec![1]],
-            rules: vec![Rule::new(2, vec![vec![0]])],
-            calculated_entropy: None,
+            weight: None,
+            contents: vec![vec![vec![1]]],
+            rules: vec![Rule::new(2, vec![vec![vec![0]]])],
         },
         Pattern {
             is_transform: false,
             id: 2,
             count: 1,
-            contents: vec![vec![1]],
-            rules: vec![Rule::new(2, vec![vec![0]]), Rule::new(2, vec![vec![0]])],
-            calculated_entropy: None,
+            weight: None,
+            contents: vec![vec![vec![1]]],
+            rules: vec![Rule::new(2, vec![vec![vec![0]]]), Rule::new(2, vec![vec![vec![0]]])],
         },
     ];
 
@@ -62,19 +110,15 @@ fn dedup_and_count_patterns_works() {
         let mut hasher = DefaultHasher::new();
         hasher.write_usize(pattern.count);
 
-        for row in &pattern.contents {
-            for n in row {
-                hasher.write_usize(*n);
-            }
+        for n in pattern.contents.iter().flatten().flatten() {
+            hasher.write_usize(*n);
         }
 
         for rule in &pattern.rules {
             hasher.write_u8(rule.direction);
 
-            for row in &rule.content {
-                for n in row {
-                    hasher.write_usize(*n);
-                }
+            for n in rule.content.iter().flatten().flatten() {
+                hasher.write_usize(*n);
             }
         }
 
@@ -91,30 +135,30 @@ fn dedup_and_count_patterns_works() {
             id: 3,
             is_transform: false,
             count: 1,
-            contents: vec![vec![2]],
-            rules: vec![Rule::new(1, vec![vec![1]]), Rule::new(3, vec![vec![1]])],
-            calculated_entropy: None,
+            weight: None,
+            contents: vec![vec![vec![2]]],
+            rules: vec![Rule::new(1, vec![vec![vec![1]]]), Rule::new(3, vec![vec![vec![1]]])],
         },
         Pattern {
             id: 0,
             is_transform: false,
             count: 1,
-            contents: vec![vec![0]],
+            weight: None,
+            contents: vec![vec![vec![0]]],
             rules: vec![
-                Rule::new(0, vec![vec![1]]),
-                Rule::new(1, vec![vec![1]]),
-                Rule::new(2, vec![vec![1]]),
-                Rule::new(3, vec![vec![1]]),
+                Rule::new(0, vec![vec![vec![1]]]),
+                Rule::new(1, vec![vec![vec![1]]]),
+                Rule::new(2, vec![vec![vec![1]]]),
+                Rule::new(3, vec![vec![vec![1]]]),
             ],
-            calculated_entropy: None,
         },
         Pattern {
             id: 10,
             is_transform: true,
             count: 1,
-            contents: vec![vec![2]],
-            rules: vec![Rule::new(0, vec![vec![1]])],
-            calculated_entropy: None,
+            weight: None,
+            contents: vec![vec![vec![2]]],
+            rules: vec![Rule::new(0, vec![vec![vec![1]]])],
         },
     ];
 
@@ -128,14 +172,13 @@ fn dedup_and_count_patterns_works() {
             id: 3,
             is_transform: false,
             count: 1,
-            contents: vec![vec![2]],
+            weight: None,
+            contents: vec![vec![vec![2]]],
             rules: vec![
-                Rule::new(0, vec![vec![1]]),
-                Rule::new(1, vec![vec![1]]),
-                Rule::new(3, vec![vec![1]]),
+                Rule::new(0, vec![vec![vec![1]]]),
+                Rule::new(1, vec![vec![vec![1]]]),
+                Rule::new(3, vec![vec![vec![1]]]),
             ],
-            // The entropy values shouldn't have any effect in this particular test.
-            calculated_entropy: None,
         }
     );
 }
@@ -145,8 +188,8 @@ fn wave_analyzer_works() {
     let mut wave = Wave::new();
     let input = vec![vec![0, 1, 2], vec![0, 1, 2], vec![0, 1, 2]];
 
-    wave.flags.push(Flags::NoTransforms);
-    wave.analyze(input, Vector2::new(2, 2), BorderMode::Clamp);
+    wave.set_symmetry(Symmetry::None);
+    wave.analyze(input, Vector2::new(2, 2), BorderMode::Clamp).expect("Analysis failed.");
 
     assert_eq!(wave.patterns.len(), 2);
 }
@@ -161,7 +204,7 @@ fn wave_analyzer_works() {
 // ];
 
 // let mut wave = Wave::new();
-// wave.analyze(sample, Vector2::new(2, 2), BorderMode::Clamp);
+// wave.analyze(sample, Vector2::new(2, 2), BorderMode::Clamp).expect("Analysis failed.");
 // wave.fill(Vector2::new(6, 6)).expect("Fill failed.");
 
 // let start = Instant::now();
@@ -169,3 +212,1273 @@ fn wave_analyzer_works() {
 // let time = start.elapsed();
 // assert!(time < Duration::from_secs(1));
 // }
+
+#[test]
+fn seeded_waves_are_reproducible() {
+    let run = |seed: u64| {
+        let mut wave = filled_wave(alternating_sample(), Symmetry::default(), seed, Vector2::new(10, 10));
+        wave.collapse_all(100, no_callback()).expect("Collapse failed.");
+        wave.perfect_rep().unwrap()
+    };
+
+    assert_eq!(run(42), run(42));
+    assert_eq!(run(7), run(7));
+    assert_ne!(run(42), run(7));
+}
+
+#[test]
+fn seed_is_kept() {
+    let mut wave: Wave = Wave::new();
+    wave.set_seed(42);
+    assert_eq!(wave.seed(), 42);
+}
+
+#[test]
+fn propagation_reaches_whole_grid() {
+    // every value only allows one neighbour above and below it, so a single collapse fixes the whole column
+    let sample = vec![vec![0, 1, 2, 3, 0, 1, 2, 3]];
+    let mut wave = filled_wave(sample, Symmetry::None, 3, Vector2::new(1, 8));
+    wave.collapse_once();
+
+    assert!(wave.elements.iter().all(|e| e.is_collapsed()));
+}
+
+#[test]
+fn bitset_works() {
+    let mut set = BitSet::full(70);
+    assert_eq!(set.len(), 70);
+    assert!(set.contains(69));
+    assert!(!set.contains(70));
+
+    assert!(set.remove(3));
+    assert!(!set.remove(3));
+    assert!(set.remove(65));
+    assert_eq!(set.len(), 68);
+
+    let mut other = BitSet::empty(70);
+    other.insert(3);
+    other.insert(4);
+    assert_eq!(other.first(), Some(3));
+    assert_eq!(other.iter().collect::<Vec<_>>(), vec![3, 4]);
+    assert_eq!(other.difference(&set), vec![3]);
+
+    set.union_with(&other);
+    assert_eq!(set.len(), 69);
+    assert_eq!(set.difference(&BitSet::full(70)), Vec::<usize>::new());
+}
+
+#[test]
+fn every_selection_heuristic_collapses() {
+    let sample = vec![
+        vec![0, 0, 0, 1, 1, 2],
+        vec![0, 0, 1, 1, 2, 2],
+        vec![0, 1, 1, 2, 2, 2],
+        vec![0, 0, 1, 1, 2, 2],
+        vec![0, 0, 0, 1, 1, 2],
+    ];
+
+    for heuristic in [
+        SelectionHeuristic::MinEntropy,
+        SelectionHeuristic::Mrv,
+        SelectionHeuristic::Scanline,
+        SelectionHeuristic::Random,
+    ] {
+        let mut wave = filled_wave(sample.to_owned(), Symmetry::default(), 11, Vector2::new(8, 8));
+        wave.set_heuristic(heuristic);
+        wave.collapse_all(50, no_callback())
+            .unwrap_or_else(|e| panic!("{:?} failed to collapse: {}", heuristic, e));
+
+        assert!(wave.perfect_rep().is_ok());
+    }
+}
+
+#[test]
+fn element_entropy_uses_remaining_weights() {
+    let weights = vec![PatternWeight::new(1.), PatternWeight::new(1.), PatternWeight::new(2.)];
+    let mut element = Element::new(BitSet::full(3), Vector3::new(0, 0, 0), &weights);
+
+    // probabilities of 1/4, 1/4 and 1/2
+    assert!((element.entropy() - 1.5).abs() < 1e-9);
+
+    element.remove(2, &weights);
+    assert!((element.entropy() - 1.).abs() < 1e-9);
+
+    element.remove(1, &weights);
+    assert_eq!(element.entropy(), 0.);
+}
+
+#[test]
+fn backtracking_recovers_from_contradictions() {
+    let restarts = |backtracking: Option<BacktrackLimits>| {
+        let mut contradictions = 0;
+        let mut backtracks = 0;
+
+        for seed in 0..10 {
+            let mut wave = contradicting_wave(seed, Vector2::new(12, 12));
+            wave.set_backtracking(backtracking);
+
+            let stats = wave
+                .collapse_all(100, no_callback())
+                .unwrap_or_else(|e| panic!("Seed {} failed to collapse: {}", seed, e));
+
+            assert!(wave.perfect_rep().is_ok());
+            contradictions += stats.contradictions;
+            backtracks += stats.backtracks;
+        }
+
+        (contradictions, backtracks)
+    };
+
+    let (without, no_backtracks) = restarts(None);
+    let (with, backtracks) = restarts(Some(BacktrackLimits::default()));
+
+    assert_eq!(no_backtracks, 0);
+    assert!(backtracks > 0);
+    assert!(with < without, "{} restarts with backtracking, {} without", with, without);
+}
+
+#[test]
+fn backtracking_limit_holds_across_restarts() {
+    let limits = BacktrackLimits {
+        max_depth: 64,
+        max_backtracks: 2,
+    };
+
+    for seed in 0..10 {
+        let mut wave = contradicting_wave(seed, Vector2::new(12, 12));
+        wave.set_backtracking(Some(limits));
+
+        let stats = wave.collapse_all(100, no_callback()).expect("Collapse failed.");
+        assert!(stats.backtracks <= 2);
+    }
+}
+
+#[test]
+fn periodic_output_tiles() {
+    // Neighbouring values differ across the edges of the sample too.
+    for seed in 0..5 {
+        let mut wave = analyzed_wave(alternating_sample(), Symmetry::default(), seed);
+        wave.set_periodic(true, true);
+        wave.set_backtracking(Some(BacktrackLimits::default()));
+        wave.fill(Vector2::new(7, 6)).expect("Fill failed.");
+        wave.collapse_all(100, no_callback())
+            .unwrap_or_else(|e| panic!("Seed {} failed to collapse: {}", seed, e));
+
+        let result = wave.perfect_rep().unwrap();
+
+        for column in result.iter() {
+            assert_ne!(column[0], column[5]);
+        }
+
+        for (first, last) in result[0].iter().zip(result[6].iter()) {
+            assert_ne!(first, last);
+        }
+    }
+}
+
+#[test]
+fn symmetry_groups_remap_rule_directions() {
+    // indexed as [x][y], so 1 is only ever found to the right of 0
+    let sample = vec![vec![0], vec![1]];
+
+    let directions = |symmetry: Symmetry| {
+        let wave = analyzed_wave(sample.to_owned(), symmetry, 0);
+
+        let zero = wave
+            .patterns
+            .iter()
+            .find(|p| p.contents == vec![vec![vec![0]]])
+            .unwrap();
+
+        let mut directions: Vec<u8> = zero
+            .rules
+            .iter()
+            .filter(|r| r.content == vec![vec![vec![1]]])
+            .map(|r| r.direction)
+            .collect();
+        directions.sort();
+        directions
+    };
+
+    assert_eq!(directions(Symmetry::None), vec![1]);
+    assert_eq!(directions(Symmetry::MirrorX), vec![1, 3]);
+    assert_eq!(directions(Symmetry::MirrorY), vec![1]);
+    assert_eq!(directions(Symmetry::MirrorXY), vec![1, 3]);
+    assert_eq!(directions(Symmetry::Rotations), vec![0, 1, 2, 3]);
+    assert_eq!(directions(Symmetry::Full), vec![0, 1, 2, 3]);
+}
+
+#[test]
+fn constraints_are_kept_across_restarts() {
+    let mut contradictions = 0;
+
+    for seed in 0..5 {
+        let mut wave = contradicting_wave(seed, Vector2::new(8, 8));
+        wave.constrain(Vector2::new(0, 0), &[2]).unwrap();
+        wave.ban(Vector2::new(7, 7), &[0, 2]).unwrap();
+        wave.constrain(Vector2::new(3, 4), &[0, 1]).unwrap();
+
+        let stats = wave
+            .collapse_all(100, no_callback())
+            .unwrap_or_else(|e| panic!("Seed {} failed to collapse: {}", seed, e));
+        contradictions += stats.contradictions;
+
+        let result = wave.perfect_rep().unwrap();
+        assert_eq!(result[0][0], 2);
+        assert_eq!(result[7][7], 1);
+        assert_ne!(result[3][4], 2);
+    }
+
+    // the constraints must have survived at least one restart
+    assert!(contradictions > 0);
+}
+
+#[test]
+fn unsatisfiable_constraints_are_reported() {
+    let sample = vec![vec![0, 1, 0, 1], vec![1, 0, 1, 0]];
+    let mut wave = analyzed_wave(sample, Symmetry::default(), 0);
+
+    assert_eq!(
+        wave.constrain(Vector2::new(0, 0), &[0]),
+        Err(WaveError::NotFilled)
+    );
+
+    wave.fill(Vector2::new(4, 4)).expect("Fill failed.");
+    assert_eq!(
+        wave.constrain(Vector2::new(4, 0), &[0]),
+        Err(WaveError::OutOfBounds {
+            position: Vector3::new(4, 0, 0)
+        })
+    );
+
+    wave.constrain(Vector2::new(0, 0), &[0]).unwrap();
+    // a checkerboard can't have the same value next to itself
+    assert_eq!(
+        wave.constrain(Vector2::new(1, 0), &[0]),
+        Err(WaveError::UnsatisfiableConstraint {
+            position: Vector3::new(1, 0, 0)
+        })
+    );
+}
+
+#[test]
+fn inpainting_keeps_unmasked_cells() {
+    let sample = vec![
+        vec![0, 0, 0, 1, 1, 2],
+        vec![0, 0, 1, 1, 2, 2],
+        vec![0, 1, 1, 2, 2, 2],
+        vec![0, 0, 1, 1, 2, 2],
+        vec![0, 0, 0, 1, 1, 2],
+    ];
+
+    let mut wave = filled_wave(sample, Symmetry::default(), 4, Vector2::new(10, 10));
+    wave.collapse_all(50, no_callback()).unwrap();
+    let existing = wave.perfect_rep().unwrap();
+
+    // reroll a rectangle in the middle
+    let mask: Vec<Vec<bool>> = (0..10)
+        .map(|x| (0..10).map(|y| (3..7).contains(&x) && (2..6).contains(&y)).collect())
+        .collect();
+
+    for seed in 0..5 {
+        wave.set_seed(seed);
+        wave.inpaint(
+            &existing,
+            &mask,
+            50,
+            no_callback(),
+        )
+        .unwrap_or_else(|e| panic!("Seed {} failed to inpaint: {}", seed, e));
+
+        let result = wave.perfect_rep().unwrap();
+
+        for x in 0..10 {
+            for y in 0..10 {
+                if !mask[x][y] {
+                    assert_eq!(result[x][y], existing[x][y]);
+                }
+            }
+        }
+    }
+
+    assert!(wave.constraints.is_empty());
+
+    // the mask has to line up with the existing output
+    assert!(wave
+        .inpaint(
+            &existing,
+            &mask[..5],
+            50,
+            no_callback()
+        )
+        .is_err());
+}
+
+#[test]
+fn steps_describe_every_change() {
+    // A few restarts happen along the way.
+    let mut expected = contradicting_wave(0, Vector2::new(8, 8));
+    let expected_stats = expected.collapse_all(100, no_callback()).unwrap();
+
+    // rebuild the grid from the events alone
+    let mut wave = contradicting_wave(0, Vector2::new(8, 8));
+    let total = wave.patterns.len();
+    let full: Vec<usize> = (0..total).collect();
+    let mut grid = vec![vec![full.to_owned(); 8]; 8];
+    let mut events = vec![];
+
+    for event in wave.steps(100) {
+        let event = event.unwrap();
+
+        match &event {
+            CollapseEvent::Observed { position, pattern } => {
+                grid[position.x][position.y] = vec![*pattern];
+            }
+            CollapseEvent::Pruned { position, removed } => {
+                grid[position.x][position.y].retain(|p| !removed.contains(p));
+            }
+            CollapseEvent::Restarted => grid = vec![vec![full.to_owned(); 8]; 8],
+            _ => {}
+        }
+
+        events.push(event);
+    }
+
+    assert!(events.contains(&CollapseEvent::Restarted));
+    assert_eq!(
+        events.last(),
+        Some(&CollapseEvent::Completed(expected_stats))
+    );
+
+    let result = wave.perfect_rep().unwrap();
+    assert_eq!(result, expected.perfect_rep().unwrap());
+
+    for (x, column) in grid.iter().enumerate() {
+        for (y, patterns) in column.iter().enumerate() {
+            assert_eq!(patterns.len(), 1);
+            assert_eq!(
+                wave.pattern_contents(patterns[0]).unwrap()[0][0],
+                result[x][y]
+            );
+        }
+    }
+
+    // events aren't gathered outside of stepping
+    assert!(wave.events.is_none());
+}
+
+#[test]
+fn errors_carry_context() {
+    let mut wave = Wave::new();
+    assert_eq!(
+        wave.collapse_all(1, no_callback()),
+        Err(WaveError::NoPatterns)
+    );
+
+    let sample = alternating_sample();
+
+    wave.set_seed(0);
+    wave.set_heuristic(SelectionHeuristic::Random);
+    wave.analyze(sample, Vector2::new(1, 1), BorderMode::Clamp).expect("Analysis failed.");
+    assert_eq!(
+        wave.collapse_all(1, no_callback()),
+        Err(WaveError::NotFilled)
+    );
+
+    wave.fill(Vector2::new(12, 12)).expect("Fill failed.");
+    assert_eq!(wave.perfect_rep(), Err(WaveError::NotCollapsed));
+
+    match wave.collapse_all(1, no_callback()) {
+        Err(WaveError::MaxContradictions(stats)) => assert_eq!(stats.contradictions, 1),
+        other => panic!("Expected the max number of contradictions, got {:?}", other),
+    }
+
+    // the contradicted state is kept
+    assert!(matches!(
+        wave.perfect_rep(),
+        Err(WaveError::Contradiction { .. })
+    ));
+
+    assert_eq!(
+        WaveError::OutOfBounds {
+            position: Vector3::new(0, 0, 3)
+        }
+        .to_string(),
+        "The position (0, 0, 3) is outside of the output"
+    );
+}
+
+#[test]
+fn palette_interns_sample_values() {
+    let wave = analyzed_wave(vec![vec!['S', 'C', 'L', 'L', 'C', 'S']], Symmetry::None, 0);
+
+    assert_eq!(wave.palette().values(), &['S', 'C', 'L']);
+    assert_eq!(wave.palette().id(&'L'), Some(2));
+    assert_eq!(wave.palette().id(&'X'), None);
+}
+
+#[test]
+fn constraints_take_sample_values() {
+    // water only touches sand, and sand only touches land on its other side
+    let sample = vec![vec!['S', 'C', 'L', 'L', 'C', 'S']];
+    let mut wave = filled_wave(sample, Symmetry::None, 5, Vector2::new(1, 6));
+    wave.constrain(Vector2::new(0, 0), &['S']).expect("Constraint failed.");
+
+    // a value that isn't in the sample can never be allowed
+    let mut unknown = wave.clone();
+    assert_eq!(
+        unknown.constrain(Vector2::new(0, 5), &['X']),
+        Err(WaveError::UnsatisfiableConstraint {
+            position: Vector3::new(0, 5, 0)
+        })
+    );
+
+    wave.collapse_all(10, no_callback()).expect("Collapse failed.");
+    let result = wave.perfect_rep().expect("Perfect representation failed.");
+
+    assert_eq!(result[0][0], 'S');
+    assert_eq!(result[0][1], 'C');
+    assert!(result[0].iter().all(|c| wave.palette().id(c).is_some()));
+}
+
+#[test]
+fn tiled_model_derives_rotated_neighbours() {
+    let mut model = TiledModel::new();
+    model.add_tile("pipe", TileSymmetry::I, 1.);
+    model.add_tile("empty", TileSymmetry::X, 1.);
+
+    // pipes only continue along their own direction, and run alongside empty tiles
+    model.add_neighbours(TileVariant::new("pipe", 0), TileVariant::new("pipe", 0));
+    model.add_neighbours(TileVariant::new("pipe", 1), TileVariant::new("empty", 0));
+    model.add_neighbours(TileVariant::new("empty", 0), TileVariant::new("empty", 0));
+
+    assert_eq!(model.variants().len(), 3);
+
+    let mut wave = model.wave().expect("Failed to create the wave.");
+    wave.set_seed(9);
+    wave.fill(Vector2::new(8, 8)).expect("Fill failed.");
+    wave.collapse_all(20, no_callback()).expect("Collapse failed.");
+    let result = wave.perfect_rep().expect("Perfect representation failed.");
+
+    let horizontal = TileVariant::new("pipe", 0);
+    let vertical = TileVariant::new("pipe", 1);
+
+    for x in 0..8 {
+        for y in 0..8 {
+            if result[x][y] == horizontal {
+                assert!(x == 0 || result[x - 1][y] == horizontal);
+                assert!(y == 0 || result[x][y - 1].name == "empty");
+            } else if result[x][y] == vertical {
+                assert!(y == 0 || result[x][y - 1] == vertical);
+                assert!(x == 0 || result[x - 1][y].name == "empty");
+            }
+        }
+    }
+}
+
+#[test]
+fn tiled_model_rejects_unknown_tiles() {
+    assert_eq!(
+        "pipe 1".parse::<TileVariant>(),
+        Ok(TileVariant::new("pipe", 1))
+    );
+
+    let mut model = TiledModel::new();
+    model.add_tile("pipe", TileSymmetry::I, 1.);
+    model.add_neighbours(TileVariant::new("pipe", 0), TileVariant::new("valve", 0));
+    assert_eq!(
+        model.wave().err(),
+        Some(WaveError::UnknownTile {
+            name: "valve".to_string(),
+            orientation: 0
+        })
+    );
+}
+
+#[test]
+fn tiled_model_rejects_invalid_weights() {
+    for weight in [0., -1., f64::NAN, f64::INFINITY] {
+        let mut model = TiledModel::new();
+        model.add_tile("empty", TileSymmetry::X, 1.);
+        model.add_tile("pipe", TileSymmetry::I, weight);
+
+        assert_eq!(
+            model.wave().err(),
+            Some(WaveError::InvalidTileWeight {
+                name: "pipe".to_string()
+            })
+        );
+    }
+}
+
+#[test]
+fn volume_keeps_layers_in_order() {
+    // ground at the bottom, then grass, then sky, with some rock mixed into the ground
+    let sample = deconstruct_layers("GRG\nGGR\n\n,,,\n,,,\n\n...\n...", false).unwrap();
+    assert_eq!(volume::dimensions_of(&sample), Vector3::new(3, 2, 3));
+
+    let mut wave: Wave<char> = Wave::new();
+    wave.set_seed(5);
+    wave.analyze_volume(sample, Vector3::new(1, 1, 1), BorderMode::Clamp);
+    wave.fill_volume(Vector3::new(5, 4, 3)).expect("Fill failed.");
+    wave.constrain_voxel(Vector3::new(2, 2, 0), &['R'])
+        .expect("Constraint failed.");
+
+    assert_eq!(
+        wave.constrain_voxel(Vector3::new(0, 0, 3), &['G']),
+        Err(WaveError::OutOfBounds {
+            position: Vector3::new(0, 0, 3)
+        })
+    );
+
+    wave.collapse_all(20, no_callback()).expect("Collapse failed.");
+    let result = wave.perfect_volume().expect("Perfect representation failed.");
+
+    assert_eq!(volume::dimensions_of(&result), Vector3::new(5, 4, 3));
+    assert_eq!(result[2][2][0], 'R');
+
+    for column in &result {
+        for stack in column {
+            assert!(stack[0] == 'G' || stack[0] == 'R');
+            assert_eq!(stack[1..], [',', '.']);
+        }
+    }
+
+    assert_eq!(wave.perfect_rep().unwrap()[2][2], 'R');
+
+    let layers = reconstruct_layers(&result);
+    assert_eq!(deconstruct_layers(&layers, false), Ok(result));
+}
+
+#[test]
+fn hex_neighbours_are_offset_by_row() {
+    let size = Vector3::new(3, 3, 1);
+    let unwrapped = Vector2::new(false, false);
+
+    // odd rows are shifted to the right, so their diagonal neighbours are a column further along
+    assert_eq!(
+        Hex.neighbours(Vector3::new(1, 1, 0), size, unwrapped),
+        vec![
+            (0, Vector3::new(2, 1, 0)),
+            (1, Vector3::new(2, 0, 0)),
+            (2, Vector3::new(1, 0, 0)),
+            (3, Vector3::new(0, 1, 0)),
+            (4, Vector3::new(1, 2, 0)),
+            (5, Vector3::new(2, 2, 0)),
+        ]
+    );
+    assert_eq!(
+        Hex.neighbours(Vector3::new(1, 0, 0), size, unwrapped),
+        vec![
+            (0, Vector3::new(2, 0, 0)),
+            (3, Vector3::new(0, 0, 0)),
+            (4, Vector3::new(0, 1, 0)),
+            (5, Vector3::new(1, 1, 0)),
+        ]
+    );
+    assert_eq!(
+        Hex.neighbours(Vector3::new(0, 0, 0), Vector3::new(4, 4, 1), Vector2::new(true, true))
+            .len(),
+        6
+    );
+
+    for direction in 0..6 {
+        assert_eq!(Hex.opposite(Hex.opposite(direction)), direction);
+        assert_eq!(Hex.transform(Hex.transform(direction, true, 0), true, 0), direction);
+        assert_eq!(Hex.transform(direction, false, 3), Hex.opposite(direction));
+    }
+}
+
+#[test]
+fn hex_topology_learns_offset_rows() {
+    // each row only ever touches the same letter to its sides, and the next letter along above and below it
+    let sample = deconstruct_hex("a a a a\n b b b b\nc c c c\n a a a a\nb b b b\n c c c c");
+    let mut wave: Wave<char> = Wave::new();
+    wave.set_seed(4);
+    wave.set_symmetry(Symmetry::None);
+    wave.set_topology(Hex);
+
+    // hex adjacencies are only learned cell by cell
+    assert_eq!(
+        wave.analyze(sample.to_owned(), Vector2::new(2, 2), BorderMode::Clamp),
+        Err(WaveError::UnsupportedChunkSize { chunk_size: Vector2::new(2, 2) })
+    );
+
+    wave.analyze(sample, Vector2::new(1, 1), BorderMode::Clamp).expect("Analysis failed.");
+    wave.fill(Vector2::new(5, 6)).expect("Fill failed.");
+    wave.collapse_all(20, no_callback()).expect("Collapse failed.");
+
+    let result = wave.perfect_rep().expect("Perfect representation failed.");
+    let next = |ch: char| match ch {
+        'a' => 'b',
+        'b' => 'c',
+        _ => 'a',
+    };
+
+    for y in 0..6 {
+        for x in 0..5 {
+            assert_eq!(result[x][y], result[0][y]);
+        }
+
+        if y > 0 {
+            assert_eq!(result[0][y], next(result[0][y - 1]));
+        }
+    }
+
+    let rows = reconstruct_hex(result.to_owned());
+    assert!(rows.lines().nth(1).unwrap().starts_with(' '));
+    assert_eq!(deconstruct_hex(&rows), result);
+}
+
+#[test]
+fn graph_model_colours_regions() {
+    // five regions of a map, where neighbouring regions need different colours
+    let mut graph = Graph::new();
+    let regions: Vec<usize> = (0..5).map(|_| graph.add_node()).collect();
+    let border = graph.add_label().unwrap();
+    let (north, south) = graph.add_label_pair().unwrap();
+
+    for (a, b) in [(0, 1), (1, 2), (2, 0), (2, 3), (3, 4), (4, 1)] {
+        graph.connect(regions[a], regions[b], border).unwrap();
+    }
+
+    // the coast is always north of the sea
+    let sea = graph.add_node();
+    graph.connect(regions[0], sea, south).unwrap();
+
+    let mut model = GraphModel::new(graph);
+    let colours: Vec<usize> = ["red", "green", "blue"]
+        .into_iter()
+        .map(|colour| model.add_value(colour, 1.))
+        .collect();
+    let water = model.add_value("water", 1.);
+
+    for &a in &colours {
+        for &b in &colours {
+            if a != b {
+                model.allow(a, border, b).unwrap();
+            }
+        }
+
+        model.allow(water, north, a).unwrap();
+    }
+
+    assert_eq!(model.allow(water, north, 8), Err(WaveError::UnknownValue { id: 8 }));
+
+    let mut wave = model.wave().expect("Failed to create the wave.");
+    wave.set_seed(11);
+    wave.collapse_all(20, no_callback()).expect("Collapse failed.");
+
+    let result: Vec<&str> = wave
+        .perfect_rep()
+        .expect("Perfect representation failed.")
+        .into_iter()
+        .map(|column| column[0])
+        .collect();
+
+    assert_eq!(result[sea], "water");
+
+    for node in 0..model.graph().len() {
+        for &(label, neighbour) in model.graph().edges(node) {
+            if label == border {
+                assert_ne!(result[node], result[neighbour]);
+            }
+        }
+    }
+}
+
+#[test]
+fn graph_rejects_unknown_nodes_and_labels() {
+    let mut graph = Graph::new();
+    graph.add_node();
+    graph.add_node();
+    let border = graph.add_label().unwrap();
+
+    assert_eq!(graph.connect(0, 9, border), Err(WaveError::UnknownNode { node: 9 }));
+    assert_eq!(graph.connect(0, 1, 7), Err(WaveError::UnknownLabel { label: 7 }));
+
+    // labels are numbered with a u8, so there's only room for 256 of them
+    let mut labels = Graph::new();
+
+    for _ in 0..255 {
+        labels.add_label().unwrap();
+    }
+
+    assert_eq!(labels.add_label_pair(), Err(WaveError::TooManyLabels));
+    assert_eq!(labels.add_label(), Ok(255));
+    assert_eq!(labels.add_label(), Err(WaveError::TooManyLabels));
+}
+
+#[test]
+fn graph_model_rejects_invalid_weights() {
+    let mut graph = Graph::new();
+    graph.add_node();
+
+    for weight in [0., -1., f64::NAN, f64::INFINITY] {
+        let mut model = GraphModel::new(graph.clone());
+        model.add_value("land", 1.);
+        model.add_value("sea", weight);
+
+        assert_eq!(model.wave().err(), Some(WaveError::InvalidValueWeight { id: 1 }));
+    }
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn graph_deserialization_checks_edges() {
+    let mut graph = Graph::new();
+    let (a, b) = (graph.add_node(), graph.add_node());
+    let (up, _) = graph.add_label_pair().unwrap();
+    graph.connect(a, b, up).unwrap();
+
+    let json = serde_json::to_value(&graph).expect("Serialization failed.");
+    let restored: Graph = serde_json::from_value(json.clone()).expect("Deserialization failed.");
+    assert_eq!(restored.edges(a), graph.edges(a));
+
+    let mut missing_node = json.clone();
+    missing_node["edges"][0][0][1] = serde_json::json!(5);
+    assert!(serde_json::from_value::<Graph>(missing_node).is_err());
+
+    let mut missing_label = json.clone();
+    missing_label["edges"][0][0][0] = serde_json::json!(9);
+    assert!(serde_json::from_value::<Graph>(missing_label).is_err());
+
+    let mut unpaired = json;
+    unpaired["opposites"][0] = serde_json::json!(0);
+    assert!(serde_json::from_value::<Graph>(unpaired).is_err());
+}
+
+/// A wave of a sample where a and b are never next to each other, so every seam between regions has to be collapsed with both of its sides in mind.
+fn seam_wave() -> Wave<char> {
+    let sample = crate::stringtools::deconstruct_string("aacbb\naacbb\nccccc\nbbcaa\nbbcaa", false);
+    analyzed_wave(sample, Symmetry::default(), 0)
+}
+
+/// The order the regions of the chunked generator tests are generated in, with gaps between some of them.
+const REGION_ORDER: [(i64, i64); 7] = [(0, 0), (2, 1), (1, 0), (-1, -1), (1, 1), (0, 1), (-1, 0)];
+
+/// A generator of 3x4 regions of the `seam_wave`, which has generated every region of `REGION_ORDER`.
+fn generated_regions() -> ChunkedGenerator<char> {
+    let mut generator = ChunkedGenerator::new(seam_wave(), Vector2::new(3, 4), 7).unwrap();
+
+    for (x, y) in REGION_ORDER {
+        generator.region(Vector2::new(x, y)).expect("Region failed.");
+    }
+
+    generator
+}
+
+#[test]
+fn chunked_generator_lines_up_seams() {
+    let generator = generated_regions();
+
+    let generated = |generator: &ChunkedGenerator<char>, x: i64, y: i64| {
+        generator
+            .get(Vector2::new(x.div_euclid(3), y.div_euclid(4)))
+            .map(|region| region[x.rem_euclid(3) as usize][y.rem_euclid(4) as usize])
+    };
+
+    for x in -3..9_i64 {
+        for y in -4..8_i64 {
+            let Some(value) = generated(&generator, x, y) else {
+                continue;
+            };
+
+            for (nx, ny) in [(x + 1, y), (x, y + 1)] {
+                if let Some(neighbour) = generated(&generator, nx, ny) {
+                    assert!(
+                        !matches!((value, neighbour), ('a', 'b') | ('b', 'a')),
+                        "at {}, {}",
+                        x,
+                        y
+                    );
+                }
+            }
+        }
+    }
+}
+
+#[test]
+fn chunked_regions_dont_depend_on_their_order() {
+    let mut generator = generated_regions();
+
+    // the same world seed gives the same regions in any order, and after they're unloaded
+    let mut again = ChunkedGenerator::new(seam_wave(), Vector2::new(3, 4), 7).unwrap();
+
+    for (x, y) in REGION_ORDER.into_iter().rev() {
+        let coordinate = Vector2::new(x, y);
+        assert_eq!(again.region(coordinate).unwrap(), generator.get(coordinate).unwrap());
+    }
+
+    let unloaded = generator.unload(Vector2::new(1, 0)).unwrap();
+    assert!(generator.get(Vector2::new(1, 0)).is_none());
+    assert_eq!(generator.region(Vector2::new(1, 0)).unwrap(), unloaded);
+}
+
+#[test]
+fn chunked_generator_checks_the_region_size() {
+    let mut wave = seam_wave();
+
+    assert!(matches!(
+        ChunkedGenerator::new(wave.clone(), Vector2::new(1, 4), 7),
+        Err(WaveError::RegionTooSmall { .. })
+    ));
+
+    wave.analyze(vec![vec!['a'; 4]; 4], Vector2::new(2, 2), BorderMode::Clamp).expect("Analysis failed.");
+    assert!(matches!(
+        ChunkedGenerator::new(wave, Vector2::new(3, 4), 7),
+        Err(WaveError::SizeNotMultipleOfChunk { .. })
+    ));
+}
+
+#[test]
+fn chunked_generator_retries_failed_regions() {
+    let wave = analyzed_wave(alternating_sample(), Symmetry::default(), 0);
+
+    // giving up at the first contradiction, the region fails with its first seed, but one of the retries gets through
+    let generator = |max_retries: usize| {
+        let mut generator = ChunkedGenerator::new(wave.clone(), Vector2::new(8, 8), 25).unwrap();
+        generator.set_max_contradictions(1);
+        generator.set_max_retries(max_retries);
+        generator
+    };
+
+    assert!(matches!(
+        generator(0).region(Vector2::new(0, 0)),
+        Err(WaveError::MaxContradictions(_))
+    ));
+    assert!(generator(3).region(Vector2::new(0, 0)).is_ok());
+}
+
+#[test]
+fn chunked_generator_needs_a_planar_square_wave() {
+    let sample = vec![vec![0, 1], vec![1, 0]];
+
+    let mut hex = Wave::new();
+    hex.set_topology(Hex);
+    hex.analyze(sample, Vector2::new(1, 1), BorderMode::Clamp).expect("Analysis failed.");
+    assert!(matches!(
+        ChunkedGenerator::new(hex, Vector2::new(4, 4), 7),
+        Err(WaveError::UnsupportedTopology)
+    ));
+
+    let mut volume = Wave::new();
+    volume.analyze_volume(vec![vec![vec![0, 1]; 2]; 2], Vector3::new(1, 1, 2), BorderMode::Clamp);
+    assert!(matches!(
+        ChunkedGenerator::new(volume, Vector2::new(4, 4), 7),
+        Err(WaveError::UnsupportedTopology)
+    ));
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn serialized_wave_resumes_mid_collapse() {
+    let sample = crate::stringtools::deconstruct_string("aab\nabb\nbba\nbaa", false);
+    let mut wave = filled_wave(sample, Symmetry::default(), 21, Vector2::new(6, 6));
+    wave.set_heuristic(SelectionHeuristic::Mrv);
+    wave.set_backtracking(Some(BacktrackLimits::default()));
+    wave.constrain(Vector2::new(0, 0), &['b']).expect("Constraint failed.");
+
+    for _ in 0..5 {
+        wave.collapse_once();
+    }
+
+    let json = serde_json::to_string(&wave).expect("Serialization failed.");
+    let mut restored: Wave<char> = serde_json::from_str(&json).expect("Deserialization failed.");
+
+    assert_eq!(restored.seed(), wave.seed());
+    assert_eq!(restored.iterations, wave.iterations);
+    assert_eq!(restored.history.len(), wave.history.len());
+    assert_eq!(restored.current_rep(), wave.current_rep());
+    assert_eq!(serde_json::to_string(&restored).unwrap(), json);
+
+    // the random number generator carries on where it left off, so both finish the same way
+    wave.collapse_all(20, no_callback()).expect("Collapse failed.");
+    restored.collapse_all(20, no_callback()).expect("Collapse of the restored wave failed.");
+
+    assert_eq!(restored.perfect_rep().unwrap(), wave.perfect_rep().unwrap());
+    assert_eq!(restored.perfect_rep().unwrap()[0][0], 'b');
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn custom_topologies_dont_serialize() {
+    // topologies outside of the crate can't be serialized
+    #[derive(Debug)]
+    struct Line;
+
+    impl Topology for Line {
+        fn directions(&self) -> usize {
+            2
+        }
+
+        fn opposite(&self, direction: u8) -> u8 {
+            1 - direction
+        }
+
+        fn transform(&self, direction: u8, _mirror: bool, _rotations: usize) -> u8 {
+            direction
+        }
+
+        fn transforms(&self, _symmetry: Symmetry) -> Vec<(bool, usize)> {
+            vec![]
+        }
+
+        fn neighbours(
+            &self,
+            _position: Vector3<usize>,
+            _size: Vector3<usize>,
+            _periodic: Vector2<bool>,
+        ) -> Vec<(u8, Vector3<usize>)> {
+            vec![]
+        }
+    }
+
+    let mut wave = filled_wave(alternating_sample(), Symmetry::default(), 0, Vector2::new(4, 4));
+    assert!(serde_json::to_string(&wave).is_ok());
+
+    wave.set_topology(Line);
+    assert!(serde_json::to_string(&wave).is_err());
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn tampered_waves_fail_to_deserialize() {
+    let sample = crate::stringtools::deconstruct_string("aab\nabb\nbba", false);
+    let wave = filled_wave(sample, Symmetry::default(), 0, Vector2::new(3, 3));
+
+    // the state of the random number generator holds a u128, which a json value can't, so it's swapped in as text afterwards
+    let rng = serde_json::to_string(&wave.rng).expect("Serialization failed.");
+    let text = serde_json::to_string(&wave).expect("Serialization failed.");
+    let mut json: serde_json::Value = serde_json::from_str(&text).unwrap();
+    json["rng"] = "rng".into();
+
+    let tampered = |change: fn(&mut serde_json::Value)| {
+        let mut json = json.clone();
+        change(&mut json);
+        let text = json.to_string().replace(r#""rng":"rng""#, &format!(r#""rng":{}"#, rng));
+        serde_json::from_str::<Wave<char>>(&text)
+    };
+
+    assert!(tampered(|_| {}).is_ok());
+
+    assert!(tampered(|json| json["patterns"][0]["rules"][0]["direction"] = 9.into()).is_err());
+    assert!(tampered(|json| json["patterns"][0]["contents"] = serde_json::json!([[[0]], [[0]]])).is_err());
+    assert!(tampered(|json| json["patterns"][0]["contents"][0][0][0] = 7.into()).is_err());
+    assert!(tampered(|json| json["chunk_size"][0] = 0.into()).is_err());
+    assert!(tampered(|json| json["elements"][0]["position"] = serde_json::json!([1, 0, 0])).is_err());
+    assert!(tampered(|json| json["chunk_fill_size"] = serde_json::json!([usize::MAX, 2, 1])).is_err());
+    assert!(tampered(|json| json["palette"][1] = json["palette"][0].clone()).is_err());
+    assert!(tampered(|json| json["patterns"][0]["count"] = 0.into()).is_err());
+}
+
+#[test]
+fn ruleset_recreates_the_wave() {
+    let sample = crate::stringtools::deconstruct_string("aabc\nabbc\nccba\nbcaa", false);
+    let mut wave: Wave<char> = Wave::new();
+    wave.set_symmetry(Symmetry::Rotations);
+    wave.analyze(sample, Vector2::new(2, 2), BorderMode::Wrap).expect("Analysis failed.");
+
+    let ruleset = wave.ruleset();
+    assert_eq!(ruleset.patterns().len(), wave.patterns.len());
+    assert_eq!(ruleset.chunk_size(), Vector3::new(2, 2, 1));
+    assert_eq!(ruleset.values(), wave.palette().values());
+
+    let mut loaded = Wave::from_ruleset(ruleset.clone()).expect("Loading the rules failed.");
+    assert_eq!(loaded.propagator, wave.propagator);
+    assert_eq!(loaded.patterns, wave.patterns);
+
+    for wave in [&mut wave, &mut loaded] {
+        wave.set_seed(5);
+        wave.fill(Vector2::new(8, 8)).expect("Fill failed.");
+        wave.collapse_all(20, no_callback()).expect("Collapse failed.");
+    }
+
+    assert_eq!(loaded.perfect_rep().unwrap(), wave.perfect_rep().unwrap());
+}
+
+#[test]
+fn invalid_rulesets_are_rejected() {
+    // rules which refer to patterns that don't exist are rejected rather than loaded
+    let mut broken = analyzed_wave(alternating_sample(), Symmetry::default(), 0).ruleset();
+    broken.patterns[0].compatible[0].push(99);
+    assert!(matches!(
+        Wave::from_ruleset(broken),
+        Err(WaveError::InvalidRuleset { .. })
+    ));
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn ruleset_serializes_with_its_version() {
+    let mut model = TiledModel::new();
+    model.add_tile("line", TileSymmetry::I, 2.);
+    model.add_tile("corner", TileSymmetry::L, 1.);
+    model.add_neighbours(TileVariant::new("line", 0), TileVariant::new("corner", 0));
+    model.add_neighbours(TileVariant::new("corner", 1), TileVariant::new("line", 0));
+    let ruleset = model.wave().expect("Failed to create the wave.").ruleset();
+
+    let json = serde_json::to_string(&ruleset).expect("Serialization failed.");
+    let from_json: Ruleset<TileVariant> = serde_json::from_str(&json).unwrap();
+    assert_eq!(from_json.patterns(), ruleset.patterns());
+    assert_eq!(from_json.values(), ruleset.values());
+
+    let mut bytes = vec![];
+    ciborium::ser::into_writer(&ruleset, &mut bytes).expect("Binary serialization failed.");
+    assert!(bytes.len() < json.len());
+    let from_bytes: Ruleset<TileVariant> = ciborium::de::from_reader(bytes.as_slice()).unwrap();
+    assert_eq!(from_bytes.patterns(), ruleset.patterns());
+
+    let mut value: serde_json::Value = serde_json::from_str(&json).unwrap();
+    value["version"] = serde_json::json!(crate::ruleset::RULESET_VERSION + 1);
+    assert!(serde_json::from_value::<Ruleset<TileVariant>>(value.clone()).is_err());
+
+    // a pattern which allows a pattern that doesn't exist is caught as well
+    value["version"] = serde_json::json!(crate::ruleset::RULESET_VERSION);
+    value["patterns"][0]["compatible"][0] = serde_json::json!([99]);
+    assert!(serde_json::from_value::<Ruleset<TileVariant>>(value).is_err());
+}
+
+#[test]
+fn undo_and_redo_restore_propagation() {
+    let mut wave = filled_wave(alternating_sample(), Symmetry::default(), 3, Vector2::new(6, 6));
+
+    let mut states = vec![wave.current_rep()];
+
+    for _ in 0..5 {
+        wave.collapse_once();
+        states.push(wave.current_rep());
+    }
+
+    // every collapse propagated further than its own element
+    assert!(wave.history.iter().all(|r| r.trail.len() > 1));
+
+    for state in states.iter().rev().skip(1) {
+        wave.undo_collapse(false).expect("Undo failed.");
+        assert_eq!(&wave.current_rep(), state);
+    }
+
+    assert_eq!(wave.iterations, 0);
+
+    for state in states.iter().skip(1) {
+        wave.redo_collapse().expect("Redo failed.");
+        assert_eq!(&wave.current_rep(), state);
+    }
+
+    // forgetting the last two collapses leaves nothing to redo
+    wave.undo_collapse(true).expect("Undo failed.");
+    wave.undo_collapse(true).expect("Undo failed.");
+    wave.redo_collapse().expect("Redo failed.");
+    assert_eq!(wave.current_rep(), states[3]);
+    assert_eq!(wave.history.len(), 3);
+}
+
+#[test]
+fn generation_log_replays_the_output() {
+    // a run which backtracked, so its log has bans as well as observations
+    let (wave, log) = (0..20)
+        .map(|seed| {
+            let mut wave = contradicting_wave(seed, Vector2::new(12, 12));
+            wave.set_backtracking(Some(BacktrackLimits::default()));
+            wave.constrain(Vector2::new(0, 0), &[1]).expect("Constraint failed.");
+            wave.collapse_all(100, no_callback()).expect("Collapse failed.");
+
+            let log = wave.generation_log().expect("The wave has no log.");
+            (wave, log)
+        })
+        .find(|(_, log)| log.decisions().iter().any(|d| matches!(d, Decision::Banned { .. })))
+        .expect("No run backtracked.");
+
+    let mut replayed = Wave::from_ruleset(wave.ruleset()).expect("Loading the rules failed.");
+    replayed.replay(&log, None).expect("Replay failed.");
+    assert_eq!(replayed.perfect_rep().unwrap(), wave.perfect_rep().unwrap());
+    assert_eq!(replayed.generation_log().unwrap(), log);
+
+    // replaying part of the log stops at that step
+    replayed.replay(&log, Some(10)).expect("Replay failed.");
+    assert_eq!(replayed.generation_log().unwrap().decisions(), &log.decisions()[..10]);
+
+    replayed.replay(&log, Some(0)).expect("Replay failed.");
+    let mut filled = Wave::from_ruleset(wave.ruleset()).expect("Loading the rules failed.");
+    filled.fill(Vector2::new(12, 12)).expect("Fill failed.");
+    filled.constrain(Vector2::new(0, 0), &[1]).expect("Constraint failed.");
+    assert_eq!(replayed.current_rep(), filled.current_rep());
+}
+
+#[test]
+fn replay_needs_the_same_rules() {
+    let mut wave = contradicting_wave(0, Vector2::new(6, 6));
+    wave.collapse_all(100, no_callback()).expect("Collapse failed.");
+    let log = wave.generation_log().expect("The wave has no log.");
+
+    let mut other = analyzed_wave(vec![vec![0, 1, 2]], Symmetry::default(), 0);
+    assert!(matches!(
+        other.replay(&log, None),
+        Err(WaveError::RulesetMismatch { .. })
+    ));
+}
+
+#[test]
+fn replay_replaces_the_waves_constraints() {
+    let mut wave = filled_wave(alternating_sample(), Symmetry::default(), 4, Vector2::new(8, 8));
+    wave.constrain(Vector2::new(0, 0), &[1]).expect("Constraint failed.");
+    wave.collapse_all(100, no_callback()).expect("Collapse failed.");
+    let log = wave.generation_log().expect("The wave has no log.");
+
+    // constraints which contradict the log's are dropped, rather than making the replay fail
+    let mut replayed = Wave::from_ruleset(wave.ruleset()).expect("Loading the rules failed.");
+    replayed.fill(Vector2::new(8, 8)).expect("Fill failed.");
+    replayed.constrain(Vector2::new(0, 0), &[2]).expect("Constraint failed.");
+    replayed.limit_count(&1, 0, Some(0)).expect("Limit failed.");
+    replayed.replay(&log, None).expect("Replay failed.");
+
+    assert_eq!(replayed.perfect_rep().unwrap(), wave.perfect_rep().unwrap());
+    assert_eq!(replayed.constraints, wave.constraints);
+    assert!(replayed.count_limits.is_empty());
+}
+
+#[test]
+fn collapse_parallel_keeps_the_first_success() {
+    let mut wave = filled_wave(alternating_sample(), Symmetry::None, 1, Vector2::new(12, 12));
+    let mut fresh = wave.clone();
+
+    wave.collapse_parallel(4, |_, wave| {
+        wave.collapse_all(100, no_callback())
+    })
+    .expect("Collapse failed.");
+
+    let result = wave.perfect_rep().unwrap();
+
+    // the winner's seed reproduces its result on a single thread
+    fresh.set_seed(wave.seed());
+    fresh.collapse_all(100, no_callback()).expect("Collapse failed.");
+    assert_eq!(fresh.perfect_rep().unwrap(), result);
+}
+
+#[test]
+fn collapse_parallel_needs_a_filled_wave() {
+    let mut unfilled = analyzed_wave(alternating_sample(), Symmetry::None, 1);
+
+    assert_eq!(
+        unfilled.collapse_parallel(3, |_, wave| {
+            wave.collapse_all(100, no_callback())
+        }),
+        Err(WaveError::NotFilled)
+    );
+}
+
+#[test]
+fn budgets_and_cancelling_leave_the_wave_resumable() {
+    let sample = vec![vec![0, 1, 2, 1], vec![1, 2, 1, 0], vec![2, 1, 0, 1]];
+    let mut wave = filled_wave(sample, Symmetry::default(), 8, Vector2::new(10, 10));
+
+    let mut uninterrupted = wave.clone();
+    uninterrupted.collapse_all(20, no_callback()).expect("Collapse failed.");
+
+    wave.set_budget(CollapseBudget {
+        deadline: None,
+        max_observations: Some(5),
+    });
+    assert_eq!(wave.collapse_all(20, no_callback()), Err(WaveError::ObservationLimitReached));
+    assert_eq!(wave.iterations, 5);
+
+    wave.set_budget(CollapseBudget {
+        deadline: Some(Instant::now()),
+        max_observations: None,
+    });
+    assert_eq!(wave.collapse_all(20, no_callback()), Err(WaveError::DeadlineExceeded));
+
+    let token = Arc::new(AtomicBool::new(true));
+    wave.set_budget(CollapseBudget::default());
+    wave.set_cancel_token(Some(token.clone()));
+    assert_eq!(wave.collapse_all(20, no_callback()), Err(WaveError::Cancelled));
+    assert_eq!(wave.iterations, 5);
+
+    // carrying on afterwards finishes the same way as if it had never stopped
+    token.store(false, Ordering::Relaxed);
+    wave.collapse_all(20, no_callback()).expect("Collapse failed.");
+    assert_eq!(wave.perfect_rep().unwrap(), uninterrupted.perfect_rep().unwrap());
+}
+
+/// A sample with some more of its values than others, to set count limits on.
+fn count_sample() -> Vec<Vec<usize>> {
+    vec![
+        vec![0, 0, 1, 0, 2],
+        vec![0, 1, 1, 2, 0],
+        vec![1, 0, 2, 2, 1],
+        vec![0, 2, 0, 1, 0],
+    ]
+}
+
+#[test]
+fn count_limits_hold_over_the_output() {
+    let sample = count_sample();
+    let count = |rep: &Vec<Vec<usize>>, value: usize| rep.iter().flatten().filter(|&&v| v == value).count();
+
+    for seed in 0..5 {
+        let mut wave = analyzed_wave(sample.to_owned(), Symmetry::default(), seed);
+        wave.set_backtracking(Some(BacktrackLimits::default()));
+        wave.limit_count(&2, 1, Some(1)).expect("Limit failed.");
+        wave.limit_count(&1, 0, Some(10)).expect("Limit failed.");
+        wave.fill(Vector2::new(8, 8)).expect("Fill failed.");
+        wave.limit_count(&0, 40, None).expect("Limit failed.");
+        wave.collapse_all(100, no_callback()).expect("Collapse failed.");
+
+        let rep = wave.perfect_rep().unwrap();
+        assert_eq!(count(&rep, 2), 1);
+        assert!(count(&rep, 1) <= 10);
+        assert!(count(&rep, 0) >= 40);
+
+        // the counts kept up to date through the collapse, its backtracking and undoing it match counting from scratch
+        for _ in 0..10 {
+            wave.undo_collapse(false).expect("Undo failed.");
+        }
+
+        wave.redo_collapse().expect("Redo failed.");
+
+        let kept = wave.counts.as_ref().expect("The values weren't counted.");
+        let values: Vec<usize> = wave.count_limits.iter().map(|l| l.value).collect();
+        let counted = ValueCounts::new(&values, &wave.patterns, wave.chunk_size, &wave.elements);
+
+        for slot in 0..values.len() {
+            assert_eq!(kept.decided(slot), counted.decided(slot));
+            assert_eq!(kept.possible(slot), counted.possible(slot));
+        }
+    }
+}
+
+#[test]
+fn unsatisfiable_count_limits_are_reported() {
+    let mut wave = analyzed_wave(count_sample(), Symmetry::default(), 0);
+    assert_eq!(wave.limit_count(&1, 3, Some(2)), Err(WaveError::UnsatisfiableCount { id: 1 }));
+    assert!(wave.limit_count(&7, 0, Some(2)).is_ok());
+    assert_eq!(wave.limit_count(&7, 1, None), Err(WaveError::UnknownCountValue { min: 1 }));
+
+    // there aren't enough cells for the minimum
+    wave.limit_count(&2, 17, None).expect("Limit failed.");
+    assert_eq!(wave.fill(Vector2::new(4, 4)), Err(WaveError::UnsatisfiableCount { id: 2 }));
+
+    wave.clear_constraints();
+    wave.fill(Vector2::new(4, 4)).expect("Fill failed.");
+    assert_eq!(wave.limit_count(&2, 17, None), Err(WaveError::UnsatisfiableCount { id: 2 }));
+}
+
+#[test]
+fn redoing_a_collapse_enforces_the_count_limits() {
+    let mut wave = filled_wave(count_sample(), Symmetry::default(), 3, Vector2::new(4, 4));
+    wave.collapse_once();
+
+    let record = wave.history.last().expect("Nothing was collapsed.");
+    let observed = wave.element_index(record.location());
+    let id = wave.patterns[record.chosen_pattern_id].contents[0][0][0];
+    let value = *wave.palette.value(id).unwrap();
+
+    // the limit is set while the collapse is undone, so only redoing it reaches the maximum
+    wave.undo_collapse(false).expect("Undo failed.");
+    wave.limit_count(&value, 0, Some(1)).expect("Limit failed.");
+    assert!(!wave.possible_cells(id).is_empty());
+
+    wave.redo_collapse().expect("Redo failed.");
+    assert!(!wave.contradiction_occurred());
+    assert!(wave.possible_cells(id).iter().all(|&(index, _)| index == observed));
+}
@@ -0,0 +1,260 @@
+use crate::{Wave, WaveError};
+use std::collections::HashMap;
+use std::fmt;
+use std::str::FromStr;
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+/// The symmetry class of a tile in a `TiledModel`, named after the letter with the same symmetries, as in Maxim Gumin's tileset format.
+///
+/// The class determines how many distinct orientations the tile has, and so how many variants of it are placed in the output. The tile's own orientation has to match the one described for its class, as that's the orientation 0 of its variants.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum TileSymmetry {
+    /// Looks the same in every orientation, like a plain tile. It has 1 variant.
+    #[default]
+    X,
+    /// Looks the same when turned upside down or mirrored, like a straight line. It has 2 variants.
+    I,
+    /// Looks the same when turned upside down or mirrored along its diagonal, like a `\`. It has 2 variants.
+    Diagonal,
+    /// Looks the same when mirrored horizontally, like a T junction with its stem pointing up or down. It has 4 variants.
+    T,
+    /// Looks the same mirrored horizontally as it does rotated a quarter turn counter-clockwise, like a corner joining the top and right edges. It has 4 variants.
+    L,
+    /// Has no symmetries at all. It has 8 variants.
+    F,
+}
+
+impl TileSymmetry {
+    /// The number of distinct orientations of a tile with this symmetry.
+    pub fn cardinality(&self) -> usize {
+        match self {
+            TileSymmetry::X => 1,
+            TileSymmetry::I | TileSymmetry::Diagonal => 2,
+            TileSymmetry::T | TileSymmetry::L => 4,
+            TileSymmetry::F => 8,
+        }
+    }
+
+    /// The orientation reached by rotating the given one a quarter turn counter-clockwise.
+    fn rotate(&self, orientation: usize) -> usize {
+        match self {
+            TileSymmetry::X => orientation,
+            TileSymmetry::I | TileSymmetry::Diagonal => 1 - orientation,
+            TileSymmetry::T | TileSymmetry::L => (orientation + 1) % 4,
+            TileSymmetry::F if orientation < 4 => (orientation + 1) % 4,
+            TileSymmetry::F => 4 + (orientation - 1) % 4,
+        }
+    }
+
+    /// The orientation reached by mirroring the given one horizontally.
+    fn reflect(&self, orientation: usize) -> usize {
+        match self {
+            TileSymmetry::X | TileSymmetry::I => orientation,
+            TileSymmetry::Diagonal => 1 - orientation,
+            TileSymmetry::T if orientation.is_multiple_of(2) => orientation,
+            TileSymmetry::T => 4 - orientation,
+            TileSymmetry::L if orientation.is_multiple_of(2) => orientation + 1,
+            TileSymmetry::L => orientation - 1,
+            TileSymmetry::F if orientation < 4 => orientation + 4,
+            TileSymmetry::F => orientation - 4,
+        }
+    }
+
+    /// The orientations reached from the given one by each of the 8 transforms of a square: the 4 rotations, and then the 4 rotations mirrored.
+    fn transforms(&self, orientation: usize) -> [usize; 8] {
+        let mut result = [orientation; 8];
+
+        for i in 1..4 {
+            result[i] = self.rotate(result[i - 1]);
+        }
+
+        for i in 0..4 {
+            result[i + 4] = self.reflect(result[i]);
+        }
+
+        result
+    }
+}
+
+impl FromStr for TileSymmetry {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "X" => Ok(TileSymmetry::X),
+            "I" => Ok(TileSymmetry::I),
+            "\\" => Ok(TileSymmetry::Diagonal),
+            "T" => Ok(TileSymmetry::T),
+            "L" => Ok(TileSymmetry::L),
+            "F" => Ok(TileSymmetry::F),
+            _ => Err(format!("{} isn't a tile symmetry (X, I, \\, T, L or F)", s)),
+        }
+    }
+}
+
+/// A tile of a `TiledModel` in one of its orientations. These are the values of the wave created by the model.
+///
+/// Orientations below 4 are the tile rotated a quarter turn counter-clockwise that many times. Orientations from 4 up are those same rotations, mirrored horizontally afterwards.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct TileVariant {
+    pub name: String,
+    pub orientation: usize,
+}
+
+impl TileVariant {
+    pub fn new(name: &str, orientation: usize) -> Self {
+        Self {
+            name: name.to_string(),
+            orientation,
+        }
+    }
+
+    /// The number of counter-clockwise quarter turns applied to the tile.
+    pub fn rotations(&self) -> usize {
+        self.orientation % 4
+    }
+
+    /// Whether the tile is mirrored horizontally after being rotated.
+    pub fn mirrored(&self) -> bool {
+        self.orientation >= 4
+    }
+}
+
+/// Parses a tile name optionally followed by a space and an orientation, such as `corner 3`. The orientation is 0 if it's left out.
+impl FromStr for TileVariant {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+
+        match s.rsplit_once(' ') {
+            Some((name, orientation)) => orientation
+                .parse()
+                .map(|o| TileVariant::new(name.trim_end(), o))
+                .map_err(|_| format!("{} isn't a valid orientation of {}", orientation, name)),
+            None => Ok(TileVariant::new(s, 0)),
+        }
+    }
+}
+
+impl fmt::Display for TileVariant {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {}", self.name, self.orientation)
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Tile {
+    name: String,
+    symmetry: TileSymmetry,
+    weight: f64,
+}
+
+/// The simple tiled model, where the tiles and which of them can be placed next to each other are declared explicitly, rather than learned from a sample.
+///
+/// Only one orientation of each pair of neighbours has to be given. Every rotation and mirroring of the pair is allowed as well, limited by the symmetry of the tiles. Once the tiles are set up, `wave` creates a `Wave` which is filled and collapsed as usual.
+#[derive(Debug, Clone, Default)]
+pub struct TiledModel {
+    tiles: Vec<Tile>,
+    /// Pairs of tiles where the second can be placed directly to the right of the first.
+    neighbours: Vec<(TileVariant, TileVariant)>,
+}
+
+impl TiledModel {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a tile, or replaces the one with the same name. Tiles are chosen in proportion to their weights, which must be positive and finite.
+    pub fn add_tile(&mut self, name: &str, symmetry: TileSymmetry, weight: f64) {
+        let tile = Tile {
+            name: name.to_string(),
+            symmetry,
+            weight,
+        };
+
+        match self.tiles.iter_mut().find(|t| t.name == name) {
+            Some(existing) => *existing = tile,
+            None => self.tiles.push(tile),
+        }
+    }
+
+    /// Allows `right` to be placed directly to the right of `left`, along with every rotation and mirroring of the pair.
+    pub fn add_neighbours(&mut self, left: TileVariant, right: TileVariant) {
+        self.neighbours.push((left, right));
+    }
+
+    /// Every distinct orientation of every tile, in the order they're given ids in the wave.
+    pub fn variants(&self) -> Vec<TileVariant> {
+        self.tiles
+            .iter()
+            .flat_map(|tile| {
+                (0..tile.symmetry.cardinality()).map(|o| TileVariant::new(&tile.name, o))
+            })
+            .collect()
+    }
+
+    /// Creates a wave whose values are the variants of the tiles, with the rules given by the neighbours.
+    ///
+    /// The wave works on single cells, so it can be filled with any size. An error is returned if a tile's weight isn't positive and finite, or if a neighbour refers to a tile that hasn't been added.
+    pub fn wave(&self) -> Result<Wave<TileVariant>, WaveError> {
+        if let Some(tile) = self.tiles.iter().find(|t| !(t.weight.is_finite() && t.weight > 0.)) {
+            return Err(WaveError::InvalidTileWeight {
+                name: tile.name.clone(),
+            });
+        }
+
+        let mut offsets: HashMap<&str, (usize, &Tile)> = HashMap::new();
+        let mut transforms: Vec<[usize; 8]> = vec![];
+        let mut weights = vec![];
+
+        for tile in &self.tiles {
+            let offset = transforms.len();
+            offsets.insert(&tile.name, (offset, tile));
+
+            for orientation in 0..tile.symmetry.cardinality() {
+                transforms.push(tile.symmetry.transforms(orientation).map(|o| o + offset));
+                weights.push(tile.weight);
+            }
+        }
+
+        let index = |variant: &TileVariant| match offsets.get(variant.name.as_str()) {
+            Some((offset, tile)) if variant.orientation < 8 => {
+                Ok(offset + tile.symmetry.transforms(0)[variant.orientation])
+            }
+            _ => Err(WaveError::UnknownTile {
+                name: variant.name.clone(),
+                orientation: variant.orientation,
+            }),
+        };
+
+        // Each rule allows the neighbour on the given side of the tile, using the wave's directions (0: +y, 1: +x, 2: -y, 3: -x). Both rules of a pair are added when propagating, so only one side of each is needed here.
+        let mut rules = vec![];
+
+        for (left, right) in &self.neighbours {
+            let left = index(left)?;
+            let right = index(right)?;
+            // the pair rotated a quarter turn counter-clockwise, which puts the left tile below the right one
+            let below = transforms[left][1];
+            let above = transforms[right][1];
+
+            rules.push((right, 3, left));
+            rules.push((transforms[right][6], 3, transforms[left][6]));
+            rules.push((transforms[left][4], 3, transforms[right][4]));
+            rules.push((transforms[left][2], 3, transforms[right][2]));
+
+            rules.push((above, 0, below));
+            rules.push((transforms[below][6], 0, transforms[above][6]));
+            rules.push((transforms[above][4], 0, transforms[below][4]));
+            rules.push((transforms[below][2], 0, transforms[above][2]));
+        }
+
+        let mut wave = Wave::new();
+        wave.load_tiles(self.variants(), &weights, &rules);
+
+        Ok(wave)
+    }
+}
@@ -0,0 +1,292 @@
+use crate::helpers::{opposite_direction, overlapping_adjacencies, volume, BorderMode};
+use crate::{dimensions_of, wrap_axis, Symmetry};
+use cgmath::{Vector2, Vector3};
+use std::any::Any;
+use std::fmt;
+
+#[cfg(feature = "serde")]
+use crate::Graph;
+#[cfg(feature = "serde")]
+use serde::{ser::Error as _, Deserialize, Deserializer, Serialize, Serializer};
+#[cfg(feature = "serde")]
+use std::sync::Arc;
+
+/// The shape of the grid a `Wave` works on, which decides how its elements neighbour each other.
+///
+/// Directions are numbered from 0 up to `directions`, and patterns learn one rule per direction they have a neighbour in. Positions are those of the wave's elements, with a z of 0 for grids which aren't volumes. A topology is set with `Wave::set_topology` before analyzing a sample.
+pub trait Topology: Any + fmt::Debug + Send + Sync {
+    /// The number of directions an element can have neighbours in.
+    fn directions(&self) -> usize;
+
+    /// The direction pointing back the way the given one came.
+    fn opposite(&self, direction: u8) -> u8;
+
+    /// The direction reached by mirroring the given one horizontally (if `mirror` is set) and then rotating it `rotations` times by the grid's smallest rotation.
+    fn transform(&self, direction: u8, mirror: bool, rotations: usize) -> u8;
+
+    /// The transforms of the symmetry group on this grid, each as whether the pattern is first mirrored horizontally and then the number of rotations. The identity is left out.
+    fn transforms(&self, symmetry: Symmetry) -> Vec<(bool, usize)>;
+
+    /// Finds the neighbours of the element at `position` in a grid of `size` elements, alongside the direction they lie in.
+    ///
+    /// Neighbours past the edges of the grid only exist on periodic axes, where they wrap around to the opposite edge.
+    fn neighbours(
+        &self,
+        position: Vector3<usize>,
+        size: Vector3<usize>,
+        periodic: Vector2<bool>,
+    ) -> Vec<(u8, Vector3<usize>)>;
+
+    /// Whether `adjacencies` can split samples into chunks of the given size. `Wave::analyze` rejects any other size up front.
+    ///
+    /// By default, only chunks of a single cell are supported.
+    fn supports_chunk_size(&self, chunk_size: Vector2<usize>) -> bool {
+        chunk_size == Vector2::new(1, 1)
+    }
+
+    /// Finds the adjacencies of every chunk of the sample, which are learned as patterns by `Wave::analyze`.
+    ///
+    /// By default, every cell of the sample is its own chunk and its neighbours are found with `neighbours`, so other chunk sizes are turned down by `supports_chunk_size`. This only supports topologies with up to 6 directions.
+    fn adjacencies(
+        &self,
+        sample: Vec<Vec<usize>>,
+        chunk_size: Vector2<usize>,
+        border_mode: BorderMode,
+    ) -> Vec<volume::Adjacency<usize>> {
+        if chunk_size != Vector2::new(1, 1) {
+            return vec![];
+        }
+
+        let size = dimensions_of(&sample).extend(1);
+        let wrap = border_mode == BorderMode::Wrap;
+        let cell = |p: Vector3<usize>| vec![vec![vec![sample[p.x][p.y]]]];
+        let mut list = vec![];
+
+        for x in 0..size.x {
+            for y in 0..size.y {
+                let position = Vector3::new(x, y, 0);
+                let neighbours = self.neighbours(position, size, Vector2::new(wrap, wrap));
+
+                if border_mode == BorderMode::Exclude && neighbours.len() < self.directions() {
+                    continue;
+                }
+
+                let mut adjacency = volume::Adjacency::new(cell(position));
+
+                for (direction, neighbour) in neighbours {
+                    adjacency.neighbours_content[direction as usize] = Some(cell(neighbour));
+                }
+
+                list.push(adjacency);
+            }
+        }
+
+        list
+    }
+}
+
+/// The square grid, where elements have neighbours above, below and to either side of them, and along the z axis for volumes.
+///
+/// Directions are 0: +y, 1: +x, 2: -y, 3: -x, 4: +z and 5: -z. This is the default topology, and the only one which supports chunks larger than a single cell.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Square;
+
+impl Topology for Square {
+    fn directions(&self) -> usize {
+        6
+    }
+
+    fn opposite(&self, direction: u8) -> u8 {
+        opposite_direction(direction)
+    }
+
+    fn transform(&self, direction: u8, mirror: bool, rotations: usize) -> u8 {
+        // transforms happen around the z axis, which leaves its directions as they are
+        if direction >= 4 {
+            return direction;
+        }
+
+        // only horizontal rules are swapped when mirroring
+        let direction = if mirror && direction % 2 == 1 {
+            opposite_direction(direction)
+        } else {
+            direction
+        };
+
+        // every rotation turns up into left, right into up, and so on
+        (direction + 3 * rotations as u8) % 4
+    }
+
+    fn transforms(&self, symmetry: Symmetry) -> Vec<(bool, usize)> {
+        symmetry.transforms()
+    }
+
+    fn neighbours(
+        &self,
+        position: Vector3<usize>,
+        size: Vector3<usize>,
+        periodic: Vector2<bool>,
+    ) -> Vec<(u8, Vector3<usize>)> {
+        if !periodic.x && !periodic.y {
+            return volume::noneg_neighbours(&position)
+                .into_iter()
+                .filter(|n| n.x < size.x && n.y < size.y && n.z < size.z)
+                .map(|n| (volume::orthog_direction(&position, &n), n))
+                .collect();
+        }
+
+        let mut list = Vec::with_capacity(6);
+
+        for (direction, (dx, dy, dz)) in volume::DIRECTION_OFFSETS.into_iter().enumerate() {
+            let x = wrap_axis(position.x as isize + dx, size.x, periodic.x);
+            let y = wrap_axis(position.y as isize + dy, size.y, periodic.y);
+            let z = wrap_axis(position.z as isize + dz, size.z, false);
+
+            if let (Some(x), Some(y), Some(z)) = (x, y, z) {
+                list.push((direction as u8, Vector3::new(x, y, z)));
+            }
+        }
+
+        list
+    }
+
+    fn supports_chunk_size(&self, _chunk_size: Vector2<usize>) -> bool {
+        true
+    }
+
+    fn adjacencies(
+        &self,
+        sample: Vec<Vec<usize>>,
+        chunk_size: Vector2<usize>,
+        border_mode: BorderMode,
+    ) -> Vec<volume::Adjacency<usize>> {
+        overlapping_adjacencies(sample, chunk_size, border_mode)
+            .into_iter()
+            .map(volume::Adjacency::from)
+            .collect()
+    }
+}
+
+/// A grid of pointy topped hexagons, stored in offset rows where every odd row is shifted half a cell to the right.
+///
+/// Elements are indexed as `[column][row]` like any other grid, and their six neighbours are found by converting them to axial coordinates. Directions go around the hexagon counter-clockwise as 0: right, 1: up right, 2: up left, 3: left, 4: down left and 5: down right, where up is towards the first row. Samples are learned one cell at a time, and only planar grids are supported.
+///
+/// Wrapping the rows around on a periodic y axis only lines up when there's an even number of them.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Hex;
+
+impl Hex {
+    /// The axial offset of the neighbour in each direction, where the second axis is the row.
+    const AXIAL_OFFSETS: [(isize, isize); 6] = [(1, 0), (1, -1), (0, -1), (-1, 0), (-1, 1), (0, 1)];
+}
+
+impl Topology for Hex {
+    fn directions(&self) -> usize {
+        6
+    }
+
+    fn opposite(&self, direction: u8) -> u8 {
+        (direction + 3) % 6
+    }
+
+    fn transform(&self, direction: u8, mirror: bool, rotations: usize) -> u8 {
+        // mirroring swaps right with left, and each diagonal with the one across from it
+        let direction = if mirror {
+            (9 - direction) % 6
+        } else {
+            direction
+        };
+
+        (direction + rotations as u8) % 6
+    }
+
+    fn transforms(&self, symmetry: Symmetry) -> Vec<(bool, usize)> {
+        let rotations = |mirror: bool| (0..6).map(move |r| (mirror, r));
+
+        match symmetry {
+            Symmetry::None => vec![],
+            Symmetry::MirrorX => vec![(true, 0)],
+            // mirroring horizontally and then rotating by 180° mirrors vertically
+            Symmetry::MirrorY => vec![(true, 3)],
+            Symmetry::MirrorXY => vec![(true, 0), (true, 3), (false, 3)],
+            Symmetry::Rotations => rotations(false).skip(1).collect(),
+            Symmetry::Full => rotations(false).skip(1).chain(rotations(true)).collect(),
+        }
+    }
+
+    fn neighbours(
+        &self,
+        position: Vector3<usize>,
+        size: Vector3<usize>,
+        periodic: Vector2<bool>,
+    ) -> Vec<(u8, Vector3<usize>)> {
+        let row = position.y as isize;
+        let q = position.x as isize - (row - (row & 1)) / 2;
+        let mut list = Vec::with_capacity(6);
+
+        for (direction, (dq, dr)) in Self::AXIAL_OFFSETS.into_iter().enumerate() {
+            let neighbour_row = row + dr;
+            let column = q + dq + (neighbour_row - (neighbour_row & 1)) / 2;
+
+            let x = wrap_axis(column, size.x, periodic.x);
+            let y = wrap_axis(neighbour_row, size.y, periodic.y);
+
+            if let (Some(x), Some(y)) = (x, y) {
+                list.push((direction as u8, Vector3::new(x, y, position.z)));
+            }
+        }
+
+        list
+    }
+}
+
+/// Serializes the topology it wraps, for saving it along with a wave or a ruleset.
+///
+/// Only the topologies of this crate can be serialized, as there's no telling what a custom topology is from behind the trait.
+#[cfg(feature = "serde")]
+pub(crate) struct SerializedTopology<'a>(pub &'a dyn Topology);
+
+/// The topologies which can be serialized.
+#[cfg(feature = "serde")]
+#[derive(Serialize, Deserialize)]
+enum TopologyKind {
+    Square,
+    Hex,
+    Graph(Graph),
+}
+
+#[cfg(feature = "serde")]
+impl Serialize for SerializedTopology<'_> {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let topology: &dyn Any = self.0;
+
+        let kind = if topology.is::<Square>() {
+            TopologyKind::Square
+        } else if topology.is::<Hex>() {
+            TopologyKind::Hex
+        } else if let Some(graph) = topology.downcast_ref::<Graph>() {
+            TopologyKind::Graph(graph.clone())
+        } else {
+            let message = format!("the topology {:?} can't be serialized", self.0);
+            return Err(S::Error::custom(message));
+        };
+
+        kind.serialize(serializer)
+    }
+}
+
+/// Deserializes a topology written with `SerializedTopology`.
+#[cfg(feature = "serde")]
+pub(crate) fn deserialize_topology<'de, D>(deserializer: D) -> Result<Arc<dyn Topology>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    Ok(match TopologyKind::deserialize(deserializer)? {
+        TopologyKind::Square => Arc::new(Square),
+        TopologyKind::Hex => Arc::new(Hex),
+        TopologyKind::Graph(graph) => Arc::new(graph),
+    })
+}